@@ -0,0 +1,384 @@
+//! Streaming writes of successive slices along one axis of a dataset.
+//!
+//! An `AppendWriter` grows a dataset along one axis as data arrives, such as
+//! a microscope acquiring one plane at a time along z. Slices are buffered
+//! until they fill a layer of blocks along that axis, which is written as
+//! complete blocks before the dataset's dimensions are grown to include it.
+
+use std::cmp;
+
+use ndarray::{
+    Array,
+    AsArray,
+    Axis,
+    IxDyn,
+    ShapeBuilder,
+    Slice,
+    SliceInfo,
+};
+use num_traits::Zero;
+
+use crate::ndarray::{
+    BoundingBox,
+    N5NdarrayReader,
+};
+use crate::{
+    CoordVec,
+    DataBlock,
+    DatasetAttributes,
+    GridCoord,
+    N5Error,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    WriteableDataBlock,
+};
+
+
+pub mod prelude {
+    pub use super::AppendWriter;
+}
+
+
+/// Writer appending slices or hyperslabs along one axis of a dataset.
+///
+/// Appended data is buffered in memory until it fills a layer of blocks as
+/// deep as the dataset's block size along the axis. Call `close` to write a
+/// final partial layer; dropping the writer also writes it, but ignores any
+/// errors.
+///
+/// ```
+/// use n5::prelude::*;
+/// use n5::append::AppendWriter;
+/// use n5::ndarray::prelude::*;
+/// use n5::smallvec::smallvec;
+/// use ndarray::Array2;
+///
+/// let n5 = N5InMemory::new();
+/// let data_attrs = DatasetAttributes::new(
+///     smallvec![64, 64, 0],
+///     smallvec![32, 32, 8],
+///     DataType::UINT16,
+///     CompressionType::default(),
+/// );
+/// n5.create_dataset("stack", &data_attrs).unwrap();
+///
+/// let mut writer = AppendWriter::<_, u16>::new(&n5, "stack", 2).unwrap();
+/// for z in 0..10u16 {
+///     let plane = Array2::from_elem((64, 64), z).into_dyn();
+///     writer.append(&plane).unwrap();
+/// }
+/// let data_attrs = writer.close().unwrap();
+/// assert_eq!(data_attrs.get_dimensions(), &[64, 64, 10]);
+///
+/// let bbox = BoundingBox::new(smallvec![0, 0, 9], smallvec![1, 1, 1]);
+/// let last = n5.read_ndarray::<u16>("stack", &data_attrs, &bbox).unwrap();
+/// assert_eq!(last[[0, 0, 0]], 9);
+/// ```
+pub struct AppendWriter<'a, N, T>
+    where N: N5Writer,
+          VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+          T: ReflectedType + Zero {
+    n5: &'a N,
+    path_name: String,
+    /// Attributes of the dataset as written so far.
+    data_attrs: DatasetAttributes,
+    axis: usize,
+    /// Column-major buffer spanning the dataset along all other axes and one
+    /// block deep along `axis`.
+    layer: Array<T, IxDyn>,
+    /// Offset of the buffered layer along `axis`.
+    layer_offset: u64,
+    /// Number of slices buffered in the layer.
+    filled: u64,
+    closed: bool,
+}
+
+impl<'a, N, T> AppendWriter<'a, N, T>
+    where N: N5Writer,
+          VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+          T: ReflectedType + Zero {
+
+    /// Open an existing dataset for appending along an axis.
+    ///
+    /// If the dataset's extent along the axis is not a multiple of its block
+    /// size, the existing partial layer of blocks is read, so that appended
+    /// data completes it.
+    ///
+    /// Returns `N5Error::DimensionMismatch` if the dataset has fewer than
+    /// `axis + 1` dimensions.
+    pub fn new(n5: &'a N, path_name: &str, axis: usize) -> Result<Self, N5Error> {
+        let data_attrs = n5.get_dataset_attributes(path_name)?;
+        if axis >= data_attrs.get_ndim() {
+            return Err(N5Error::DimensionMismatch {
+                expected: axis + 1,
+                found: data_attrs.get_ndim(),
+            });
+        }
+
+        let depth = u64::from(data_attrs.get_block_size()[axis]);
+        let extent = data_attrs.get_dimensions()[axis];
+        let filled = extent % depth;
+        let layer_offset = extent - filled;
+
+        let mut shape: CoordVec<usize> = data_attrs.get_dimensions().iter()
+            .map(|&d| d as usize)
+            .collect();
+        shape[axis] = depth as usize;
+        let mut layer = Array::zeros(IxDyn(&shape).f());
+
+        if filled > 0 {
+            let mut offset = GridCoord::from_elem(0, data_attrs.get_ndim());
+            offset[axis] = layer_offset;
+            let mut size = GridCoord::from_slice(data_attrs.get_dimensions());
+            size[axis] = filled;
+            let existing = n5.read_ndarray::<T>(path_name, &data_attrs, &BoundingBox::new(offset, size))?;
+            layer.slice_axis_mut(Axis(axis), Slice::from(..filled as usize))
+                .assign(&existing);
+        }
+
+        Ok(AppendWriter {
+            n5,
+            path_name: path_name.to_owned(),
+            data_attrs,
+            axis,
+            layer,
+            layer_offset,
+            filled,
+            closed: false,
+        })
+    }
+
+    /// Append a slice, with one dimension fewer than the dataset, or a
+    /// hyperslab of any depth along the axis. Its shape along all other axes
+    /// must match the dataset's dimensions, or `N5Error::ShapeMismatch` is
+    /// returned.
+    pub fn append<'b, A>(&mut self, data: A) -> Result<(), N5Error>
+        where A: AsArray<'b, T, IxDyn>,
+              T: 'b {
+
+        let mut data = data.into();
+        let ndim = self.data_attrs.get_ndim();
+        if data.ndim() + 1 == ndim {
+            data = data.insert_axis(Axis(self.axis));
+        }
+        if data.ndim() != ndim {
+            return Err(N5Error::DimensionMismatch {
+                expected: ndim,
+                found: data.ndim(),
+            });
+        }
+        let axis = self.axis;
+        if data.shape().iter().zip(self.layer.shape()).enumerate().any(|(d, (s, l))| d != axis && s != l) {
+            let found: Vec<u64> = data.shape().iter().map(|&s| s as u64).collect();
+            let mut expected = self.data_attrs.get_dimensions().to_vec();
+            expected[axis] = found[axis];
+            return Err(N5Error::ShapeMismatch { expected, found });
+        }
+
+        let depth = self.layer.len_of(Axis(axis));
+        let total = data.len_of(Axis(axis));
+        let mut start = 0;
+        while start < total {
+            let filled = self.filled as usize;
+            let n = cmp::min(depth - filled, total - start);
+            self.layer.slice_axis_mut(Axis(axis), Slice::from(filled..filled + n))
+                .assign(&data.slice_axis(Axis(axis), Slice::from(start..start + n)));
+            self.filled += n as u64;
+            start += n;
+
+            if filled + n == depth {
+                self.flush_layer()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write any partial final layer, returning the attributes of the grown
+    /// dataset.
+    pub fn close(mut self) -> Result<DatasetAttributes, N5Error> {
+        self.closed = true;
+        self.flush_layer()?;
+        Ok(self.data_attrs.clone())
+    }
+
+    /// Write the buffered layer as blocks, then grow the dataset to include
+    /// it. Once a layer is full, buffering moves on to the next layer.
+    fn flush_layer(&mut self) -> Result<(), N5Error> {
+        if self.filled == 0 {
+            return Ok(());
+        }
+
+        let mut dimensions = GridCoord::from_slice(self.data_attrs.get_dimensions());
+        dimensions[self.axis] = self.layer_offset + self.filled;
+        let grown_attrs = DatasetAttributes {
+            dimensions: dimensions.clone(),
+            ..self.data_attrs.clone()
+        };
+
+        let mut offset = GridCoord::from_elem(0, dimensions.len());
+        offset[self.axis] = self.layer_offset;
+        let mut size = dimensions.clone();
+        size[self.axis] = self.filled;
+        let layer_bbox = BoundingBox::new(offset.clone(), size);
+
+        for coord in grown_attrs.bounded_coord_iter(&layer_bbox) {
+            let grid_coord = GridCoord::from(&coord[..]);
+            let block_bbox = grown_attrs.get_block_bounds(&grid_coord);
+            let layer_slice = (block_bbox.clone() - &offset).to_ndarray_slice();
+            let block_view = self.layer.slice(SliceInfo::<_, IxDyn>::new(layer_slice).unwrap().as_ref());
+            let block = VecDataBlock::new(
+                block_bbox.size_block(),
+                grid_coord,
+                block_view.t().iter().cloned().collect());
+
+            self.n5.write_block(&self.path_name, &grown_attrs, &block)?;
+        }
+
+        if dimensions[self.axis] > self.data_attrs.get_dimensions()[self.axis] {
+            self.n5.resize_dataset(&self.path_name, dimensions)?;
+        }
+        self.data_attrs = grown_attrs;
+
+        if self.filled == self.layer.len_of(Axis(self.axis)) as u64 {
+            self.layer_offset += self.filled;
+            self.filled = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, N, T> Drop for AppendWriter<'a, N, T>
+    where N: N5Writer,
+          VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+          T: ReflectedType + Zero {
+
+    fn drop(&mut self) {
+        if !self.closed && !std::thread::panicking() {
+            let _ = self.flush_layer();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+    use crate::in_memory::N5InMemory;
+    use crate::{
+        DataType,
+        N5Reader,
+    };
+
+    fn create_stack(n5: &N5InMemory) -> DatasetAttributes {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![5, 4, 0],
+            smallvec![2, 3, 3],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        n5.create_dataset("stack", &data_attrs).unwrap();
+        data_attrs
+    }
+
+    fn plane(z: usize) -> Array<i32, IxDyn> {
+        Array::from_shape_fn(IxDyn(&[5, 4]), |idx| (idx[0] + 10 * idx[1] + 100 * z) as i32)
+    }
+
+    fn expected(depth: usize) -> Array<i32, IxDyn> {
+        Array::from_shape_fn(IxDyn(&[5, 4, depth]), |idx| (idx[0] + 10 * idx[1] + 100 * idx[2]) as i32)
+    }
+
+    fn read_all(n5: &N5InMemory) -> Array<i32, IxDyn> {
+        let data_attrs = n5.get_dataset_attributes("stack").unwrap();
+        n5.read_ndarray::<i32>("stack", &data_attrs, &data_attrs.get_bounds()).unwrap()
+    }
+
+    #[test]
+    fn test_append_slices() {
+        let n5 = N5InMemory::new();
+        create_stack(&n5);
+
+        let mut writer = AppendWriter::<_, i32>::new(&n5, "stack", 2).unwrap();
+        for z in 0..7 {
+            writer.append(&plane(z)).unwrap();
+            let written = n5.get_dataset_attributes("stack").unwrap().get_dimensions()[2];
+            assert_eq!(written, (z as u64 + 1) / 3 * 3);
+        }
+        assert_eq!(writer.close().unwrap().get_dimensions(), &[5, 4, 7]);
+        assert_eq!(read_all(&n5), expected(7));
+
+        let block = n5.read_block::<i32>("stack", &n5.get_dataset_attributes("stack").unwrap(), smallvec![2, 1, 2])
+            .unwrap()
+            .unwrap();
+        assert_eq!(block.get_size(), &[1, 1, 1]);
+    }
+
+    #[test]
+    fn test_append_hyperslabs_after_partial_layer() {
+        let n5 = N5InMemory::new();
+        create_stack(&n5);
+
+        let mut writer = AppendWriter::<_, i32>::new(&n5, "stack", 2).unwrap();
+        writer.append(&expected(4)).unwrap();
+        writer.close().unwrap();
+
+        let mut writer = AppendWriter::<_, i32>::new(&n5, "stack", 2).unwrap();
+        let slab = expected(11);
+        writer.append(slab.slice_axis(Axis(2), Slice::from(4..11))).unwrap();
+        drop(writer);
+
+        assert_eq!(n5.get_dataset_attributes("stack").unwrap().get_dimensions(), &[5, 4, 11]);
+        assert_eq!(read_all(&n5), expected(11));
+    }
+
+    #[test]
+    fn test_append_along_first_axis() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![0, 3],
+            smallvec![2, 2],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        n5.create_dataset("rows", &data_attrs).unwrap();
+
+        let mut writer = AppendWriter::<_, i32>::new(&n5, "rows", 0).unwrap();
+        for x in 0..3 {
+            writer.append(&Array::from_elem(IxDyn(&[3]), x)).unwrap();
+        }
+        let data_attrs = writer.close().unwrap();
+        assert_eq!(data_attrs.get_dimensions(), &[3, 3]);
+        let rows = n5.read_ndarray::<i32>("rows", &data_attrs, &data_attrs.get_bounds()).unwrap();
+        assert_eq!(rows, Array::from_shape_fn(IxDyn(&[3, 3]), |idx| idx[0] as i32));
+    }
+
+    #[test]
+    fn test_append_shape_mismatch() {
+        let n5 = N5InMemory::new();
+        create_stack(&n5);
+
+        let mut writer = AppendWriter::<_, i32>::new(&n5, "stack", 2).unwrap();
+        assert!(matches!(
+            writer.append(&Array::zeros(IxDyn(&[5]))),
+            Err(N5Error::DimensionMismatch { expected: 3, found: 1 })));
+        match writer.append(&Array::zeros(IxDyn(&[5, 3]))) {
+            Err(N5Error::ShapeMismatch { expected, found }) => {
+                assert_eq!(expected, vec![5, 4, 1]);
+                assert_eq!(found, vec![5, 3, 1]);
+            },
+            other => panic!("Expected ShapeMismatch, got {:?}", other),
+        }
+        writer.close().unwrap();
+        assert_eq!(n5.get_dataset_attributes("stack").unwrap().get_dimensions(), &[5, 4, 0]);
+
+        assert!(matches!(
+            AppendWriter::<_, i32>::new(&n5, "stack", 3),
+            Err(N5Error::DimensionMismatch { expected: 4, found: 3 })));
+    }
+}
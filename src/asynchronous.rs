@@ -0,0 +1,544 @@
+//! Asynchronous counterparts of the N5 container traits.
+//!
+//! The futures returned by these traits are `Send`, so they may be spawned on
+//! a multi-threaded tokio runtime. `N5Filesystem` and `N5Http` (with the
+//! `http-async` feature) implement them natively. Any other synchronous
+//! container can be used through `SyncAdapter`, which runs each call on
+//! tokio's blocking thread pool.
+
+use std::future::Future;
+use std::io::Error;
+use std::sync::Arc;
+
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::{
+    DataBlock,
+    DataBlockMetadata,
+    DatasetAttributes,
+    GridCoord,
+    N5Lister,
+    N5Reader,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    SliceDataBlock,
+    VecDataBlock,
+    Version,
+    WriteableDataBlock,
+};
+
+
+/// Non-mutating asynchronous operations on N5 containers.
+pub trait N5AsyncReader: Send + Sync {
+    /// Get the N5 specification version of the container.
+    fn get_version(&self) -> impl Future<Output = Result<Version>> + Send;
+
+    /// Get attributes for a dataset.
+    fn get_dataset_attributes(&self, path_name: &str) -> impl Future<Output = Result<DatasetAttributes>> + Send;
+
+    /// Test whether a group or dataset exists.
+    fn exists(&self, path_name: &str) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Test whether a dataset exists.
+    fn dataset_exists(&self, path_name: &str) -> impl Future<Output = Result<bool>> + Send {
+        async move {
+            Ok(self.exists(path_name).await? && self.get_dataset_attributes(path_name).await.is_ok())
+        }
+    }
+
+    /// Get a URI string for a data block. See `N5Reader::get_block_uri`.
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String>;
+
+    /// Read a single dataset block into a linear vec.
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> impl Future<Output = Result<Option<VecDataBlock<T>>>> + Send
+        where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+              T: ReflectedType;
+
+    /// Read metadata about a block.
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> impl Future<Output = Result<Option<DataBlockMetadata>>> + Send;
+
+    /// List all attributes of a group.
+    fn list_attributes(&self, path_name: &str) -> impl Future<Output = Result<Value>> + Send;
+
+    /// Get a single attribute of a group, deserialized as `T`. See
+    /// `N5Reader::get_attribute`.
+    fn get_attribute<T: DeserializeOwned>(
+        &self,
+        path_name: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<T>> + Send {
+        async move {
+            crate::deserialize_attribute(path_name, key, self.list_attributes(path_name).await?)
+        }
+    }
+
+    /// Get a nested attribute of a group by a JSON-pointer-style path,
+    /// deserialized as `T`. See `N5Reader::get_nested_attribute`.
+    fn get_nested_attribute<T: DeserializeOwned>(
+        &self,
+        path_name: &str,
+        attribute_path: &str,
+    ) -> impl Future<Output = Result<T>> + Send {
+        async move {
+            crate::deserialize_nested_attribute(path_name, attribute_path, self.list_attributes(path_name).await?)
+        }
+    }
+
+    /// Get all attributes of a group, deserialized as `T`.
+    fn get_attributes_as<T: DeserializeOwned>(&self, path_name: &str) -> impl Future<Output = Result<T>> + Send {
+        async move {
+            crate::deserialize_attributes(path_name, self.list_attributes(path_name).await?)
+        }
+    }
+}
+
+pub trait N5AsyncLister: N5AsyncReader {
+    /// List all groups (including datasets) in a group.
+    fn list(&self, path_name: &str) -> impl Future<Output = Result<Vec<String>>> + Send;
+}
+
+/// Mutating asynchronous operations on N5 containers.
+pub trait N5AsyncWriter: N5AsyncReader {
+    /// Set a single attribute.
+    fn set_attribute<T: Serialize>(
+        &self,
+        path_name: &str,
+        key: String,
+        attribute: T,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let attributes = serde_json::to_value(attribute)
+            .map(|value| vec![(key, value)].into_iter().collect());
+        async move {
+            self.set_attributes(path_name, attributes?).await
+        }
+    }
+
+    /// Set a map of attributes.
+    fn set_attributes(
+        &self,
+        path_name: &str,
+        attributes: serde_json::Map<String, Value>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Set mandatory dataset attributes.
+    fn set_dataset_attributes(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let attributes = serde_json::to_value(data_attrs);
+        async move {
+            if let Value::Object(map) = attributes? {
+                self.set_attributes(path_name, map).await
+            } else {
+                panic!("Impossible: DatasetAttributes serializes to object")
+            }
+        }
+    }
+
+    /// Create a group (directory).
+    fn create_group(&self, path_name: &str) -> impl Future<Output = Result<()>> + Send;
+
+    /// Create a dataset. This will create the dataset group and attributes,
+    /// but not populate any block data.
+    fn create_dataset(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.create_group(path_name).await?;
+            self.set_dataset_attributes(path_name, data_attrs).await
+        }
+    }
+
+    /// Remove the N5 container.
+    fn remove_all(&self) -> impl Future<Output = Result<()>> + Send {
+        self.remove("")
+    }
+
+    /// Remove a group or dataset (directory and all contained files).
+    fn remove(&self, path_name: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn write_block<T, B>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        block: &B,
+    ) -> impl Future<Output = Result<()>> + Send
+        where VecDataBlock<T>: DataBlock<T> + WriteableDataBlock,
+              B: DataBlock<T> + WriteableDataBlock + Sync,
+              T: ReflectedType;
+
+    /// Delete a block from a dataset.
+    ///
+    /// Returns `true` if the block does not exist on the backend at the
+    /// completion of the call.
+    fn delete_block(
+        &self,
+        path_name: &str,
+        grid_position: &[u64],
+    ) -> impl Future<Output = Result<bool>> + Send;
+}
+
+/// Run a synchronous N5 call on tokio's blocking thread pool.
+pub(crate) async fn spawn_blocking<R, F>(f: F) -> Result<R>
+        where F: FnOnce() -> Result<R> + Send + 'static,
+              R: Send + 'static {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(Error::other(e).into()),
+    }
+}
+
+/// Copy a block so that it can be written on another thread.
+pub(crate) fn to_owned_block<T, B>(block: &B) -> VecDataBlock<T>
+        where B: DataBlock<T>,
+              T: ReflectedType {
+    SliceDataBlock::new(
+        block.get_size().into(),
+        block.get_grid_position().into(),
+        block.get_data().to_vec())
+}
+
+/// Adapts a synchronous N5 container to the asynchronous traits by running
+/// each call on tokio's blocking thread pool.
+///
+/// ```
+/// use n5::prelude::*;
+/// use n5::asynchronous::{
+///     N5AsyncReader,
+///     SyncAdapter,
+/// };
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let n5 = SyncAdapter::new(N5InMemory::new());
+/// runtime.block_on(async {
+///     assert!(n5.exists("").await.unwrap());
+/// });
+/// ```
+pub struct SyncAdapter<N> {
+    inner: Arc<N>,
+}
+
+impl<N> SyncAdapter<N> {
+    pub fn new(inner: N) -> SyncAdapter<N> {
+        SyncAdapter {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Get the underlying synchronous container.
+    pub fn get_ref(&self) -> &N {
+        &self.inner
+    }
+}
+
+impl<N> Clone for SyncAdapter<N> {
+    fn clone(&self) -> Self {
+        SyncAdapter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<N> From<Arc<N>> for SyncAdapter<N> {
+    fn from(inner: Arc<N>) -> Self {
+        SyncAdapter {inner}
+    }
+}
+
+impl<N: N5Reader + Send + Sync + 'static> N5AsyncReader for SyncAdapter<N> {
+    fn get_version(&self) -> impl Future<Output = Result<Version>> + Send {
+        let n5 = self.inner.clone();
+        spawn_blocking(move || n5.get_version())
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> impl Future<Output = Result<DatasetAttributes>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.get_dataset_attributes(&path_name))
+    }
+
+    fn exists(&self, path_name: &str) -> impl Future<Output = Result<bool>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.exists(&path_name))
+    }
+
+    fn dataset_exists(&self, path_name: &str) -> impl Future<Output = Result<bool>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.dataset_exists(&path_name))
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        self.inner.get_block_uri(path_name, grid_position)
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> impl Future<Output = Result<Option<VecDataBlock<T>>>> + Send
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        let data_attrs = data_attrs.clone();
+        spawn_blocking(move || n5.read_block(&path_name, &data_attrs, grid_position))
+    }
+
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> impl Future<Output = Result<Option<DataBlockMetadata>>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        let data_attrs = data_attrs.clone();
+        let grid_position = grid_position.to_vec();
+        spawn_blocking(move || n5.block_metadata(&path_name, &data_attrs, &grid_position))
+    }
+
+    fn list_attributes(&self, path_name: &str) -> impl Future<Output = Result<Value>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.list_attributes(&path_name))
+    }
+}
+
+impl<N: N5Lister + Send + Sync + 'static> N5AsyncLister for SyncAdapter<N> {
+    fn list(&self, path_name: &str) -> impl Future<Output = Result<Vec<String>>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.list(&path_name))
+    }
+}
+
+impl<N: N5Writer + Send + Sync + 'static> N5AsyncWriter for SyncAdapter<N> {
+    fn set_attributes(
+        &self,
+        path_name: &str,
+        attributes: serde_json::Map<String, Value>,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.set_attributes(&path_name, attributes))
+    }
+
+    fn create_group(&self, path_name: &str) -> impl Future<Output = Result<()>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.create_group(&path_name))
+    }
+
+    fn create_dataset(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        let data_attrs = data_attrs.clone();
+        spawn_blocking(move || n5.create_dataset(&path_name, &data_attrs))
+    }
+
+    fn remove(&self, path_name: &str) -> impl Future<Output = Result<()>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        spawn_blocking(move || n5.remove(&path_name))
+    }
+
+    fn write_block<T, B>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        block: &B,
+    ) -> impl Future<Output = Result<()>> + Send
+            where VecDataBlock<T>: DataBlock<T> + WriteableDataBlock,
+                  B: DataBlock<T> + WriteableDataBlock + Sync,
+                  T: ReflectedType {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        let data_attrs = data_attrs.clone();
+        let block = to_owned_block(block);
+        spawn_blocking(move || n5.write_block(&path_name, &data_attrs, &block))
+    }
+
+    fn delete_block(
+        &self,
+        path_name: &str,
+        grid_position: &[u64],
+    ) -> impl Future<Output = Result<bool>> + Send {
+        let n5 = self.inner.clone();
+        let path_name = path_name.to_owned();
+        let grid_position = grid_position.to_vec();
+        spawn_blocking(move || n5.delete_block(&path_name, &grid_position))
+    }
+}
+
+#[cfg(feature = "use_ndarray")]
+pub use self::ndarray::N5AsyncNdarrayReader;
+
+#[cfg(feature = "use_ndarray")]
+mod ndarray {
+    use super::*;
+    use crate::ndarray::{
+        assign_block_to_ndarray,
+        BoundingBox,
+    };
+    use crate::N5Error;
+    use ::ndarray::{
+        Array,
+        Dim,
+        IxDynImpl,
+        ShapeBuilder,
+    };
+
+    pub trait N5AsyncNdarrayReader: N5AsyncReader {
+        /// Read an abitrary bounding box from an N5 volume in an ndarray,
+        /// reading blocks in serial as necessary.
+        ///
+        /// Assumes blocks are column-major and returns a column-major ndarray.
+        fn read_ndarray<T>(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            bbox: &BoundingBox,
+        ) -> impl Future<Output = Result<Array<T, Dim<IxDynImpl>>>> + Send
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType + num_traits::identities::Zero {
+            async move {
+                if bbox.get_offset().len() != data_attrs.get_ndim() {
+                    return Err(N5Error::DimensionMismatch {
+                        expected: data_attrs.get_ndim(),
+                        found: bbox.get_offset().len(),
+                    });
+                }
+
+                let mut arr = Array::zeros(bbox.size_ndarray_shape().f());
+                let coords: Vec<Vec<u64>> = data_attrs.bounded_coord_iter(bbox).collect();
+                for coord in coords {
+                    let grid_pos = GridCoord::from(&coord[..]);
+                    if let Some(block) = self.read_block(path_name, data_attrs, grid_pos).await? {
+                        assign_block_to_ndarray(&mut arr, bbox, data_attrs, &block);
+                    }
+                }
+
+                Ok(arr)
+            }
+        }
+    }
+
+    impl<N: N5AsyncReader> N5AsyncNdarrayReader for N {}
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+    use crate::in_memory::N5InMemory;
+    use crate::DataType;
+
+    pub(crate) fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    /// Write and read back a dataset through the asynchronous traits.
+    pub(crate) async fn dataset_rw<N: N5AsyncWriter + N5AsyncLister>(n5: &N) {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![5, 5],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        n5.create_dataset("foo/bar", &data_attrs).await.unwrap();
+        n5.set_attribute("foo", "baz".to_owned(), 3).await.unwrap();
+        assert!(n5.dataset_exists("foo/bar").await.unwrap());
+        assert!(!n5.dataset_exists("foo").await.unwrap());
+        assert_eq!(n5.get_dataset_attributes("foo/bar").await.unwrap(), data_attrs);
+        assert_eq!(n5.list_attributes("foo").await.unwrap()["baz"], 3);
+        assert_eq!(n5.get_attribute::<u8>("foo", "baz").await.unwrap(), 3);
+        assert!(matches!(
+            n5.get_attribute::<u8>("foo", "qux").await,
+            Err(crate::N5Error::AttributeNotFound { .. })));
+        assert_eq!(n5.get_nested_attribute::<u64>("foo/bar", "dimensions/1").await.unwrap(), 10);
+        assert_eq!(n5.list("foo").await.unwrap(), vec!["bar".to_owned()]);
+
+        let block_data: Vec<i32> = (0..25).collect();
+        let block_in = SliceDataBlock::new(smallvec![5, 5], smallvec![1, 0], &block_data[..]);
+        n5.write_block("foo/bar", &data_attrs, &block_in).await.unwrap();
+        let block_out = n5.read_block::<i32>("foo/bar", &data_attrs, smallvec![1, 0]).await
+            .unwrap()
+            .expect("Block is empty");
+        assert_eq!(block_out.get_data(), &block_data[..]);
+        assert!(n5.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 0]).await.unwrap().is_none());
+        assert!(n5.block_metadata("foo/bar", &data_attrs, &[1, 0]).await.unwrap().is_some());
+
+        assert!(n5.delete_block("foo/bar", &[1, 0]).await.unwrap());
+        assert!(n5.read_block::<i32>("foo/bar", &data_attrs, smallvec![1, 0]).await.unwrap().is_none());
+
+        n5.remove("foo").await.unwrap();
+        assert!(!n5.exists("foo").await.unwrap());
+    }
+
+    #[test]
+    fn sync_adapter_rw() {
+        let n5 = SyncAdapter::new(N5InMemory::new());
+        runtime().block_on(dataset_rw(&n5));
+        assert!(!n5.get_ref().exists("foo").unwrap());
+    }
+
+    #[test]
+    fn futures_are_send() {
+        fn assert_send<F: Future + Send>(_: F) {}
+        let n5 = SyncAdapter::new(N5InMemory::new());
+        assert_send(dataset_rw(&n5));
+    }
+
+    #[cfg(feature = "use_ndarray")]
+    #[test]
+    fn read_ndarray() {
+        use crate::ndarray::N5NdarrayWriter;
+        use crate::ndarray::N5NdarrayReader;
+
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![7, 9],
+            smallvec![2, 4],
+            DataType::UINT16,
+            CompressionType::default(),
+        );
+        n5.create_dataset("foo", &data_attrs).unwrap();
+        let arr = ::ndarray::Array::from_shape_fn((7, 9), |(i, j)| (i * 9 + j) as u16).into_dyn();
+        n5.write_ndarray("foo", &data_attrs, smallvec![0, 0], &arr, 0).unwrap();
+        let bbox = crate::ndarray::BoundingBox::new(smallvec![1, 2], smallvec![5, 6]);
+        let expected = n5.read_ndarray::<u16>("foo", &data_attrs, &bbox).unwrap();
+
+        let n5 = SyncAdapter::new(n5);
+        let read = runtime().block_on(N5AsyncNdarrayReader::read_ndarray::<u16>(&n5, "foo", &data_attrs, &bbox))
+            .unwrap();
+        assert_eq!(read, expected);
+    }
+}
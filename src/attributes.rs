@@ -0,0 +1,204 @@
+//! Nested attribute access by JSON-pointer-style paths.
+//!
+//! Paths are `/`-separated keys, such as `multiscales/0/datasets`, with an
+//! optional leading `/`. As in JSON pointers, `~1` and `~0` escape `/` and
+//! `~` within keys, and numeric keys without leading zeros index into arrays.
+//! An empty path refers to all attributes.
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use crate::N5Error;
+
+
+/// Split a path into unescaped keys.
+fn keys(path: &str) -> Vec<String> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    if path.is_empty() {
+        return Vec::new();
+    }
+    path.split('/')
+        .map(|key| key.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+/// Parse an array index, which as in JSON pointers has no sign or leading
+/// zeros.
+fn array_index(key: &str) -> Option<usize> {
+    if key.starts_with('+') || (key.starts_with('0') && key.len() > 1) {
+        return None;
+    }
+    key.parse().ok()
+}
+
+fn child<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(array) => array_index(key).and_then(|i| array.get(i)),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(value: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    match value {
+        Value::Object(map) => map.get_mut(key),
+        Value::Array(array) => array_index(key).and_then(move |i| array.get_mut(i)),
+        _ => None,
+    }
+}
+
+/// Get the child of a value at a key, creating it if necessary, or `None` if
+/// it cannot be created. `null` is replaced with an object. As in JSON Patch,
+/// arrays are only extended by an index of their length or `-`, which append
+/// an element.
+fn child_or_insert<'a>(value: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    if value.is_null() {
+        *value = Value::Object(Map::new());
+    }
+
+    match value {
+        Value::Object(map) => Some(map.entry(key).or_insert(Value::Null)),
+        Value::Array(array) => {
+            let index = if key == "-" {array.len()} else {array_index(key)?};
+            if index == array.len() {
+                array.push(Value::Null);
+            }
+            array.get_mut(index)
+        },
+        _ => None,
+    }
+}
+
+/// Get the attribute at a path.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let attrs = json!({"multiscales": [{"datasets": ["s0", "s1"]}]});
+/// assert_eq!(
+///     n5::attributes::get_nested(&attrs, "multiscales/0/datasets/1"),
+///     Some(&json!("s1")));
+/// assert_eq!(n5::attributes::get_nested(&attrs, "multiscales/1"), None);
+/// ```
+pub fn get_nested<'a>(attributes: &'a Value, path: &str) -> Option<&'a Value> {
+    keys(path).iter().try_fold(attributes, |value, key| child(value, key))
+}
+
+/// Set the attribute at a path, creating intermediate objects as needed.
+///
+/// Returns `N5Error::InvalidAttributePath`, leaving the attributes unchanged,
+/// if the path indexes past the end of an array or into a value other than
+/// an object, array or `null`.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut attrs = json!({"multiscales": [{"name": "raw"}]});
+/// n5::attributes::set_nested(&mut attrs, "multiscales/0/axes/2", json!("z")).unwrap();
+/// n5::attributes::set_nested(&mut attrs, "multiscales/-", json!({})).unwrap();
+/// n5::attributes::set_nested(&mut attrs, "a/b", json!(1)).unwrap();
+/// assert_eq!(attrs, json!({
+///     "multiscales": [{"name": "raw", "axes": {"2": "z"}}, {}],
+///     "a": {"b": 1},
+/// }));
+/// assert!(n5::attributes::set_nested(&mut attrs, "multiscales/3", json!({})).is_err());
+/// assert!(n5::attributes::set_nested(&mut attrs, "a/b/c", json!(2)).is_err());
+/// ```
+pub fn set_nested(attributes: &mut Value, path: &str, attribute: Value) -> Result<(), N5Error> {
+    // Values are only created once the path leaves existing values, after
+    // which it cannot fail, so attributes are unchanged on error.
+    let target = keys(path).iter().try_fold(attributes, |value, key| {
+        child_or_insert(value, key).ok_or_else(|| N5Error::InvalidAttributePath {
+            attribute_path: path.to_owned(),
+            key: key.to_owned(),
+        })
+    })?;
+    *target = attribute;
+    Ok(())
+}
+
+/// Remove the attribute at a path, returning it if it existed. Removing an
+/// element of an array shifts the following elements.
+pub fn remove_nested(attributes: &mut Value, path: &str) -> Option<Value> {
+    let mut keys = keys(path);
+    let last = keys.pop()?;
+    let parent = keys.iter().try_fold(attributes, |value, key| child_mut(value, key))?;
+    match parent {
+        Value::Object(map) => map.remove(&last),
+        Value::Array(array) => match array_index(&last) {
+            Some(index) if index < array.len() => Some(array.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(keys(""), Vec::<String>::new());
+        assert_eq!(keys("/"), Vec::<String>::new());
+        assert_eq!(keys("a/b"), vec!["a", "b"]);
+        assert_eq!(keys("/a/b"), vec!["a", "b"]);
+        assert_eq!(keys("a~1b/~0c/"), vec!["a/b", "~c", ""]);
+    }
+
+    #[test]
+    fn nested_get_set_remove() {
+        let mut attrs = json!({
+            "a": {"b": [1, 2, {"c": true}]},
+            "d/e": "slash",
+        });
+        assert_eq!(get_nested(&attrs, "a/b/2/c"), Some(&json!(true)));
+        assert_eq!(get_nested(&attrs, "/a/b/0"), Some(&json!(1)));
+        assert_eq!(get_nested(&attrs, "d~1e"), Some(&json!("slash")));
+        assert_eq!(get_nested(&attrs, ""), Some(&attrs));
+        assert_eq!(get_nested(&attrs, "a/b/x"), None);
+        assert_eq!(get_nested(&attrs, "a/b/0/c"), None);
+
+        assert_eq!(get_nested(&attrs, "a/b/01"), None);
+
+        set_nested(&mut attrs, "a/b/3", json!(5)).unwrap();
+        set_nested(&mut attrs, "a/b/-", json!(6)).unwrap();
+        set_nested(&mut attrs, "a/b/0", json!(null)).unwrap();
+        set_nested(&mut attrs, "a/b/0/f", json!("object")).unwrap();
+        set_nested(&mut attrs, "g/h/0", json!([])).unwrap();
+        set_nested(&mut attrs, "g/h/0/-", json!(null)).unwrap();
+        assert_eq!(attrs, json!({
+            "a": {"b": [{"f": "object"}, 2, {"c": true}, 5, 6]},
+            "d/e": "slash",
+            "g": {"h": {"0": [null]}},
+        }));
+
+        let unchanged = attrs.clone();
+        for path in &["a/b/6", "a/b/x", "a/b/01", "a/b/1/f", "d~1e/f", "a/b/usize/-"] {
+            match set_nested(&mut attrs, path, json!(0)) {
+                Err(N5Error::InvalidAttributePath { attribute_path, .. }) => assert_eq!(&attribute_path, path),
+                other => panic!("Expected InvalidAttributePath for {}, got {:?}", path, other),
+            }
+        }
+        assert!(set_nested(&mut attrs, "a/b/99999999999999999999/x", json!(0)).is_err());
+        assert_eq!(attrs, unchanged);
+
+        assert_eq!(remove_nested(&mut attrs, "a/b/1"), Some(json!(2)));
+        assert_eq!(remove_nested(&mut attrs, "a/b/9"), None);
+        assert_eq!(remove_nested(&mut attrs, "g/h"), Some(json!({"0": [null]})));
+        assert_eq!(remove_nested(&mut attrs, "d~1e"), Some(json!("slash")));
+        assert_eq!(remove_nested(&mut attrs, "x/y"), None);
+        assert_eq!(remove_nested(&mut attrs, ""), None);
+        assert_eq!(attrs, json!({
+            "a": {"b": [{"f": "object"}, {"c": true}, 5, 6]},
+            "g": {},
+        }));
+
+        set_nested(&mut attrs, "", json!({"replaced": 1})).unwrap();
+        assert_eq!(attrs, json!({"replaced": 1}));
+    }
+}
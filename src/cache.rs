@@ -0,0 +1,504 @@
+//! A caching layer for any N5 reader.
+//!
+//! `CachedReader` keeps decoded blocks and parsed dataset attributes in a
+//! least-recently-used cache bounded by an approximate size in bytes, so that
+//! repeatedly reading overlapping regions does not re-read and re-decode the
+//! same blocks. Writes made to the underlying container after blocks are
+//! cached are not seen until the cache is cleared.
+
+use std::any::{
+    Any,
+    TypeId,
+};
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::mem::{
+    size_of,
+    size_of_val,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    PoisonError,
+};
+
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::{
+    DataBlock,
+    DataBlockMetadata,
+    DatasetAttributes,
+    GridCoord,
+    N5BlockLister,
+    N5Lister,
+    N5Reader,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    SliceDataBlock,
+    VecDataBlock,
+    Version,
+};
+
+
+/// Statistics of a `CachedReader`'s cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of reads answered from the cache.
+    pub hits: u64,
+    /// Number of reads passed to the underlying reader.
+    pub misses: u64,
+    /// Number of entries evicted to stay within the capacity.
+    pub evictions: u64,
+    /// Number of entries currently cached.
+    pub entries: usize,
+    /// Approximate size of the cached entries, in bytes.
+    pub size: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Attributes(String),
+    Block {
+        path_name: String,
+        grid_position: GridCoord,
+        /// Element type the block was read as.
+        type_id: TypeId,
+    },
+}
+
+#[derive(Clone)]
+enum CacheValue {
+    Attributes(DatasetAttributes),
+    /// A decoded `VecDataBlock`, or `None` if the block does not exist.
+    Block(Option<Arc<dyn Any + Send + Sync>>),
+}
+
+struct CacheEntry {
+    value: CacheValue,
+    size: usize,
+    /// Time of the most recent use, as an index into `Cache::order`.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Cache keys ordered from least to most recently used.
+    order: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn get(&mut self, key: &CacheKey) -> Option<CacheValue> {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.order.remove(&entry.last_used);
+                self.clock += 1;
+                entry.last_used = self.clock;
+                self.order.insert(self.clock, key.clone());
+                self.stats.hits += 1;
+                Some(entry.value.clone())
+            },
+            None => {
+                self.stats.misses += 1;
+                None
+            },
+        }
+    }
+
+    fn insert(&mut self, key: CacheKey, value: CacheValue, size: usize, capacity: usize) {
+        self.remove(&key);
+        if size > capacity {
+            return;
+        }
+
+        while self.stats.size + size > capacity {
+            let oldest = match self.order.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            if let Some(oldest_key) = self.order.remove(&oldest) {
+                self.remove(&oldest_key);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(key, CacheEntry {
+            value,
+            size,
+            last_used: self.clock,
+        });
+        self.stats.entries += 1;
+        self.stats.size += size;
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.stats.entries -= 1;
+            self.stats.size -= entry.size;
+        }
+    }
+}
+
+/// Approximate size of a cache entry, in bytes.
+fn entry_size(key: &CacheKey, value: &CacheValue, data_size: usize) -> usize {
+    let key_size = match key {
+        CacheKey::Attributes(path_name) => path_name.len(),
+        CacheKey::Block {path_name, grid_position, ..} =>
+            path_name.len() + grid_position.len() * size_of::<u64>(),
+    };
+    let value_size = match value {
+        CacheValue::Attributes(data_attrs) =>
+            size_of::<DatasetAttributes>() + data_attrs.get_ndim() * (size_of::<u64>() + size_of::<u32>()),
+        CacheValue::Block(_) => data_size,
+    };
+    size_of::<CacheKey>() + size_of::<CacheEntry>() + key_size + value_size
+}
+
+/// Approximate size of a decoded block, in bytes.
+fn block_size<T>(block: &VecDataBlock<T>) -> usize
+        where VecDataBlock<T>: DataBlock<T>,
+              T: ReflectedType {
+    size_of::<VecDataBlock<T>>()
+        + size_of_val(block.get_size())
+        + size_of_val(block.get_grid_position())
+        + size_of_val(block.get_data())
+}
+
+/// An N5 reader that caches decoded blocks and dataset attributes read
+/// through another reader.
+///
+/// Blocks are cached by dataset path, grid position and element type, so the
+/// dataset attributes given when reading a block are assumed to be those of
+/// the dataset.
+///
+/// ```
+/// use n5::prelude::*;
+/// use n5::cache::CachedReader;
+/// use n5::smallvec::smallvec;
+///
+/// let n5 = N5InMemory::new();
+/// let data_attrs = DatasetAttributes::new(
+///     smallvec![4, 4],
+///     smallvec![2, 2],
+///     DataType::UINT8,
+///     CompressionType::default(),
+/// );
+/// n5.create_dataset("foo", &data_attrs).unwrap();
+/// n5.write_block("foo", &data_attrs, &SliceDataBlock::new(
+///     smallvec![2, 2], smallvec![0, 0], vec![1u8, 2, 3, 4])).unwrap();
+///
+/// let cached = CachedReader::new(n5, 1 << 20);
+/// for _ in 0..3 {
+///     let block = cached.read_block::<u8>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+///     assert_eq!(block.unwrap().get_data(), &[1, 2, 3, 4]);
+/// }
+/// assert_eq!(cached.stats().misses, 1);
+/// assert_eq!(cached.stats().hits, 2);
+/// ```
+pub struct CachedReader<N: N5Reader> {
+    inner: N,
+    /// Maximum approximate size of cached entries, in bytes.
+    capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+impl<N: N5Reader> CachedReader<N> {
+    /// Cache reads from `inner`, holding at most approximately `capacity`
+    /// bytes of blocks and attributes.
+    pub fn new(inner: N, capacity: usize) -> CachedReader<N> {
+        CachedReader {
+            inner,
+            capacity,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Get the underlying reader.
+    pub fn get_ref(&self) -> &N {
+        &self.inner
+    }
+
+    /// Unwrap the underlying reader, discarding the cache.
+    pub fn into_inner(self) -> N {
+        self.inner
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the cache's hit, miss, and eviction counts and current size.
+    pub fn stats(&self) -> CacheStats {
+        self.cache().stats
+    }
+
+    /// Discard all cached entries. Statistics other than the current size are
+    /// kept.
+    pub fn clear(&self) {
+        let mut cache = self.cache();
+        cache.entries.clear();
+        cache.order.clear();
+        cache.stats.entries = 0;
+        cache.stats.size = 0;
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn block_key<T: ReflectedType>(path_name: &str, grid_position: &[u64]) -> CacheKey {
+        CacheKey::Block {
+            path_name: path_name.to_owned(),
+            grid_position: grid_position.into(),
+            type_id: TypeId::of::<T>(),
+        }
+    }
+
+    /// Get a cached block, which is `Some(None)` if the block is cached as
+    /// not existing.
+    fn get_block<T: ReflectedType>(&self, key: &CacheKey) -> Option<Option<Arc<VecDataBlock<T>>>> {
+        match self.cache().get(key) {
+            Some(CacheValue::Block(block)) => Some(block.map(|block| block.downcast()
+                .expect("Cached block has the type of its key"))),
+            _ => None,
+        }
+    }
+
+    fn insert_block<T>(&self, key: CacheKey, block: Option<VecDataBlock<T>>)
+            where VecDataBlock<T>: DataBlock<T>,
+                  T: ReflectedType {
+        let data_size = block.as_ref().map_or(0, block_size);
+        let value = CacheValue::Block(block.map(|block| Arc::new(block) as Arc<dyn Any + Send + Sync>));
+        let size = entry_size(&key, &value, data_size);
+        self.cache().insert(key, value, size, self.capacity);
+    }
+}
+
+impl<N: N5Reader> N5Reader for CachedReader<N> {
+    fn get_version(&self) -> Result<Version> {
+        self.inner.get_version()
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+        let key = CacheKey::Attributes(path_name.to_owned());
+        if let Some(CacheValue::Attributes(data_attrs)) = self.cache().get(&key) {
+            return Ok(data_attrs);
+        }
+
+        let data_attrs = self.inner.get_dataset_attributes(path_name)?;
+        let value = CacheValue::Attributes(data_attrs.clone());
+        let size = entry_size(&key, &value, 0);
+        self.cache().insert(key, value, size, self.capacity);
+        Ok(data_attrs)
+    }
+
+    fn exists(&self, path_name: &str) -> Result<bool> {
+        self.inner.exists(path_name)
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        self.inner.get_block_uri(path_name, grid_position)
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<VecDataBlock<T>>>
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        let key = Self::block_key::<T>(path_name, &grid_position);
+        if let Some(block) = self.get_block::<T>(&key) {
+            return Ok(block.map(|block| (*block).clone()));
+        }
+
+        let block = self.inner.read_block(path_name, data_attrs, grid_position)?;
+        self.insert_block(key, block.clone());
+        Ok(block)
+    }
+
+    fn read_block_into<T: ReflectedType, B: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        block: &mut B,
+    ) -> Result<Option<()>> {
+        let key = Self::block_key::<T>(path_name, &grid_position);
+        if let Some(cached) = self.get_block::<T>(&key) {
+            return Ok(cached.map(|cached| block.reinitialize_with(cached.as_ref())));
+        }
+
+        let found = self.inner.read_block_into(path_name, data_attrs, grid_position, block)?;
+        let cached = found.map(|_| SliceDataBlock::new(
+            block.get_size().into(),
+            block.get_grid_position().into(),
+            block.get_data().to_vec()));
+        self.insert_block(key, cached);
+        Ok(found)
+    }
+
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<DataBlockMetadata>> {
+        self.inner.block_metadata(path_name, data_attrs, grid_position)
+    }
+
+    fn list_attributes(&self, path_name: &str) -> Result<Value> {
+        self.inner.list_attributes(path_name)
+    }
+}
+
+impl<N: N5Lister> N5Lister for CachedReader<N> {
+    fn list(&self, path_name: &str) -> Result<Vec<String>> {
+        self.inner.list(path_name)
+    }
+}
+
+impl<N: N5BlockLister> N5BlockLister for CachedReader<N> {
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>> {
+        self.inner.list_blocks(path_name)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+    use crate::in_memory::N5InMemory;
+    use crate::{
+        DataType,
+        N5Writer,
+    };
+
+    fn create_dataset(n5: &N5InMemory) -> DatasetAttributes {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![8, 8],
+            smallvec![4, 4],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        n5.create_dataset("foo", &data_attrs).unwrap();
+        for i in 0..2 {
+            for j in 0..2 {
+                let block_data: Vec<i32> = (0..16).map(|v| v + 100 * (2 * i + j)).collect();
+                n5.write_block("foo", &data_attrs, &SliceDataBlock::new(
+                    smallvec![4, 4],
+                    smallvec![i as u64, j as u64],
+                    block_data)).unwrap();
+            }
+        }
+        data_attrs
+    }
+
+    #[test]
+    fn cache_blocks_and_attributes() {
+        let n5 = N5InMemory::new();
+        let data_attrs = create_dataset(&n5);
+        let cached = CachedReader::new(n5, 1 << 20);
+
+        assert_eq!(cached.get_dataset_attributes("foo").unwrap(), data_attrs);
+        assert_eq!(cached.get_dataset_attributes("foo").unwrap(), data_attrs);
+        assert_eq!(cached.stats().hits, 1);
+        assert_eq!(cached.stats().misses, 1);
+
+        let block = cached.read_block::<i32>("foo", &data_attrs, smallvec![1, 0]).unwrap().unwrap();
+        assert_eq!(block.get_data(), &(200..216).collect::<Vec<i32>>()[..]);
+        let mut block_buff = cached.read_block::<i32>("foo", &data_attrs, smallvec![1, 0]).unwrap().unwrap();
+        assert_eq!(cached.stats().hits, 2);
+
+        // Blocks read into a buffer are cached, and vice versa.
+        assert!(cached.read_block_into("foo", &data_attrs, smallvec![0, 1], &mut block_buff).unwrap().is_some());
+        assert_eq!(block_buff.get_data(), &(100..116).collect::<Vec<i32>>()[..]);
+        assert_eq!(block_buff.get_grid_position(), &[0, 1]);
+        let block = cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 1]).unwrap().unwrap();
+        assert_eq!(block.get_data(), block_buff.get_data());
+        assert!(cached.read_block_into("foo", &data_attrs, smallvec![1, 0], &mut block_buff).unwrap().is_some());
+        assert_eq!(block_buff.get_grid_position(), &[1, 0]);
+        assert_eq!(cached.stats().hits, 4);
+        assert_eq!(cached.stats().misses, 3);
+
+        // Missing blocks are cached as missing.
+        assert!(cached.read_block::<i32>("foo", &data_attrs, smallvec![2, 2]).unwrap().is_none());
+        assert!(cached.read_block_into("foo", &data_attrs, smallvec![2, 2], &mut block_buff).unwrap().is_none());
+        assert_eq!(cached.stats().hits, 5);
+        assert_eq!(cached.stats().entries, 4);
+
+        cached.clear();
+        assert_eq!(cached.stats().entries, 0);
+        assert_eq!(cached.stats().size, 0);
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![1, 0]).unwrap();
+        assert_eq!(cached.stats().misses, 5);
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let n5 = N5InMemory::new();
+        let data_attrs = create_dataset(&n5);
+        let probe = CachedReader::new(N5InMemory::new(), usize::MAX);
+        let key = CachedReader::<N5InMemory>::block_key::<i32>("foo", &[0, 0]);
+        let block = n5.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        probe.insert_block(key, block);
+        let entry_size = probe.stats().size;
+
+        // Room for two blocks.
+        let cached = CachedReader::new(n5, 2 * entry_size + entry_size / 2);
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 1]).unwrap();
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![1, 0]).unwrap();
+        assert_eq!(cached.stats(), CacheStats {
+            hits: 1,
+            misses: 3,
+            evictions: 1,
+            entries: 2,
+            size: 2 * entry_size,
+        });
+
+        // [0, 1] was evicted rather than the more recently used [0, 0].
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        assert_eq!(cached.stats().hits, 2);
+        cached.read_block::<i32>("foo", &data_attrs, smallvec![0, 1]).unwrap();
+        assert_eq!(cached.stats().misses, 4);
+
+        // Entries larger than the cache are not cached.
+        let tiny = CachedReader::new(cached.into_inner(), 1);
+        tiny.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        tiny.read_block::<i32>("foo", &data_attrs, smallvec![0, 0]).unwrap();
+        assert_eq!(tiny.stats().misses, 2);
+        assert_eq!(tiny.stats().entries, 0);
+    }
+
+    #[test]
+    fn separate_element_types() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4],
+            smallvec![4],
+            DataType::OBJECT,
+            CompressionType::default(),
+        );
+        n5.create_dataset("foo", &data_attrs).unwrap();
+        n5.write_block("foo", &data_attrs, &SliceDataBlock::new_object(smallvec![0], vec![1u8, 2, 3])).unwrap();
+        let cached = CachedReader::new(n5, 1 << 20);
+
+        assert!(cached.read_block::<u8>("foo", &data_attrs, smallvec![0]).unwrap().is_some());
+        assert!(cached.read_block::<i32>("foo", &data_attrs, smallvec![0]).is_err());
+        assert_eq!(cached.stats().misses, 2);
+    }
+}
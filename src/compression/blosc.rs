@@ -0,0 +1,913 @@
+//! Blosc compression, compatible with the Java `n5-blosc` plugin.
+//!
+//! This is a pure rust implementation of the c-blosc 1.x frame format. Frames
+//! written by c-blosc using the `blosclz`, `lz4`, `lz4hc`, `zlib` and `zstd`
+//! internal compressors can be decoded. `snappy` frames are not supported.
+//! `zstd` requires the `zstd` feature.
+
+use std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+    Read,
+    Result,
+    Write,
+};
+
+use byteorder::{
+    ByteOrder,
+    LittleEndian,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::{
+    Compression,
+    Encoder,
+};
+use crate::DataType;
+
+
+/// Blosc frame format version written by this implementation.
+const BLOSC_VERSION_FORMAT: u8 = 2;
+/// Version of the internal compressor formats written by this implementation.
+const BLOSC_VERSION_LZ: u8 = 1;
+const BLOSC_HEADER_LEN: usize = 16;
+/// Largest buffer c-blosc compresses into a single frame.
+const MAX_BUFFERSIZE: usize = i32::MAX as usize - BLOSC_HEADER_LEN;
+/// Bound on the ratio of decompressed to compressed size of BloscLZ and LZ4
+/// streams, which encode at most 255 bytes of a match per input byte.
+const MAX_LZ_EXPANSION: usize = 256;
+/// Buffers smaller than this are never compressed.
+const MIN_BUFFERSIZE: usize = 128;
+/// Maximum type size for which blocks are split into per-byte streams.
+const MAX_SPLITS: usize = 16;
+/// Default block size for fast codecs, sized to fit in a typical L1 cache.
+const L1: usize = 32 * 1024;
+
+const FLAG_SHUFFLE: u8 = 0x01;
+const FLAG_MEMCPYED: u8 = 0x02;
+const FLAG_BITSHUFFLE: u8 = 0x04;
+const FLAG_DONT_SPLIT: u8 = 0x10;
+
+/// Do not shuffle bytes before compression.
+pub const NOSHUFFLE: i32 = 0;
+/// Shuffle bytes of each element before compression.
+pub const SHUFFLE: i32 = 1;
+/// Shuffle bits of each element before compression.
+pub const BITSHUFFLE: i32 = 2;
+
+
+/// Internal compressors available to Blosc.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BloscCompressor {
+    BloscLz,
+    Lz4,
+    Lz4Hc,
+    Snappy,
+    Zlib,
+    Zstd,
+}
+
+impl BloscCompressor {
+    /// Code for this compressor's format in Blosc frame flags.
+    fn format_code(self) -> u8 {
+        match self {
+            BloscCompressor::BloscLz => 0,
+            BloscCompressor::Lz4 | BloscCompressor::Lz4Hc => 1,
+            BloscCompressor::Snappy => 2,
+            BloscCompressor::Zlib => 3,
+            BloscCompressor::Zstd => 4,
+        }
+    }
+
+    /// Whether this is a high compression ratio codec, for which c-blosc
+    /// uses larger blocks.
+    fn is_hcr(self) -> bool {
+        matches!(self, BloscCompressor::Lz4Hc | BloscCompressor::Zlib | BloscCompressor::Zstd)
+    }
+
+    fn compress(self, clevel: i32, src: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            BloscCompressor::BloscLz => Ok(blosclz::compress(src)),
+            BloscCompressor::Lz4 => lz4::block::compress(src, None, false),
+            BloscCompressor::Lz4Hc => lz4::block::compress(
+                src,
+                Some(lz4::block::CompressionMode::HIGHCOMPRESSION(clevel)),
+                false),
+            BloscCompressor::Snappy => Err(unsupported_snappy()),
+            BloscCompressor::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(clevel as u32));
+                encoder.write_all(src)?;
+                encoder.finish()
+            },
+            #[cfg(feature = "zstd")]
+            BloscCompressor::Zstd => ::zstd::bulk::compress(src, zstd_level(clevel)),
+            #[cfg(not(feature = "zstd"))]
+            BloscCompressor::Zstd => Err(unsupported_zstd()),
+        }
+    }
+
+    /// Decompress a stream expected to hold `len` bytes, returning at most
+    /// that many. Output is only allocated as far as the stream can expand.
+    fn decompress(format_code: u8, src: &[u8], len: usize) -> Result<Vec<u8>> {
+        match format_code {
+            0 => {
+                let mut dest = lz_dest(src, len)?;
+                let n = blosclz::decompress(src, &mut dest)?;
+                dest.truncate(n);
+                Ok(dest)
+            },
+            1 => {
+                let mut dest = lz_dest(src, len)?;
+                let n = lz4::block::decompress_to_buffer(src, Some(len as i32), &mut dest)?;
+                dest.truncate(n);
+                Ok(dest)
+            },
+            2 => Err(unsupported_snappy()),
+            3 => read_at_most(flate2::read::ZlibDecoder::new(src), len),
+            #[cfg(feature = "zstd")]
+            4 => read_at_most(::zstd::stream::read::Decoder::with_buffer(src)?, len),
+            #[cfg(not(feature = "zstd"))]
+            4 => Err(unsupported_zstd()),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Unknown Blosc compressor format")),
+        }
+    }
+}
+
+/// Allocate the output of a BloscLZ or LZ4 stream, if `src` can expand to
+/// `len` bytes.
+fn lz_dest(src: &[u8], len: usize) -> Result<Vec<u8>> {
+    if len > src.len().saturating_mul(MAX_LZ_EXPANSION) {
+        return Err(Error::new(ErrorKind::InvalidData, "Blosc stream is too short for its size"));
+    }
+    Ok(vec![0; len])
+}
+
+/// Read at most `len` bytes, growing the output only as data is decoded.
+fn read_at_most<R: Read>(r: R, len: usize) -> Result<Vec<u8>> {
+    let mut dest = Vec::new();
+    r.take(len as u64).read_to_end(&mut dest)?;
+    Ok(dest)
+}
+
+fn unsupported_snappy() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Blosc snappy compression is not supported")
+}
+
+#[cfg(not(feature = "zstd"))]
+fn unsupported_zstd() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Blosc zstd compression requires the `zstd` feature")
+}
+
+/// Map Blosc's [0, 9] compression levels onto zstd's, as c-blosc does.
+#[cfg(feature = "zstd")]
+fn zstd_level(clevel: i32) -> i32 {
+    if clevel < 9 {
+        clevel * 2 - 1
+    } else {
+        22
+    }
+}
+
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BloscCompression {
+    #[serde(default = "default_blosc_cname")]
+    cname: BloscCompressor,
+    #[serde(default = "default_blosc_clevel")]
+    clevel: i32,
+    /// One of `NOSHUFFLE`, `SHUFFLE` or `BITSHUFFLE`.
+    #[serde(default = "default_blosc_shuffle")]
+    shuffle: i32,
+    /// Size of blocks within the Blosc frame, or 0 to choose automatically.
+    #[serde(default)]
+    blocksize: i32,
+    /// Retained for compatibility with Java; this implementation always
+    /// compresses on the calling thread.
+    #[serde(default = "default_blosc_nthreads")]
+    nthreads: i32,
+}
+
+fn default_blosc_cname() -> BloscCompressor {BloscCompressor::BloscLz}
+
+fn default_blosc_clevel() -> i32 {6}
+
+fn default_blosc_shuffle() -> i32 {SHUFFLE}
+
+fn default_blosc_nthreads() -> i32 {1}
+
+impl Default for BloscCompression {
+    fn default() -> BloscCompression {
+        BloscCompression {
+            cname: default_blosc_cname(),
+            clevel: default_blosc_clevel(),
+            shuffle: default_blosc_shuffle(),
+            blocksize: 0,
+            nthreads: default_blosc_nthreads(),
+        }
+    }
+}
+
+impl BloscCompression {
+    pub fn new(cname: BloscCompressor, clevel: i32, shuffle: i32) -> BloscCompression {
+        BloscCompression {
+            cname,
+            clevel,
+            shuffle,
+            ..Default::default()
+        }
+    }
+
+    /// Choose a block size similarly to c-blosc. Any block size that is a
+    /// multiple of the type size produces valid frames.
+    fn get_effective_blocksize(&self, nbytes: usize, typesize: usize) -> usize {
+        let mut blocksize = if self.blocksize > 0 {
+            std::cmp::max(self.blocksize as usize, MIN_BUFFERSIZE)
+        } else if self.cname.is_hcr() {
+            8 * L1
+        } else {
+            L1
+        };
+
+        blocksize = std::cmp::min(blocksize, nbytes);
+        if blocksize > typesize {
+            blocksize = blocksize / typesize * typesize;
+        }
+
+        blocksize
+    }
+
+    /// Compress a buffer of elements of `typesize` bytes into a Blosc frame.
+    fn compress(&self, src: &[u8], typesize: usize) -> Result<Vec<u8>> {
+        let nbytes = src.len();
+        if nbytes > MAX_BUFFERSIZE {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer is too large for Blosc"));
+        }
+
+        let clevel = self.clevel.clamp(0, 9);
+        let mut flags = self.cname.format_code() << 5;
+        match self.shuffle {
+            SHUFFLE => flags |= FLAG_SHUFFLE,
+            BITSHUFFLE => flags |= FLAG_BITSHUFFLE,
+            _ => {},
+        }
+
+        let blocksize = self.get_effective_blocksize(nbytes, typesize);
+        let split = typesize <= MAX_SPLITS && blocksize / typesize >= MIN_BUFFERSIZE;
+        if !split {
+            flags |= FLAG_DONT_SPLIT;
+        }
+
+        let mut frame = Vec::with_capacity(nbytes + BLOSC_HEADER_LEN);
+        frame.extend_from_slice(&[BLOSC_VERSION_FORMAT, BLOSC_VERSION_LZ, flags, typesize as u8]);
+        frame.extend_from_slice(&[0; 12]);
+
+        let compressed = clevel > 0 && nbytes >= MIN_BUFFERSIZE &&
+            self.compress_blocks(src, clevel, flags, typesize, blocksize, &mut frame)?;
+
+        if !compressed {
+            frame.truncate(BLOSC_HEADER_LEN);
+            frame[2] |= FLAG_MEMCPYED;
+            frame.extend_from_slice(src);
+        }
+
+        LittleEndian::write_u32(&mut frame[4..8], nbytes as u32);
+        LittleEndian::write_u32(&mut frame[8..12], blocksize as u32);
+        let cbytes = frame.len() as u32;
+        LittleEndian::write_u32(&mut frame[12..16], cbytes);
+
+        Ok(frame)
+    }
+
+    /// Compress all blocks of `src` into `frame`, returning `false` if the
+    /// result is not smaller than storing the buffer uncompressed.
+    fn compress_blocks(
+        &self,
+        src: &[u8],
+        clevel: i32,
+        flags: u8,
+        typesize: usize,
+        blocksize: usize,
+        frame: &mut Vec<u8>,
+    ) -> Result<bool> {
+        let nblocks = src.len().div_ceil(blocksize);
+        let bstarts_offset = frame.len();
+        frame.resize(bstarts_offset + 4 * nblocks, 0);
+        let mut shuffled = vec![0; blocksize];
+
+        for (i, block) in src.chunks(blocksize).enumerate() {
+            let bstart = frame.len() as u32;
+            LittleEndian::write_u32(&mut frame[bstarts_offset + 4 * i..], bstart);
+
+            let shuffled = &mut shuffled[..block.len()];
+            let block = shuffle_block(flags, typesize, block, shuffled);
+            let nsplits = get_nsplits(flags, typesize, block.len(), block.len() < blocksize);
+            let neblock = block.len() / nsplits;
+
+            for stream in block.chunks(neblock) {
+                let compressed = self.cname.compress(clevel, stream)?;
+                if compressed.is_empty() || compressed.len() >= neblock {
+                    frame.extend_from_slice(&(neblock as u32).to_le_bytes());
+                    frame.extend_from_slice(stream);
+                } else {
+                    frame.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                    frame.extend_from_slice(&compressed);
+                }
+            }
+
+            if frame.len() >= src.len() + BLOSC_HEADER_LEN {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Decompress a Blosc frame.
+fn decompress(frame: &[u8]) -> Result<Vec<u8>> {
+    fn malformed(msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("Malformed Blosc frame: {}", msg))
+    }
+
+    if frame.len() < BLOSC_HEADER_LEN {
+        return Err(malformed("too short for header"));
+    }
+    let flags = frame[2];
+    let typesize = frame[3] as usize;
+    let nbytes = LittleEndian::read_u32(&frame[4..8]) as usize;
+    let blocksize = LittleEndian::read_u32(&frame[8..12]) as usize;
+    let cbytes = LittleEndian::read_u32(&frame[12..16]) as usize;
+    if cbytes > frame.len() {
+        return Err(malformed("truncated"));
+    }
+    let frame = &frame[..cbytes];
+    if nbytes > MAX_BUFFERSIZE {
+        return Err(malformed("too large"));
+    }
+
+    if flags & FLAG_MEMCPYED != 0 {
+        return frame.get(BLOSC_HEADER_LEN..BLOSC_HEADER_LEN + nbytes)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| malformed("truncated"));
+    }
+    if nbytes == 0 {
+        return Ok(Vec::new());
+    }
+    if blocksize == 0 || typesize == 0 {
+        return Err(malformed("zero block or type size"));
+    }
+
+    let format_code = flags >> 5;
+    let nblocks = nbytes.div_ceil(blocksize);
+    let bstarts = frame.get(BLOSC_HEADER_LEN..BLOSC_HEADER_LEN + 4 * nblocks)
+        .ok_or_else(|| malformed("truncated block offsets"))?;
+    let shuffled = flags & (FLAG_SHUFFLE | FLAG_BITSHUFFLE) != 0;
+    // The header sizes are not trusted, so output grows only as streams are
+    // decoded rather than being allocated up front.
+    let mut dest = Vec::new();
+    let mut block = Vec::new();
+
+    for i in 0..nblocks {
+        let mut pos = LittleEndian::read_u32(&bstarts[4 * i..]) as usize;
+        let bsize = std::cmp::min(blocksize, nbytes - i * blocksize);
+        let nsplits = get_nsplits(flags, typesize, bsize, bsize < blocksize);
+        let neblock = bsize / nsplits;
+        block.clear();
+
+        for _ in 0..nsplits {
+            let csize = frame.get(pos..pos + 4)
+                .map(LittleEndian::read_u32)
+                .ok_or_else(|| malformed("truncated stream"))? as usize;
+            pos += 4;
+            let stream = frame.get(pos..pos + csize)
+                .ok_or_else(|| malformed("truncated stream"))?;
+            pos += csize;
+
+            if csize == neblock {
+                block.extend_from_slice(stream);
+            } else {
+                let decoded = BloscCompressor::decompress(format_code, stream, neblock)?;
+                if decoded.len() != neblock {
+                    return Err(malformed("stream decompressed to wrong size"));
+                }
+                block.extend_from_slice(&decoded);
+            }
+        }
+
+        if shuffled {
+            let start = dest.len();
+            dest.resize(start + bsize, 0);
+            unshuffle_block(flags, typesize, &block, &mut dest[start..]);
+        } else {
+            dest.extend_from_slice(&block);
+        }
+    }
+
+    Ok(dest)
+}
+
+/// Number of streams each block is split into, matching c-blosc.
+fn get_nsplits(flags: u8, typesize: usize, bsize: usize, leftover: bool) -> usize {
+    if flags & FLAG_DONT_SPLIT == 0 &&
+            typesize <= MAX_SPLITS &&
+            bsize / typesize >= MIN_BUFFERSIZE &&
+            !leftover {
+        typesize
+    } else {
+        1
+    }
+}
+
+/// Shuffle a block into `dest` according to `flags`, returning the block to
+/// compress.
+fn shuffle_block<'a>(flags: u8, typesize: usize, src: &'a [u8], dest: &'a mut [u8]) -> &'a [u8] {
+    if flags & FLAG_SHUFFLE != 0 && typesize > 1 {
+        byte_shuffle(typesize, src, dest, false);
+        dest
+    } else if flags & FLAG_BITSHUFFLE != 0 && src.len() >= typesize {
+        bit_shuffle(typesize, src, dest, false);
+        dest
+    } else {
+        src
+    }
+}
+
+fn unshuffle_block(flags: u8, typesize: usize, src: &[u8], dest: &mut [u8]) {
+    if flags & FLAG_SHUFFLE != 0 && typesize > 1 {
+        byte_shuffle(typesize, src, dest, true);
+    } else if flags & FLAG_BITSHUFFLE != 0 && src.len() >= typesize {
+        bit_shuffle(typesize, src, dest, true);
+    } else {
+        dest.copy_from_slice(src);
+    }
+}
+
+/// Transpose bytes so that byte `j` of every element is contiguous. Trailing
+/// bytes that do not form a whole element are copied unchanged.
+fn byte_shuffle(typesize: usize, src: &[u8], dest: &mut [u8], inverse: bool) {
+    let neblock = src.len() / typesize;
+    for i in 0..neblock {
+        for j in 0..typesize {
+            let (shuffled, unshuffled) = (j * neblock + i, i * typesize + j);
+            if inverse {
+                dest[unshuffled] = src[shuffled];
+            } else {
+                dest[shuffled] = src[unshuffled];
+            }
+        }
+    }
+    let tail = neblock * typesize;
+    dest[tail..].copy_from_slice(&src[tail..]);
+}
+
+/// Transpose bits so that bit `k` of byte `j` of every element is contiguous,
+/// following the layout of the bitshuffle library. Only a multiple of 8
+/// elements is shuffled; trailing bytes are copied unchanged.
+fn bit_shuffle(typesize: usize, src: &[u8], dest: &mut [u8], inverse: bool) {
+    let nelem = src.len() / typesize;
+    let nelem = nelem - nelem % 8;
+    let row_len = nelem / 8;
+    let tail = nelem * typesize;
+    dest[..tail].iter_mut().for_each(|b| *b = 0);
+
+    for i in 0..nelem {
+        for j in 0..typesize {
+            for k in 0..8 {
+                let elem_byte = i * typesize + j;
+                let row_byte = (j * 8 + k) * row_len + i / 8;
+                if inverse {
+                    dest[elem_byte] |= ((src[row_byte] >> (i % 8)) & 1) << k;
+                } else {
+                    dest[row_byte] |= ((src[elem_byte] >> k) & 1) << (i % 8);
+                }
+            }
+        }
+    }
+    dest[tail..].copy_from_slice(&src[tail..]);
+}
+
+/// The BloscLZ codec, a variant of FastLZ used as Blosc's default.
+mod blosclz {
+    use std::io::{
+        Error,
+        ErrorKind,
+        Result,
+    };
+
+    const MAX_COPY: usize = 32;
+    const MAX_LEN: usize = 264;
+    const MAX_DISTANCE: usize = 8191;
+    const MAX_FARDISTANCE: usize = 65535 + MAX_DISTANCE - 1;
+    const HASH_LOG: u32 = 13;
+    const MIN_MATCH: usize = 3;
+
+    fn hash(bytes: &[u8]) -> usize {
+        let v = u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16;
+        (v.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+    }
+
+    /// Greedily compress `src` with a single-entry hash table.
+    pub(super) fn compress(src: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(src.len() + src.len() / 32 + 1);
+        let mut table = vec![usize::MAX; 1 << HASH_LOG];
+        let mut literal_start = 0;
+        let mut ip = 0;
+
+        while ip + MIN_MATCH <= src.len() {
+            let h = hash(&src[ip..]);
+            let candidate = table[h];
+            table[h] = ip;
+
+            if candidate != usize::MAX &&
+                    ip - candidate - 1 <= MAX_FARDISTANCE &&
+                    src[candidate..candidate + MIN_MATCH] == src[ip..ip + MIN_MATCH] {
+                let max_len = std::cmp::min(src.len() - ip, MAX_LEN);
+                let mut len = MIN_MATCH;
+                while len < max_len && src[candidate + len] == src[ip + len] {
+                    len += 1;
+                }
+
+                emit_literals(&mut out, &src[literal_start..ip]);
+                emit_match(&mut out, len, ip - candidate - 1);
+                ip += len;
+                literal_start = ip;
+            } else {
+                ip += 1;
+            }
+        }
+        emit_literals(&mut out, &src[literal_start..]);
+
+        out
+    }
+
+    fn emit_literals(out: &mut Vec<u8>, literals: &[u8]) {
+        for run in literals.chunks(MAX_COPY) {
+            out.push((run.len() - 1) as u8);
+            out.extend_from_slice(run);
+        }
+    }
+
+    /// Emit a match of `len` bytes starting `distance + 1` bytes back.
+    fn emit_match(out: &mut Vec<u8>, len: usize, distance: usize) {
+        let len = len - 2;
+        let (near, far) = if distance < MAX_DISTANCE {
+            (distance, None)
+        } else {
+            (MAX_DISTANCE, Some(distance - MAX_DISTANCE))
+        };
+
+        out.push((std::cmp::min(len, 7) << 5) as u8 | (near >> 8) as u8);
+        if len >= 7 {
+            let mut remaining = len - 7;
+            while remaining >= 255 {
+                out.push(255);
+                remaining -= 255;
+            }
+            out.push(remaining as u8);
+        }
+        out.push((near & 0xff) as u8);
+        if let Some(far) = far {
+            out.push((far >> 8) as u8);
+            out.push((far & 0xff) as u8);
+        }
+    }
+
+    pub(super) fn decompress(src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        fn corrupt() -> Error {
+            Error::new(ErrorKind::InvalidData, "Corrupt BloscLZ stream")
+        }
+
+        if src.is_empty() {
+            return Ok(0);
+        }
+
+        let mut ip = 1;
+        let mut op = 0;
+        let mut ctrl = (src[0] & 31) as usize;
+
+        loop {
+            if ctrl >= 32 {
+                let mut len = (ctrl >> 5) - 1;
+                let mut distance = (ctrl & 31) << 8;
+                if len == 6 {
+                    loop {
+                        let code = *src.get(ip).ok_or_else(corrupt)?;
+                        ip += 1;
+                        len += code as usize;
+                        if code != 255 {
+                            break;
+                        }
+                    }
+                }
+                let code = *src.get(ip).ok_or_else(corrupt)?;
+                ip += 1;
+                distance += code as usize;
+                if code == 255 && distance == MAX_DISTANCE {
+                    let far = src.get(ip..ip + 2).ok_or_else(corrupt)?;
+                    ip += 2;
+                    distance = ((far[0] as usize) << 8) + far[1] as usize + MAX_DISTANCE;
+                }
+
+                let len = len + 3;
+                if op + len > dest.len() || distance + 1 > op {
+                    return Err(corrupt());
+                }
+                let from = op - distance - 1;
+                for k in 0..len {
+                    dest[op + k] = dest[from + k];
+                }
+                op += len;
+            } else {
+                let len = ctrl + 1;
+                if op + len > dest.len() || ip + len > src.len() {
+                    return Err(corrupt());
+                }
+                dest[op..op + len].copy_from_slice(&src[ip..ip + len]);
+                op += len;
+                ip += len;
+            }
+
+            match src.get(ip) {
+                Some(&c) => ctrl = c as usize,
+                None => break,
+            }
+            ip += 1;
+        }
+
+        Ok(op)
+    }
+}
+
+/// Buffers all written data and encodes it as a Blosc frame when finished.
+struct BloscWriter<W: Write> {
+    compression: BloscCompression,
+    typesize: usize,
+    buffer: Vec<u8>,
+    inner: W,
+}
+
+impl<W: Write> Write for BloscWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        self.buffer.extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    /// Blosc frames can not be written incrementally, so this only flushes
+    /// the underlying writer.
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Encoder for BloscWriter<W> {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let frame = self.compression.compress(&self.buffer, self.typesize)?;
+        self.inner.write_all(&frame)?;
+        self.inner.flush()
+    }
+}
+
+impl Compression for BloscCompression {
+    fn decoder<'a, R: Read + 'a>(&self, mut r: R) -> Result<Box<dyn Read + 'a>> {
+        let mut frame = Vec::new();
+        r.read_to_end(&mut frame)?;
+        Ok(Box::new(Cursor::new(decompress(&frame)?)))
+    }
+
+    /// Create an encoder of bytes, which shuffles have no effect on.
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        self.typed_encoder(w, DataType::UINT8)
+    }
+
+    fn typed_encoder<'a, W: Write + 'a>(&self, w: W, data_type: DataType) -> Result<Box<dyn Encoder + 'a>> {
+        if self.cname == BloscCompressor::Snappy {
+            return Err(unsupported_snappy());
+        }
+        Ok(Box::new(BloscWriter {
+            compression: self.clone(),
+            typesize: data_type.size_of(),
+            buffer: Vec::new(),
+            inner: w,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+
+    // Example from the n5 documentation spec. Below Blosc's minimum buffer
+    // size, so the data is stored uncompressed, with a type size of 2.
+    const TEST_BLOCK_I16_BLOSC: [u8; 44] = [
+        0x00, 0x00,
+        0x00, 0x03,
+        0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x03,
+        0x02, 0x01, 0x13, 0x02,
+        0x0c, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x00, 0x00,
+        0x1c, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x02,
+        0x00, 0x03, 0x00, 0x04,
+        0x00, 0x05, 0x00, 0x06,
+    ];
+
+    fn all_compressors() -> Vec<BloscCompressor> {
+        let mut compressors = vec![
+            BloscCompressor::BloscLz,
+            BloscCompressor::Lz4,
+            BloscCompressor::Lz4Hc,
+            BloscCompressor::Zlib,
+        ];
+        if cfg!(feature = "zstd") {
+            compressors.push(BloscCompressor::Zstd);
+        }
+        compressors
+    }
+
+    #[test]
+    fn test_read_doc_spec_block() {
+        crate::tests::test_read_doc_spec_block(
+            TEST_BLOCK_I16_BLOSC.as_ref(),
+            CompressionType::Blosc(BloscCompression::default()));
+    }
+
+    #[test]
+    fn test_write_doc_spec_block() {
+        crate::tests::test_write_doc_spec_block(
+            TEST_BLOCK_I16_BLOSC.as_ref(),
+            CompressionType::Blosc(BloscCompression::default()));
+    }
+
+    #[test]
+    fn test_rw() {
+        for cname in all_compressors() {
+            for &shuffle in &[NOSHUFFLE, SHUFFLE, BITSHUFFLE] {
+                crate::tests::test_block_compression_rw(
+                    CompressionType::Blosc(BloscCompression::new(cname, 5, shuffle)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Blosc(BloscCompression::default()));
+    }
+
+    #[test]
+    fn test_write_io_error() {
+        crate::tests::test_write_block_io_error(CompressionType::Blosc(BloscCompression::default()));
+    }
+
+    #[test]
+    fn test_frame_rw() {
+        let data: Vec<u8> = (0..100_000_u32)
+            .flat_map(|i| ((i / 7) % 251).to_be_bytes().to_vec())
+            .collect();
+        for cname in all_compressors() {
+            let compression = BloscCompression::new(cname, 9, BITSHUFFLE);
+            let frame = compression.compress(&data, 4).unwrap();
+            assert!(frame.len() < data.len() / 2, "{:?} did not compress", cname);
+            assert_eq!(frame[2] & FLAG_MEMCPYED, 0);
+            assert_eq!(decompress(&frame).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_typesize_from_data_type() {
+        let data: Vec<u8> = (0..1000_i32).flat_map(|i| i.to_be_bytes().to_vec()).collect();
+        let mut frame = Vec::new();
+        let mut encoder = BloscCompression::default()
+            .typed_encoder(&mut frame, DataType::INT32)
+            .unwrap();
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(frame[3], 4);
+        assert_eq!(frame[2] & (FLAG_SHUFFLE | FLAG_MEMCPYED), FLAG_SHUFFLE);
+        assert_eq!(decompress(&frame).unwrap(), data);
+    }
+
+    #[test]
+    fn test_decompress_oversized_header() {
+        // Claims the largest buffer c-blosc allows, in one block of a 4 byte
+        // BloscLZ stream, which must fail without allocating the buffer.
+        let nbytes = MAX_BUFFERSIZE as u32;
+        let mut frame = vec![0x02, 0x01, FLAG_DONT_SPLIT, 0x01];
+        frame.extend_from_slice(&nbytes.to_le_bytes());
+        frame.extend_from_slice(&nbytes.to_le_bytes());
+        frame.extend_from_slice(&28_u32.to_le_bytes());
+        frame.extend_from_slice(&20_u32.to_le_bytes());
+        frame.extend_from_slice(&4_u32.to_le_bytes());
+        frame.extend_from_slice(&[0x00, 0x00, 0xe0, 0xff]);
+
+        assert!(decompress(&frame).is_err());
+    }
+
+    #[test]
+    fn test_blosclz_far_match() {
+        // Literals, then a 5 byte match encoded as far, with a 16-bit offset
+        // following the maximum near offset. As in c-blosc's
+        // `blosclz_decompress`, the match copies from
+        // `op - ofs - MAX_DISTANCE - 1`, where `MAX_DISTANCE` is 8191, here
+        // the start of the output.
+        let literals: Vec<u8> = (0..10_000_u32).map(|i| (i % 251) as u8).collect();
+        let ofs = literals.len() - 8191 - 1;
+        let mut stream = Vec::new();
+        for run in literals.chunks(32) {
+            stream.push((run.len() - 1) as u8);
+            stream.extend_from_slice(run);
+        }
+        stream.extend_from_slice(&[(3 << 5) | 31, 0xff, (ofs >> 8) as u8, ofs as u8]);
+
+        let mut dest = vec![0; literals.len() + 5];
+        assert_eq!(blosclz::decompress(&stream, &mut dest).unwrap(), dest.len());
+        assert_eq!(&dest[..literals.len()], &literals[..]);
+        assert_eq!(&dest[literals.len()..], &literals[..5]);
+    }
+
+    #[test]
+    fn test_decompress_split_shuffled_frame() {
+        // A c-blosc style frame with type size 2, split into one stream per
+        // byte, with the stream of high bytes stored uncompressed and the
+        // stream of low bytes compressed as a BloscLZ run.
+        let data: Vec<u8> = (0..128_u16).flat_map(|i| i.to_le_bytes().to_vec()).collect();
+        let mut frame = vec![0x02, 0x01, FLAG_SHUFFLE, 0x02];
+        frame.extend_from_slice(&256_u32.to_le_bytes());
+        frame.extend_from_slice(&256_u32.to_le_bytes());
+        frame.extend_from_slice(&0_u32.to_le_bytes());
+        frame.extend_from_slice(&20_u32.to_le_bytes());
+        frame.extend_from_slice(&128_u32.to_le_bytes());
+        frame.extend((0..128_u8).collect::<Vec<_>>());
+        // Literal 0, then a run of 127 copies of the previous byte.
+        let run = [0x00, 0x00, 0xe0, 127 - 9, 0x00];
+        frame.extend_from_slice(&(run.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&run);
+        let cbytes = frame.len() as u32;
+        frame[12..16].copy_from_slice(&cbytes.to_le_bytes());
+
+        assert_eq!(decompress(&frame).unwrap(), data);
+    }
+
+    #[test]
+    fn test_bit_shuffle_layout() {
+        // 8 elements of 2 bytes; bit 0 of the low byte is set in element 1
+        // only, and bit 7 of the high byte is set in every element.
+        let mut src = [0x80_u8; 17];
+        for i in 0..8 {
+            src[2 * i] = if i == 1 {0x01} else {0x00};
+        }
+        let mut shuffled = [0; 17];
+        bit_shuffle(2, &src, &mut shuffled, false);
+
+        let mut expected = [0; 17];
+        expected[0] = 0b0000_0010;
+        expected[15] = 0xff;
+        expected[16] = 0x80;
+        assert_eq!(shuffled, expected);
+
+        let mut unshuffled = [0; 17];
+        bit_shuffle(2, &shuffled, &mut unshuffled, true);
+        assert_eq!(unshuffled, src);
+    }
+
+    #[test]
+    fn test_java_serialization() {
+        let compression: CompressionType = serde_json::from_str(
+            r#"{"type":"blosc","cname":"zstd","clevel":5,"shuffle":2,"blocksize":0,"nthreads":4}"#)
+            .expect("Failed to deserialize blosc compression");
+        assert_eq!(
+            compression,
+            CompressionType::Blosc(BloscCompression {
+                cname: BloscCompressor::Zstd,
+                clevel: 5,
+                shuffle: BITSHUFFLE,
+                blocksize: 0,
+                nthreads: 4,
+            }));
+
+        let compression: CompressionType = serde_json::from_str(r#"{"type":"blosc"}"#)
+            .expect("Failed to deserialize blosc compression");
+        assert_eq!(compression, CompressionType::Blosc(BloscCompression::default()));
+        assert_eq!(
+            serde_json::to_value(&compression).unwrap(),
+            serde_json::json!({
+                "type": "blosc",
+                "cname": "blosclz",
+                "clevel": 6,
+                "shuffle": 1,
+                "blocksize": 0,
+                "nthreads": 1,
+            }));
+    }
+}
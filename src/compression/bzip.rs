@@ -1,4 +1,4 @@
-use std::io::{Read, Write};
+use std::io::{Read, Result, Write};
 
 use bzip2::Compression as BzCompression;
 use bzip2::read::BzDecoder;
@@ -10,6 +10,7 @@ use serde::{
 
 use super::{
     Compression,
+    Encoder,
 };
 
 
@@ -44,12 +45,18 @@ impl Default for Bzip2Compression {
 }
 
 impl Compression for Bzip2Compression {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
-        Box::new(BzDecoder::new(r))
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(BzDecoder::new(r)))
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
-        Box::new(BzEncoder::new(w, self.get_effective_compression()))
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(BzEncoder::new(w, self.get_effective_compression())))
+    }
+}
+
+impl<W: Write> Encoder for BzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        BzEncoder::finish(*self)?.flush()
     }
 }
 
@@ -98,4 +105,9 @@ mod tests {
     fn test_rw() {
         crate::tests::test_block_compression_rw(CompressionType::Bzip2(Bzip2Compression::default()));
     }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Bzip2(Bzip2Compression::default()));
+    }
 }
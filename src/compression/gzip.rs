@@ -1,8 +1,14 @@
-use std::io::{Read, Write};
+use std::io::{Read, Result, Write};
 
 use flate2::Compression as GzCompression;
-use flate2::read::GzDecoder;
-use flate2::write::GzEncoder;
+use flate2::read::{
+    GzDecoder,
+    ZlibDecoder,
+};
+use flate2::write::{
+    GzEncoder,
+    ZlibEncoder,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -10,6 +16,7 @@ use serde::{
 
 use super::{
     Compression,
+    Encoder,
 };
 
 
@@ -18,9 +25,19 @@ use super::{
 pub struct GzipCompression {
     #[serde(default = "default_gzip_level")]
     level: i32,
+    /// Use zlib rather than gzip wrapping for the deflate stream.
+    #[serde(default)]
+    use_zlib: bool,
 }
 
 impl GzipCompression {
+    pub fn new(level: i32, use_zlib: bool) -> GzipCompression {
+        GzipCompression {
+            level,
+            use_zlib,
+        }
+    }
+
     /// Java has -1 as the default compression level for Gzip
     /// despite this not being a valid compression level.
     ///
@@ -41,17 +58,38 @@ impl Default for GzipCompression {
     fn default() -> GzipCompression {
         GzipCompression {
             level: default_gzip_level(),
+            use_zlib: false,
         }
     }
 }
 
 impl Compression for GzipCompression {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
-        Box::new(GzDecoder::new(r))
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
+        if self.use_zlib {
+            Ok(Box::new(ZlibDecoder::new(r)))
+        } else {
+            Ok(Box::new(GzDecoder::new(r)))
+        }
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
-        Box::new(GzEncoder::new(w, self.get_effective_level()))
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        if self.use_zlib {
+            Ok(Box::new(ZlibEncoder::new(w, self.get_effective_level())))
+        } else {
+            Ok(Box::new(GzEncoder::new(w, self.get_effective_level())))
+        }
+    }
+}
+
+impl<W: Write> Encoder for GzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        GzEncoder::finish(*self)?.flush()
+    }
+}
+
+impl<W: Write> Encoder for ZlibEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        ZlibEncoder::finish(*self)?.flush()
     }
 }
 
@@ -77,6 +115,20 @@ mod tests {
         0x0c, 0x00, 0x00, 0x00,
     ];
 
+    // The spec example as written by Java N5 with `useZlib` enabled.
+    const TEST_BLOCK_I16_ZLIB: [u8; 36] = [
+        0x00, 0x00,
+        0x00, 0x03,
+        0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x03,
+        0x78, 0x9c, 0x63, 0x60,
+        0x64, 0x60, 0x62, 0x60,
+        0x66, 0x60, 0x61, 0x60,
+        0x65, 0x60, 0x03, 0x00,
+        0x00, 0x67, 0x00, 0x16,
+    ];
+
     #[test]
     fn test_read_doc_spec_block() {
         crate::tests::test_read_doc_spec_block(
@@ -100,4 +152,62 @@ mod tests {
     fn test_rw() {
         crate::tests::test_block_compression_rw(CompressionType::Gzip(GzipCompression::default()));
     }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Gzip(GzipCompression::default()));
+    }
+
+    #[test]
+    fn test_write_io_error() {
+        crate::tests::test_write_block_io_error(CompressionType::Gzip(GzipCompression::default()));
+    }
+
+    #[test]
+    fn test_read_corrupt_block() {
+        // Set the reserved deflate block type in the first block header.
+        let mut corrupt_block = TEST_BLOCK_I16_GZIP;
+        corrupt_block[26] = 0xff;
+        crate::tests::test_read_corrupt_block(
+            &corrupt_block,
+            CompressionType::Gzip(GzipCompression::default()));
+    }
+
+    #[test]
+    fn test_read_zlib_block() {
+        crate::tests::test_read_doc_spec_block(
+            TEST_BLOCK_I16_ZLIB.as_ref(),
+            CompressionType::Gzip(GzipCompression::new(-1, true)));
+    }
+
+    #[test]
+    fn test_write_zlib_block() {
+        crate::tests::test_write_doc_spec_block(
+            TEST_BLOCK_I16_ZLIB.as_ref(),
+            CompressionType::Gzip(GzipCompression::new(-1, true)));
+    }
+
+    #[test]
+    fn test_rw_zlib() {
+        crate::tests::test_block_compression_rw(CompressionType::Gzip(GzipCompression::new(6, true)));
+    }
+
+    #[test]
+    fn test_encoder_flush_zlib() {
+        crate::tests::test_encoder_flush(CompressionType::Gzip(GzipCompression::new(6, true)));
+    }
+
+    #[test]
+    fn test_java_serialization() {
+        let compression: CompressionType = serde_json::from_str(r#"{"type":"gzip","level":-1,"useZlib":true}"#)
+            .expect("Failed to deserialize gzip compression");
+        assert_eq!(compression, CompressionType::Gzip(GzipCompression::new(-1, true)));
+
+        let compression: CompressionType = serde_json::from_str(r#"{"type":"gzip","level":-1}"#)
+            .expect("Failed to deserialize gzip compression");
+        assert_eq!(compression, CompressionType::Gzip(GzipCompression::default()));
+        assert_eq!(
+            serde_json::to_value(&compression).unwrap(),
+            serde_json::json!({"type": "gzip", "level": -1, "useZlib": false}));
+    }
 }
@@ -1,55 +1,265 @@
-use std::io::{Read, Result, Write};
+use std::hash::Hasher;
+use std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+    Read,
+    Result,
+    Write,
+};
 
+use byteorder::{
+    LittleEndian,
+    ReadBytesExt,
+    WriteBytesExt,
+};
 use lz4::{
     BlockMode,
     BlockSize,
     Decoder,
-    Encoder,
+    Encoder as Lz4Encoder,
     EncoderBuilder,
 };
 use serde::{
     Deserialize,
     Serialize,
 };
+use twox_hash::XxHash32;
 
 use super::{
     Compression,
+    Encoder,
 };
 
 
-// From: https://github.com/bozaro/lz4-rs/issues/9
-// Kludge to finish Lz4 encoder on Drop.
-struct Wrapper<W: Write> {
-    s: Option<Encoder<W>>,
+/// Magic bytes starting each block of a lz4-java `LZ4BlockOutputStream`.
+const BLOCK_MAGIC: &[u8; 8] = b"LZ4Block";
+/// Magic bytes starting an LZ4 frame.
+const FRAME_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+const BLOCK_METHOD_RAW: u8 = 0x10;
+const BLOCK_METHOD_LZ4: u8 = 0x20;
+const BLOCK_COMPRESSION_LEVEL_BASE: u32 = 10;
+const BLOCK_MIN_SIZE: usize = 64;
+const BLOCK_MAX_SIZE: usize = 1 << 25;
+const BLOCK_CHECKSUM_SEED: u32 = 0x9747_b28c;
+
+fn block_checksum(data: &[u8]) -> u32 {
+    let mut hasher = XxHash32::with_seed(BLOCK_CHECKSUM_SEED);
+    hasher.write(data);
+    hasher.finish() as u32 & 0x0FFF_FFFF
+}
+
+impl<W: Write> Encoder for Lz4Encoder<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        let (mut w, result) = Lz4Encoder::finish(*self);
+        result?;
+        w.flush()
+    }
+}
+
+
+/// Writer producing the block stream layout of lz4-java's
+/// `LZ4BlockOutputStream`, which Java N5 uses for LZ4 compression.
+struct BlockWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    block_size: usize,
+    token_level: u8,
 }
 
-impl<W: Write> Write for Wrapper<W> {
-    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
-        self.s.as_mut().unwrap().write(buffer)
+impl<W: Write> BlockWriter<W> {
+    fn new(inner: W, block_size: usize) -> BlockWriter<W> {
+        let block_size = block_size.clamp(BLOCK_MIN_SIZE, BLOCK_MAX_SIZE);
+        let level = (32 - (block_size as u32 - 1).leading_zeros())
+            .saturating_sub(BLOCK_COMPRESSION_LEVEL_BASE);
+        BlockWriter {
+            inner,
+            buffer: Vec::with_capacity(block_size),
+            block_size,
+            token_level: level as u8,
+        }
     }
 
+    fn write_header(&mut self, method: u8, compressed_len: usize, original_len: usize, checksum: u32) -> Result<()> {
+        self.inner.write_all(BLOCK_MAGIC)?;
+        self.inner.write_u8(method | self.token_level)?;
+        self.inner.write_i32::<LittleEndian>(compressed_len as i32)?;
+        self.inner.write_i32::<LittleEndian>(original_len as i32)?;
+        self.inner.write_u32::<LittleEndian>(checksum)
+    }
+
+    fn write_buffered_block(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let checksum = block_checksum(&self.buffer);
+        let compressed = lz4::block::compress(&self.buffer, None, false)?;
+        let buffer = std::mem::take(&mut self.buffer);
+        if compressed.len() < buffer.len() {
+            self.write_header(BLOCK_METHOD_LZ4, compressed.len(), buffer.len(), checksum)?;
+            self.inner.write_all(&compressed)?;
+        } else {
+            self.write_header(BLOCK_METHOD_RAW, buffer.len(), buffer.len(), checksum)?;
+            self.inner.write_all(&buffer)?;
+        }
+        self.buffer = buffer;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+}
+
+impl<W: Write> Write for BlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(self.block_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == self.block_size {
+            self.write_buffered_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Write any buffered data as a block, like lz4-java.
     fn flush(&mut self) -> Result<()> {
-        self.s.as_mut().unwrap().flush()
+        self.write_buffered_block()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Encoder for BlockWriter<W> {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.write_buffered_block()?;
+        self.write_header(BLOCK_METHOD_RAW, 0, 0, 0)?;
+        self.inner.flush()
+    }
+}
+
+/// Reader for the block stream layout of lz4-java's `LZ4BlockInputStream`.
+struct BlockReader<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    compressed: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> BlockReader<R> {
+    fn new(inner: R) -> BlockReader<R> {
+        BlockReader {
+            inner,
+            block: Vec::new(),
+            compressed: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn read_block(&mut self) -> Result<()> {
+        let mut magic = [0u8; 8];
+        self.inner.read_exact(&mut magic)?;
+        if &magic != BLOCK_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid LZ4 block magic"));
+        }
+        let method = self.inner.read_u8()? & 0xF0;
+        let compressed_len = self.inner.read_i32::<LittleEndian>()?;
+        let original_len = self.inner.read_i32::<LittleEndian>()?;
+        let checksum = self.inner.read_u32::<LittleEndian>()?;
+        if original_len < 0 || compressed_len < 0 ||
+            original_len as usize > BLOCK_MAX_SIZE ||
+            (method == BLOCK_METHOD_RAW && original_len != compressed_len) {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid LZ4 block lengths"));
+        }
+
+        if original_len == 0 && compressed_len == 0 {
+            if checksum != 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid LZ4 end block checksum"));
+            }
+            self.finished = true;
+            return Ok(());
+        }
+
+        self.block.resize(original_len as usize, 0);
+        match method {
+            BLOCK_METHOD_RAW => self.inner.read_exact(&mut self.block)?,
+            BLOCK_METHOD_LZ4 => {
+                self.compressed.resize(compressed_len as usize, 0);
+                self.inner.read_exact(&mut self.compressed)?;
+                let len = lz4::block::decompress_to_buffer(
+                    &self.compressed,
+                    Some(original_len),
+                    &mut self.block)?;
+                if len != self.block.len() {
+                    return Err(Error::new(ErrorKind::InvalidData, "Truncated LZ4 block"));
+                }
+            },
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Unknown LZ4 block compression method")),
+        }
+        if block_checksum(&self.block) != checksum {
+            return Err(Error::new(ErrorKind::InvalidData, "LZ4 block checksum mismatch"));
+        }
+        self.position = 0;
+
+        Ok(())
     }
 }
 
-impl<W: Write> Drop for Wrapper<W> {
-    fn drop(&mut self) {
-        if let Some(s) = self.s.take() {
-            s.finish().1.unwrap();
+impl<R: Read> Read for BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while self.position == self.block.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.block.clear();
+            self.position = 0;
+            self.read_block()?;
         }
+        let len = buf.len().min(self.block.len() - self.position);
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
     }
 }
 
 
+/// Stream layout used to write LZ4 compressed blocks.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Lz4Format {
+    /// lz4-java block stream, as written by Java N5.
+    #[default]
+    Block,
+    /// LZ4 frame format, as written by earlier versions of this crate. Not
+    /// readable by Java N5.
+    Frame,
+}
+
+impl Lz4Format {
+    fn is_default(&self) -> bool {
+        *self == Lz4Format::default()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Lz4Compression {
     #[serde(default = "default_lz4_block_size")]
     block_size: i32,
+    /// Blocks are always readable in either format, but are written in this
+    /// one. Not part of Java N5, so only serialized for the legacy format.
+    #[serde(default, skip_serializing_if = "Lz4Format::is_default")]
+    format: Lz4Format,
 }
 
 impl Lz4Compression {
+    pub fn new(block_size: i32, format: Lz4Format) -> Lz4Compression {
+        Lz4Compression {
+            block_size,
+            format,
+        }
+    }
+
     /// `lz4` uses an enum for specifying block size, so choose the smallest
     /// larger size from that enum.
     fn get_effective_block_size(&self) -> BlockSize {
@@ -71,22 +281,36 @@ impl Default for Lz4Compression {
     fn default() -> Lz4Compression {
         Lz4Compression {
             block_size: default_lz4_block_size(),
+            format: Lz4Format::default(),
         }
     }
 }
 
 impl Compression for Lz4Compression {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
-        Box::new(Decoder::new(r).expect("TODO: LZ4 returns a result here"))
+    fn decoder<'a, R: Read + 'a>(&self, mut r: R) -> Result<Box<dyn Read + 'a>> {
+        // Detect the format from the magic bytes so that blocks written in
+        // either format remain readable.
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        let r = Cursor::new(magic).chain(r);
+        if magic == FRAME_MAGIC {
+            Ok(Box::new(Decoder::new(r)?))
+        } else {
+            Ok(Box::new(BlockReader::new(r)))
+        }
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
-        let encoder = EncoderBuilder::new()
-            .block_size(self.get_effective_block_size())
-            .block_mode(BlockMode::Independent)
-            .build(w)
-            .expect("TODO");
-        Box::new(Wrapper {s: Some(encoder)})
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        match self.format {
+            Lz4Format::Block => Ok(Box::new(BlockWriter::new(w, self.block_size.max(0) as usize))),
+            Lz4Format::Frame => {
+                let encoder = EncoderBuilder::new()
+                    .block_size(self.get_effective_block_size())
+                    .block_mode(BlockMode::Independent)
+                    .build(w)?;
+                Ok(Box::new(encoder))
+            },
+        }
     }
 }
 
@@ -95,7 +319,35 @@ mod tests {
     use super::*;
     use crate::compression::CompressionType;
 
-    const TEST_BLOCK_I16_LZ4: [u8; 47] = [
+    // Generated with this implementation, following the layout of lz4-java's
+    // `LZ4BlockOutputStream`, and not written by Java N5. Data this short is
+    // stored uncompressed, and its checksum matches an independent XXH32
+    // with lz4-java's seed and mask.
+    const TEST_BLOCK_I16_LZ4: [u8; 70] = [
+        0x00, 0x00,
+        0x00, 0x03,
+        0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x03,
+        0x4c, 0x5a, 0x34, 0x42,
+        0x6c, 0x6f, 0x63, 0x6b,
+        0x16,
+        0x0c, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x00, 0x00,
+        0x90, 0x25, 0x8b, 0x06,
+        0x00, 0x01, 0x00, 0x02,
+        0x00, 0x03, 0x00, 0x04,
+        0x00, 0x05, 0x00, 0x06,
+        0x4c, 0x5a, 0x34, 0x42,
+        0x6c, 0x6f, 0x63, 0x6b,
+        0x16,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // Written by earlier versions of this crate in LZ4 frame format.
+    const TEST_BLOCK_I16_LZ4_FRAME: [u8; 47] = [
         0x00, 0x00,
         0x00, 0x03,
         0x00, 0x00, 0x00, 0x01,
@@ -125,8 +377,68 @@ mod tests {
             CompressionType::Lz4(Lz4Compression::default()));
     }
 
+    #[test]
+    fn test_read_legacy_frame_block() {
+        crate::tests::test_read_doc_spec_block(
+            TEST_BLOCK_I16_LZ4_FRAME.as_ref(),
+            CompressionType::Lz4(Lz4Compression::default()));
+    }
+
     #[test]
     fn test_rw() {
         crate::tests::test_block_compression_rw(CompressionType::Lz4(Lz4Compression::default()));
     }
+
+    #[test]
+    fn test_rw_small_blocks() {
+        crate::tests::test_block_compression_rw(CompressionType::Lz4(Lz4Compression::new(64, Lz4Format::Block)));
+    }
+
+    #[test]
+    fn test_rw_frame() {
+        crate::tests::test_block_compression_rw(CompressionType::Lz4(Lz4Compression::new(65_536, Lz4Format::Frame)));
+    }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Lz4(Lz4Compression::default()));
+        crate::tests::test_encoder_flush(CompressionType::Lz4(Lz4Compression::new(65_536, Lz4Format::Frame)));
+    }
+
+    #[test]
+    fn test_write_io_error() {
+        crate::tests::test_write_block_io_error(CompressionType::Lz4(Lz4Compression::default()));
+        crate::tests::test_write_block_io_error(CompressionType::Lz4(Lz4Compression::new(65_536, Lz4Format::Frame)));
+    }
+
+    #[test]
+    fn test_block_stream_checksum() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 7) as u8).collect();
+        let mut stream = Vec::new();
+        let mut writer = Box::new(BlockWriter::new(&mut stream, 256));
+        writer.write_all(&data).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(stream[8], BLOCK_METHOD_LZ4);
+
+        let mut decoded = Vec::new();
+        BlockReader::new(&stream[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        let last = stream.len() - 1 - 21;
+        stream[last] ^= 0xFF;
+        assert!(BlockReader::new(&stream[..]).read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_java_serialization() {
+        let compression: CompressionType = serde_json::from_str(r#"{"type":"lz4","blockSize":1024}"#)
+            .expect("Failed to deserialize lz4 compression");
+        assert_eq!(compression, CompressionType::Lz4(Lz4Compression::new(1024, Lz4Format::Block)));
+        assert_eq!(
+            serde_json::to_value(&compression).unwrap(),
+            serde_json::json!({"type": "lz4", "blockSize": 1024}));
+        assert_eq!(
+            serde_json::to_value(CompressionType::Lz4(Lz4Compression::new(1024, Lz4Format::Frame))).unwrap(),
+            serde_json::json!({"type": "lz4", "blockSize": 1024, "format": "frame"}));
+    }
 }
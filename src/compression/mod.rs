@@ -1,15 +1,19 @@
 //! Compression for block voxel data.
 
 use std;
-use std::io::{Read, Write};
+use std::io::{Read, Result, Write};
 
 use serde::{
     Deserialize,
     Serialize,
 };
 
+use crate::DataType;
+
 
 pub mod raw;
+#[cfg(feature = "blosc")]
+pub mod blosc;
 #[cfg(feature = "bzip")]
 pub mod bzip;
 #[cfg(feature = "gzip")]
@@ -18,13 +22,37 @@ pub mod gzip;
 pub mod lz;
 #[cfg(feature = "xz")]
 pub mod xz;
+#[cfg(feature = "zstd")]
+pub mod zstd;
 
 
 /// Common interface for compressing writers and decompressing readers.
 pub trait Compression : Default {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a>;
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>>;
+
+    /// Create an encoder compressing into `w`.
+    ///
+    /// The compressed stream is only complete once the encoder is finished
+    /// with `Encoder::finish`. Dropping an encoder without finishing it may
+    /// leave the stream truncated.
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>>;
+
+    /// Create an encoder compressing elements of `data_type` into `w`.
+    ///
+    /// Compression schemes which rearrange data by element, such as Blosc's
+    /// shuffles, use the element size. Others encode the same as `encoder`.
+    fn typed_encoder<'a, W: Write + 'a>(&self, w: W, _data_type: DataType) -> Result<Box<dyn Encoder + 'a>> {
+        self.encoder(w)
+    }
+}
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a>;
+/// Writer compressing data into an underlying writer.
+///
+/// Flushing an encoder flushes as much data as the compression scheme allows
+/// without ending the compressed stream.
+pub trait Encoder: Write {
+    /// Finish the compressed stream and flush the underlying writer.
+    fn finish(self: Box<Self>) -> Result<()>;
 }
 
 /// Enumeration of known compression schemes.
@@ -33,6 +61,8 @@ pub trait Compression : Default {
 #[serde(tag = "type")]
 pub enum CompressionType {
     Raw(raw::RawCompression),
+    #[cfg(feature = "blosc")]
+    Blosc(blosc::BloscCompression),
     #[cfg(feature = "bzip")]
     Bzip2(bzip::Bzip2Compression),
     #[cfg(feature = "gzip")]
@@ -41,6 +71,8 @@ pub enum CompressionType {
     Lz4(lz::Lz4Compression),
     #[cfg(feature = "xz")]
     Xz(xz::XzCompression),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::ZstdCompression),
 }
 
 impl CompressionType {
@@ -57,10 +89,13 @@ impl Default for CompressionType {
 }
 
 impl Compression for CompressionType {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
         match *self {
             CompressionType::Raw(ref c) => c.decoder(r),
 
+            #[cfg(feature = "blosc")]
+            CompressionType::Blosc(ref c) => c.decoder(r),
+
             #[cfg(feature = "bzip")]
             CompressionType::Bzip2(ref c) => c.decoder(r),
 
@@ -72,13 +107,19 @@ impl Compression for CompressionType {
 
             #[cfg(feature = "lz")]
             CompressionType::Lz4(ref c) => c.decoder(r),
+
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd(ref c) => c.decoder(r),
         }
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
         match *self {
             CompressionType::Raw(ref c) => c.encoder(w),
 
+            #[cfg(feature = "blosc")]
+            CompressionType::Blosc(ref c) => c.encoder(w),
+
             #[cfg(feature = "bzip")]
             CompressionType::Bzip2(ref c) => c.encoder(w),
 
@@ -90,6 +131,17 @@ impl Compression for CompressionType {
 
             #[cfg(feature = "lz")]
             CompressionType::Lz4(ref c) => c.encoder(w),
+
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd(ref c) => c.encoder(w),
+        }
+    }
+
+    #[cfg(feature = "blosc")]
+    fn typed_encoder<'a, W: Write + 'a>(&self, w: W, data_type: DataType) -> Result<Box<dyn Encoder + 'a>> {
+        match *self {
+            CompressionType::Blosc(ref c) => c.typed_encoder(w, data_type),
+            _ => self.encoder(w),
         }
     }
 }
@@ -99,6 +151,9 @@ impl std::fmt::Display for CompressionType {
         write!(f, "{}", match *self {
             CompressionType::Raw(_) => "Raw",
 
+            #[cfg(feature = "blosc")]
+            CompressionType::Blosc(_) => "Blosc",
+
             #[cfg(feature = "bzip")]
             CompressionType::Bzip2(_) => "Bzip2",
 
@@ -110,6 +165,9 @@ impl std::fmt::Display for CompressionType {
 
             #[cfg(feature = "lz")]
             CompressionType::Lz4(_) => "Lz4",
+
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd(_) => "Zstd",
         })
     }
 }
@@ -125,6 +183,8 @@ macro_rules! compression_from_impl {
 }
 
 compression_from_impl!(Raw, raw::RawCompression);
+#[cfg(feature = "blosc")]
+compression_from_impl!(Blosc, blosc::BloscCompression);
 #[cfg(feature = "bzip")]
 compression_from_impl!(Bzip2, bzip::Bzip2Compression);
 #[cfg(feature = "gzip")]
@@ -133,3 +193,5 @@ compression_from_impl!(Gzip, gzip::GzipCompression);
 compression_from_impl!(Xz, xz::XzCompression);
 #[cfg(feature = "lz")]
 compression_from_impl!(Lz4, lz::Lz4Compression);
+#[cfg(feature = "zstd")]
+compression_from_impl!(Zstd, zstd::ZstdCompression);
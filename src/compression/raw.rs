@@ -1,23 +1,45 @@
-use std::io::{Read, Write};
+use std::io::{Read, Result, Write};
 
 use serde::{
     Deserialize,
     Serialize,
 };
 
-use super::Compression;
+use super::{
+    Compression,
+    Encoder,
+};
 
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
 pub struct RawCompression;
 
 impl Compression for RawCompression {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
-        Box::new(r)
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(r))
+    }
+
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(RawEncoder(w)))
+    }
+}
+
+/// Writes data through to the underlying writer unchanged.
+struct RawEncoder<W: Write>(W);
+
+impl<W: Write> Write for RawEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
-        Box::new(w)
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Encoder for RawEncoder<W> {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.0.flush()
     }
 }
 
@@ -25,6 +47,7 @@ impl Compression for RawCompression {
 mod tests {
     use super::*;
     use crate::compression::CompressionType;
+    use crate::DataBlock;
 
     // Example from the n5 documentation spec.
     const TEST_BLOCK_I16_RAW: [u8; 28] = [
@@ -41,6 +64,14 @@ mod tests {
         0x00, 0x06,
     ];
 
+    // Object (mode 2) block of the bytes 1 to 6, as written by Java N5.
+    const TEST_BLOCK_OBJECT_RAW: [u8; 12] = [
+        0x00, 0x02,
+        0x00, 0x00, 0x00, 0x06,
+        0x01, 0x02, 0x03, 0x04,
+        0x05, 0x06,
+    ];
+
     #[test]
     fn test_read_doc_spec_block() {
         crate::tests::test_read_doc_spec_block(
@@ -60,8 +91,48 @@ mod tests {
         crate::tests::test_block_compression_rw(CompressionType::Raw(RawCompression));
     }
 
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Raw(RawCompression));
+    }
+
+    #[test]
+    fn test_read_truncated_block() {
+        crate::tests::test_read_corrupt_block(
+            &TEST_BLOCK_I16_RAW[..TEST_BLOCK_I16_RAW.len() - 1],
+            CompressionType::Raw(RawCompression));
+    }
+
     #[test]
     fn test_varlength_rw() {
         crate::tests::test_varlength_block_rw(CompressionType::Raw(RawCompression));
     }
+
+    #[test]
+    fn test_object_rw() {
+        crate::tests::test_object_block_rw(CompressionType::Raw(RawCompression));
+    }
+
+    #[test]
+    fn test_object_block_layout() {
+        let data_attrs = crate::DatasetAttributes::new(
+            smallvec![5, 6, 7],
+            smallvec![1, 2, 3],
+            crate::DataType::OBJECT,
+            CompressionType::Raw(RawCompression));
+        let block_in = crate::SliceDataBlock::new_object(smallvec![0, 0, 0], [1u8, 2, 3, 4, 5, 6]);
+        let mut buff: Vec<u8> = Vec::new();
+        <crate::DefaultBlock as crate::DefaultBlockWriter<u8, _, _>>::write_block(
+            &mut buff,
+            &data_attrs,
+            &block_in).expect("write_block failed");
+        assert_eq!(buff, TEST_BLOCK_OBJECT_RAW);
+
+        let block_out = <crate::DefaultBlock as crate::DefaultBlockReader<u8, _>>::read_block(
+            &TEST_BLOCK_OBJECT_RAW[..],
+            &data_attrs,
+            smallvec![0, 0, 0]).expect("read_block failed");
+        assert!(block_out.get_size().is_empty());
+        assert_eq!(block_out.get_data(), &[1, 2, 3, 4, 5, 6]);
+    }
 }
@@ -1,4 +1,4 @@
-use std::io::{Read, Write};
+use std::io::{Read, Result, Write};
 
 use serde::{
     Deserialize,
@@ -9,6 +9,7 @@ use xz2::write::XzEncoder;
 
 use super::{
     Compression,
+    Encoder,
 };
 
 
@@ -30,13 +31,19 @@ impl Default for XzCompression {
 }
 
 impl Compression for XzCompression {
-    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Box<dyn Read + 'a> {
-        Box::new(XzDecoder::new(r))
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(XzDecoder::new(r)))
     }
 
-    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Box<dyn Write + 'a> {
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
         // TODO: check that preset is non-negative.s
-        Box::new(XzEncoder::new(w, self.preset as u32))
+        Ok(Box::new(XzEncoder::new(w, self.preset as u32)))
+    }
+}
+
+impl<W: Write> Encoder for XzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        XzEncoder::finish(*self)?.flush()
     }
 }
 
@@ -89,4 +96,9 @@ mod tests {
     fn test_rw() {
         crate::tests::test_block_compression_rw(CompressionType::Xz(XzCompression::default()));
     }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Xz(XzCompression::default()));
+    }
 }
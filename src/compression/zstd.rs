@@ -0,0 +1,129 @@
+use std::io::{Read, Result, Write};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use zstd::stream::read::Decoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+use super::{
+    Compression,
+    Encoder,
+};
+
+
+/// Zstandard compression, compatible with the Java `n5-zstandard` plugin.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ZstdCompression {
+    #[serde(default = "default_zstd_level")]
+    level: i32,
+    /// Number of worker threads for compression. `None` or `Some(0)` compress
+    /// on the calling thread.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nb_workers: Option<u32>,
+}
+
+impl ZstdCompression {
+    pub fn new(level: i32, nb_workers: Option<u32>) -> ZstdCompression {
+        ZstdCompression {
+            level,
+            nb_workers,
+        }
+    }
+}
+
+fn default_zstd_level() -> i32 {zstd::DEFAULT_COMPRESSION_LEVEL}
+
+impl Default for ZstdCompression {
+    fn default() -> ZstdCompression {
+        ZstdCompression {
+            level: default_zstd_level(),
+            nb_workers: None,
+        }
+    }
+}
+
+impl Compression for ZstdCompression {
+    fn decoder<'a, R: Read + 'a>(&self, r: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(Decoder::new(r)?))
+    }
+
+    fn encoder<'a, W: Write + 'a>(&self, w: W) -> Result<Box<dyn Encoder + 'a>> {
+        let mut encoder = ZstdEncoder::new(w, self.level)?;
+        match self.nb_workers {
+            Some(n) if n > 0 => encoder.multithread(n)?,
+            _ => {},
+        }
+        Ok(Box::new(encoder))
+    }
+}
+
+impl<W: Write> Encoder for ZstdEncoder<'_, W> {
+    fn finish(self: Box<Self>) -> Result<()> {
+        ZstdEncoder::finish(*self)?.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+
+    // Generated with this implementation at the default level. The reference
+    // `zstd` CLI decodes it and writes the same frame with `-3 --no-check`.
+    // It was not written by Java N5.
+    const TEST_BLOCK_I16_ZSTD: [u8; 37] = [
+        0x00, 0x00,
+        0x00, 0x03,
+        0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x03,
+        0x28, 0xb5, 0x2f, 0xfd,
+        0x00, 0x58, 0x61, 0x00,
+        0x00, 0x00, 0x01, 0x00,
+        0x02, 0x00, 0x03, 0x00,
+        0x04, 0x00, 0x05, 0x00,
+        0x06,
+    ];
+
+    #[test]
+    fn test_read_doc_spec_block() {
+        crate::tests::test_read_doc_spec_block(
+            TEST_BLOCK_I16_ZSTD.as_ref(),
+            CompressionType::Zstd(ZstdCompression::default()));
+    }
+
+    #[test]
+    fn test_write_doc_spec_block() {
+        crate::tests::test_write_doc_spec_block(
+            TEST_BLOCK_I16_ZSTD.as_ref(),
+            CompressionType::Zstd(ZstdCompression::default()));
+    }
+
+    #[test]
+    fn test_rw() {
+        crate::tests::test_block_compression_rw(CompressionType::Zstd(ZstdCompression::default()));
+    }
+
+    #[test]
+    fn test_encoder_flush() {
+        crate::tests::test_encoder_flush(CompressionType::Zstd(ZstdCompression::default()));
+    }
+
+    #[test]
+    fn test_rw_multithreaded() {
+        crate::tests::test_block_compression_rw(CompressionType::Zstd(ZstdCompression::new(5, Some(2))));
+    }
+
+    #[test]
+    fn test_java_serialization() {
+        let compression: CompressionType = serde_json::from_str(r#"{"type":"zstd","level":7}"#)
+            .expect("Failed to deserialize zstd compression");
+        assert_eq!(compression, CompressionType::Zstd(ZstdCompression::new(7, None)));
+        assert_eq!(
+            serde_json::to_value(&compression).unwrap(),
+            serde_json::json!({"type": "zstd", "level": 7}));
+    }
+}
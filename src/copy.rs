@@ -0,0 +1,277 @@
+//! Copying groups and datasets between containers.
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use crate::compression::CompressionType;
+use crate::{
+    DatasetAttributes,
+    N5BlockLister,
+    N5Error,
+    N5Lister,
+    N5Writer,
+    DATASET_ATTRIBUTE_KEYS,
+    VERSION_ATTRIBUTE_KEY,
+};
+
+
+/// Copy a group or dataset, with its attributes, blocks and all descendants,
+/// from one container to another.
+///
+/// Datasets keep their dimensions, block size and data type. If a
+/// `compression` is given, datasets are created with it and their blocks are
+/// decoded and encoded again. Otherwise, or if it matches a dataset's
+/// compression, blocks are copied as their encoded bytes. Only blocks listed
+/// by the source within the dataset bounds are copied. The container version
+/// attribute is not copied.
+///
+/// ```
+/// use n5::prelude::*;
+/// use n5::smallvec::smallvec;
+///
+/// let src = N5InMemory::new();
+/// let data_attrs = DatasetAttributes::new(
+///     smallvec![10, 10],
+///     smallvec![5, 5],
+///     DataType::UINT8,
+///     CompressionType::default(),
+/// );
+/// src.create_dataset("raw/s0", &data_attrs).unwrap();
+/// src.set_attribute("raw", "resolution".to_owned(), [4, 4]).unwrap();
+/// let block = SliceDataBlock::new(smallvec![5, 5], smallvec![1, 0], [7u8; 25]);
+/// src.write_block("raw/s0", &data_attrs, &block).unwrap();
+///
+/// let dst = N5InMemory::new();
+/// n5::copy::copy(&src, "raw", &dst, "copied", None).unwrap();
+///
+/// let copied_attrs = dst.get_dataset_attributes("copied/s0").unwrap();
+/// assert_eq!(copied_attrs, data_attrs);
+/// let copied = dst.read_block::<u8>("copied/s0", &copied_attrs, smallvec![1, 0]).unwrap().unwrap();
+/// assert_eq!(copied.get_data(), &[7u8; 25][..]);
+/// assert_eq!(dst.get_attribute::<[u32; 2]>("copied", "resolution").unwrap(), [4, 4]);
+/// ```
+pub fn copy<R, W>(
+    src: &R,
+    src_path: &str,
+    dst: &W,
+    dst_path: &str,
+    compression: Option<&CompressionType>,
+) -> Result<(), N5Error>
+        where R: N5Lister + N5BlockLister + ?Sized,
+              W: N5Writer + ?Sized {
+    if !src.exists(src_path)? {
+        return Err(N5Error::PathNotFound(src_path.to_owned()));
+    }
+    let mut attributes = match src.list_attributes(src_path) {
+        Ok(Value::Object(attributes)) => attributes,
+        Ok(_) => Map::new(),
+        Err(N5Error::AttributeNotFound { key: None, .. }) => Map::new(),
+        Err(e) => return Err(e),
+    };
+    attributes.remove(VERSION_ATTRIBUTE_KEY);
+
+    if src.dataset_exists(src_path)? {
+        let src_attrs = src.get_dataset_attributes(src_path)?;
+        let dst_attrs = match compression {
+            Some(compression) => DatasetAttributes {
+                compression: compression.clone(),
+                ..src_attrs.clone()
+            },
+            None => src_attrs.clone(),
+        };
+        for key in DATASET_ATTRIBUTE_KEYS.iter() {
+            attributes.remove(*key);
+        }
+
+        dst.create_dataset(dst_path, &dst_attrs)?;
+        if !attributes.is_empty() {
+            dst.set_attributes(dst_path, attributes)?;
+        }
+        copy_blocks(src, src_path, &src_attrs, dst, dst_path, &dst_attrs)
+    } else {
+        dst.create_group(dst_path)?;
+        if !attributes.is_empty() {
+            dst.set_attributes(dst_path, attributes)?;
+        }
+        for child in src.list(src_path)? {
+            copy(src, &child_path(src_path, &child), dst, &child_path(dst_path, &child), compression)?;
+        }
+        Ok(())
+    }
+}
+
+fn child_path(path_name: &str, child: &str) -> String {
+    let path_name = path_name.trim_end_matches('/');
+    if path_name.is_empty() {
+        child.to_owned()
+    } else {
+        format!("{}/{}", path_name, child)
+    }
+}
+
+/// Copy every existing block of a dataset, as encoded bytes if the
+/// compression of both datasets matches.
+fn copy_blocks<R, W>(
+    src: &R,
+    src_path: &str,
+    src_attrs: &DatasetAttributes,
+    dst: &W,
+    dst_path: &str,
+    dst_attrs: &DatasetAttributes,
+) -> Result<(), N5Error>
+        where R: N5BlockLister + ?Sized,
+              W: N5Writer + ?Sized {
+    let grid_positions = src.list_blocks(src_path)?.into_iter()
+        .filter(|grid_position| src_attrs.in_bounds(grid_position));
+
+    if src_attrs.compression == dst_attrs.compression {
+        for grid_position in grid_positions {
+            if let Some(data) = src.read_block_raw(src_path, src_attrs, grid_position.clone())? {
+                dst.write_block_raw(dst_path, dst_attrs, grid_position, &data)?;
+            }
+        }
+    } else {
+        data_type_match!(src_attrs.data_type, {
+            for grid_position in grid_positions {
+                if let Some(block) = src.read_block::<RsType>(src_path, src_attrs, grid_position)? {
+                    dst.write_block(dst_path, dst_attrs, &block)?;
+                }
+            }
+        })
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::raw::RawCompression;
+    use crate::in_memory::N5InMemory;
+    use crate::{
+        DataBlock,
+        DataType,
+        N5Reader,
+        SliceDataBlock,
+    };
+    use serde_json::json;
+
+    fn create_source() -> (N5InMemory, DatasetAttributes) {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![7, 4, 3],
+            smallvec![3, 4, 2],
+            DataType::INT16,
+            CompressionType::Raw(RawCompression),
+        );
+        n5.create_group("a/empty").unwrap();
+        n5.set_attribute("a", "name".to_owned(), "group").unwrap();
+        n5.create_dataset("a/b/data", &data_attrs).unwrap();
+        n5.set_attribute("a/b/data", "resolution".to_owned(), [1, 2, 3]).unwrap();
+
+        let full: Vec<i16> = (0..24).collect();
+        n5.write_block("a/b/data", &data_attrs, &SliceDataBlock::new(
+            smallvec![3, 4, 2], smallvec![0, 0, 1], &full)).unwrap();
+        n5.write_block("a/b/data", &data_attrs, &SliceDataBlock::new(
+            smallvec![1, 4, 1], smallvec![2, 0, 1], &full[..4])).unwrap();
+        let varlength: Vec<i16> = (0..30).collect();
+        n5.write_block("a/b/data", &data_attrs, &SliceDataBlock::new(
+            smallvec![3, 4, 2], smallvec![1, 0, 0], &varlength)).unwrap();
+
+        (n5, data_attrs)
+    }
+
+    fn check_copy<N: N5Lister>(dst: &N, dst_path: &str, src: &N5InMemory, compression: &CompressionType) {
+        let mut children = dst.list(dst_path).unwrap();
+        children.sort();
+        assert_eq!(children, vec!["b", "empty"]);
+        assert_eq!(dst.list_attributes(dst_path).unwrap(), json!({"name": "group"}));
+
+        let src_attrs = src.get_dataset_attributes("a/b/data").unwrap();
+        let data_path = format!("{}/b/data", dst_path);
+        let dst_attrs = dst.get_dataset_attributes(&data_path).unwrap();
+        assert_eq!(dst_attrs.get_compression(), compression);
+        assert_eq!(dst_attrs.get_dimensions(), src_attrs.get_dimensions());
+        assert_eq!(dst.get_attribute::<[u64; 3]>(&data_path, "resolution").unwrap(), [1, 2, 3]);
+
+        for coord in src_attrs.coord_iter() {
+            let expected = src.read_block::<i16>("a/b/data", &src_attrs, coord.clone().into()).unwrap();
+            let copied = dst.read_block::<i16>(&data_path, &dst_attrs, coord.into()).unwrap();
+            assert_eq!(
+                copied.map(|b| (b.get_size().to_vec(), b.into_data())),
+                expected.map(|b| (b.get_size().to_vec(), b.into_data())));
+        }
+    }
+
+    #[test]
+    fn test_copy_raw() {
+        let (src, data_attrs) = create_source();
+        let dst = N5InMemory::new();
+        copy(&src, "a", &dst, "c/d", None).unwrap();
+        check_copy(&dst, "c/d", &src, &CompressionType::Raw(RawCompression));
+        assert_eq!(
+            dst.read_block_raw("c/d/b/data", &data_attrs, smallvec![1, 0, 0]).unwrap(),
+            src.read_block_raw("a/b/data", &data_attrs, smallvec![1, 0, 0]).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_copy_reencoded() {
+        use crate::compression::gzip::GzipCompression;
+
+        let (src, _) = create_source();
+        let dst = N5InMemory::new();
+        let gzip = CompressionType::Gzip(GzipCompression::default());
+        copy(&src, "a", &dst, "a", Some(&gzip)).unwrap();
+        check_copy(&dst, "a", &src, &gzip);
+    }
+
+    #[test]
+    fn test_copy_root() {
+        let (src, _) = create_source();
+        let dst = N5InMemory::new();
+        copy(&src, "", &dst, "", None).unwrap();
+        assert_eq!(dst.list("").unwrap(), vec!["a"]);
+        assert_eq!(dst.get_version().unwrap(), src.get_version().unwrap());
+        check_copy(&dst, "a", &src, &CompressionType::Raw(RawCompression));
+
+        assert!(matches!(
+            copy(&src, "missing", &dst, "missing", None),
+            Err(N5Error::PathNotFound(_))));
+    }
+
+    #[cfg(feature = "filesystem")]
+    #[test]
+    fn test_copy_filesystem_sharded() {
+        use crate::filesystem::N5Filesystem;
+        use tempdir::TempDir;
+
+        let (src, data_attrs) = create_source();
+        let src_dir = TempDir::new("rust_n5_tests").unwrap();
+        let src_fs = N5Filesystem::open_or_create(src_dir.path().to_str().unwrap()).unwrap();
+        copy(&src, "a", &src_fs, "a", None).unwrap();
+        let sharded_attrs = data_attrs.clone().with_shard_size(smallvec![6, 4, 2]);
+        src_fs.create_dataset("sharded", &sharded_attrs).unwrap();
+        let block_data: Vec<i16> = (0..24).collect();
+        src_fs.write_block("sharded", &sharded_attrs, &SliceDataBlock::new(
+            smallvec![3, 4, 2], smallvec![1, 0, 0], &block_data)).unwrap();
+
+        let dst_dir = TempDir::new("rust_n5_tests").unwrap();
+        let dst_fs = N5Filesystem::open_or_create(dst_dir.path().to_str().unwrap()).unwrap();
+        copy(&src_fs, "", &dst_fs, "", None).unwrap();
+
+        check_copy(&dst_fs, "a", &src, &CompressionType::Raw(RawCompression));
+        assert_eq!(dst_fs.get_dataset_attributes("sharded").unwrap(), sharded_attrs);
+        assert_eq!(
+            dst_fs.read_block_raw("sharded", &sharded_attrs, smallvec![1, 0, 0]).unwrap(),
+            src_fs.read_block_raw("sharded", &sharded_attrs, smallvec![1, 0, 0]).unwrap());
+        assert_eq!(dst_fs.read_block_raw("sharded", &sharded_attrs, smallvec![0, 0, 0]).unwrap(), None);
+
+        // Other backends do not support sharding.
+        assert!(matches!(
+            copy(&src_fs, "sharded", &N5InMemory::new(), "sharded", None),
+            Err(N5Error::InvalidMetadata(_))));
+    }
+}
@@ -21,6 +21,9 @@ pub enum DataType {
     INT64,
     FLOAT32,
     FLOAT64,
+    /// Opaque bytes, such as Java serialized objects, stored in object
+    /// (mode 2) blocks. Blocks of this type are read and written as `u8`.
+    OBJECT,
 }
 
 /// Replace all RsType tokens with the provide type.
@@ -79,7 +82,7 @@ macro_rules! data_type_rstype_replace {
 
 /// Match a DataType-valued expression, and in each arm repeat the provided
 /// code block with the token `RsType` replaced with the primitive type
-/// appropriate for that arm.
+/// appropriate for that arm. `DataType::OBJECT` is matched as `u8`.
 #[macro_export]
 macro_rules! data_type_match {
     ($match_expr:expr, $($expr:tt)*) => {
@@ -95,6 +98,7 @@ macro_rules! data_type_match {
                 $crate::DataType::INT64 => $crate::data_type_rstype_replace!(i64, $($expr)*),
                 $crate::DataType::FLOAT32 => $crate::data_type_rstype_replace!(f32, $($expr)*),
                 $crate::DataType::FLOAT64 => $crate::data_type_rstype_replace!(f64, $($expr)*),
+                $crate::DataType::OBJECT => $crate::data_type_rstype_replace!(u8, $($expr)*),
             }
         }
     };
@@ -0,0 +1,188 @@
+//! Error type for N5 operations.
+
+use std::fmt;
+use std::io::ErrorKind;
+
+use crate::{
+    DataType,
+    GridCoord,
+    Version,
+};
+
+
+/// Result type for N5 operations.
+pub type Result<T> = std::result::Result<T, N5Error>;
+
+/// Errors arising from N5 operations.
+#[derive(Debug)]
+pub enum N5Error {
+    /// The container's N5 version is not compatible with this library's
+    /// version, `crate::VERSION`.
+    IncompatibleVersion(Version),
+    /// The container's version attribute is missing or malformed.
+    InvalidVersion(String),
+    /// No group or dataset exists at this path.
+    PathNotFound(String),
+    /// No dataset exists at this path.
+    DatasetNotFound(String),
+    /// A path name resolves outside the container.
+    PathEscape(String),
+    /// A block was requested with a type different from the dataset's.
+    WrongDataType {
+        expected: DataType,
+        found: DataType,
+    },
+    /// A grid position is outside the bounds of the dataset.
+    OutOfBounds(GridCoord),
+    /// Coordinates or arrays do not match the dataset's dimensionality.
+    DimensionMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// An array's shape does not match the dataset's dimensions.
+    ShapeMismatch {
+        expected: Vec<u64>,
+        found: Vec<u64>,
+    },
+    /// A block header could not be parsed.
+    MalformedHeader(String),
+    /// Dataset or container metadata is malformed or not supported.
+    InvalidMetadata(String),
+    /// A group has no attribute with this key, or no attributes at all if
+    /// `key` is `None`.
+    AttributeNotFound {
+        path: String,
+        key: Option<String>,
+    },
+    /// A mandatory dataset attribute would be removed from a dataset.
+    MandatoryAttribute {
+        path: String,
+        key: String,
+    },
+    /// An attribute, or all attributes if `key` is `None`, could not be
+    /// deserialized as the requested type.
+    InvalidAttribute {
+        path: String,
+        key: Option<String>,
+        source: serde_json::Error,
+    },
+    /// A nested attribute path cannot be set at `key`, because it indexes
+    /// past the end of an array or into a value that is not an object or
+    /// array.
+    InvalidAttributePath {
+        attribute_path: String,
+        key: String,
+    },
+    /// A container opened read-only was asked to modify this path.
+    ReadOnly(String),
+    /// A compression codec failed to encode or decode, including errors
+    /// reading or writing the block data stream through it.
+    Codec(std::io::Error),
+    /// Attributes could not be serialized or deserialized.
+    Json(serde_json::Error),
+    /// An I/O error from the container backend.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for N5Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            N5Error::IncompatibleVersion(v) =>
+                write!(f, "Container version {} is incompatible with supported version {}", v, crate::VERSION),
+            N5Error::InvalidVersion(msg) => write!(f, "Invalid container version: {}", msg),
+            N5Error::PathNotFound(path) => write!(f, "Path does not exist: \"{}\"", path),
+            N5Error::DatasetNotFound(path) => write!(f, "Dataset does not exist: \"{}\"", path),
+            N5Error::PathEscape(path) => write!(f, "Path name is outside this N5 container: \"{}\"", path),
+            N5Error::WrongDataType { expected, found } =>
+                write!(f, "Wrong data type: dataset is {} but {} was requested", expected, found),
+            N5Error::OutOfBounds(coord) => write!(f, "Grid position {:?} is out of bounds", &coord[..]),
+            N5Error::DimensionMismatch { expected, found } =>
+                write!(f, "Wrong number of dimensions: expected {} but found {}", expected, found),
+            N5Error::ShapeMismatch { expected, found } =>
+                write!(f, "Wrong shape: expected {:?} but found {:?}", expected, found),
+            N5Error::MalformedHeader(msg) => write!(f, "Malformed block header: {}", msg),
+            N5Error::InvalidMetadata(msg) => write!(f, "Invalid metadata: {}", msg),
+            N5Error::AttributeNotFound { path, key: Some(key) } =>
+                write!(f, "Attribute \"{}\" does not exist at \"{}\"", key, path),
+            N5Error::AttributeNotFound { path, key: None } =>
+                write!(f, "No attributes exist at \"{}\"", path),
+            N5Error::MandatoryAttribute { path, key } =>
+                write!(f, "Attribute \"{}\" is mandatory for the dataset at \"{}\"", key, path),
+            N5Error::InvalidAttribute { path, key: Some(key), source } =>
+                write!(f, "Attribute \"{}\" at \"{}\" is not of the requested type: {}", key, path, source),
+            N5Error::InvalidAttribute { path, key: None, source } =>
+                write!(f, "Attributes at \"{}\" are not of the requested type: {}", path, source),
+            N5Error::InvalidAttributePath { attribute_path, key } =>
+                write!(f, "Cannot set nested attribute \"{}\" at key \"{}\"", attribute_path, key),
+            N5Error::ReadOnly(path) => write!(f, "Container is read-only: cannot modify \"{}\"", path),
+            N5Error::Codec(e) => write!(f, "Compression codec error: {}", e),
+            N5Error::Json(e) => write!(f, "JSON error: {}", e),
+            N5Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl N5Error {
+    /// Error for a path whose attributes do not exist, depending on whether
+    /// a group exists there.
+    pub(crate) fn missing_attributes(path_name: &str, group_exists: bool) -> N5Error {
+        if group_exists {
+            N5Error::AttributeNotFound {
+                path: path_name.to_owned(),
+                key: None,
+            }
+        } else {
+            N5Error::PathNotFound(path_name.to_owned())
+        }
+    }
+}
+
+impl std::error::Error for N5Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            N5Error::Codec(e) | N5Error::Io(e) => Some(e),
+            N5Error::Json(e) | N5Error::InvalidAttribute { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for N5Error {
+    fn from(e: std::io::Error) -> Self {
+        N5Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for N5Error {
+    fn from(e: serde_json::Error) -> Self {
+        N5Error::Json(e)
+    }
+}
+
+/// Allows N5 errors to propagate through code still using `std::io::Result`.
+impl From<N5Error> for std::io::Error {
+    fn from(e: N5Error) -> Self {
+        let kind = match e {
+            N5Error::Io(e) => return e,
+            N5Error::Codec(ref e) => e.kind(),
+            N5Error::PathNotFound(_) |
+            N5Error::DatasetNotFound(_) |
+            N5Error::AttributeNotFound { .. } |
+            N5Error::PathEscape(_) => ErrorKind::NotFound,
+            N5Error::ReadOnly(_) => ErrorKind::PermissionDenied,
+            N5Error::WrongDataType { .. } |
+            N5Error::OutOfBounds(_) |
+            N5Error::DimensionMismatch { .. } |
+            N5Error::ShapeMismatch { .. } |
+            N5Error::MandatoryAttribute { .. } |
+            N5Error::InvalidAttributePath { .. } => ErrorKind::InvalidInput,
+            N5Error::IncompatibleVersion(_) |
+            N5Error::InvalidVersion(_) |
+            N5Error::MalformedHeader(_) |
+            N5Error::InvalidMetadata(_) |
+            N5Error::InvalidAttribute { .. } |
+            N5Error::Json(_) => ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
@@ -10,16 +10,34 @@ use std::io::{
     BufReader,
     BufWriter,
     Read,
-    Result,
     Seek,
     SeekFrom,
+    Take,
+    Write,
 };
+use std::ops::Deref;
 use std::path::{
+    Path,
     PathBuf,
 };
 use std::str::FromStr;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::{
+    Condvar,
+    Mutex,
+    PoisonError,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
 
 use fs2::FileExt;
+use same_file::Handle;
+use serde::Serialize;
 use serde_json::{
     self,
     json,
@@ -27,6 +45,14 @@ use serde_json::{
 };
 use walkdir::WalkDir;
 
+use crate::attributes;
+use crate::error::Result;
+use crate::shard::{
+    self,
+    ShardIndex,
+    ShardPosition,
+    ShardUpdate,
+};
 use crate::{
     is_version_compatible,
     DataBlock,
@@ -35,6 +61,8 @@ use crate::{
     DefaultBlockReader,
     DefaultBlockWriter,
     GridCoord,
+    N5BlockLister,
+    N5Error,
     N5Lister,
     N5Reader,
     N5Writer,
@@ -50,98 +78,508 @@ use crate::{
 /// Name of the attributes file stored in the container root and dataset dirs.
 const ATTRIBUTES_FILE: &str = "attributes.json";
 
+/// Distinguishes temporary files of concurrent atomic writes and stale lock
+/// file removals in this process.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Paths of shards being updated by this process, so that updates in this
+/// process are serialized even when files are not locked.
+static UPDATING_SHARDS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static UPDATED_SHARD: Condvar = Condvar::new();
+
+
+/// How an `N5Filesystem` coordinates concurrent access to its files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Files are not locked. This is only safe if there is no concurrent
+    /// writer, or if blocks are written atomically, attributes are not
+    /// written concurrently, and no two processes write blocks in the same
+    /// shard concurrently. Writes of a shard by threads of one process are
+    /// serialized, but writers in different processes can lose each other's
+    /// blocks in a shard, even if writes are atomic.
+    None,
+    /// Advisory shared locks for reading and exclusive locks for writing are
+    /// taken on each file.
+    #[default]
+    Advisory,
+    /// A lock file, named after the locked file with a `.lock` suffix, is
+    /// created beside it while it is read or written. This works where
+    /// advisory locks do not, such as on some network filesystems, but reads
+    /// are also exclusive. Read-only containers wait for lock files without
+    /// creating them. Lock files left behind by a crashed process are removed
+    /// once older than `N5FilesystemBuilder::stale_lock_age`, if set, or
+    /// otherwise must be removed manually.
+    LockFile,
+}
 
-/// A filesystem-backed N5 container.
-#[derive(Clone)]
-pub struct N5Filesystem {
+/// How a container locks files: its `LockMode` and how it waits for lock
+/// files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileLocks {
+    pub(crate) mode: LockMode,
+    pub(crate) read_only: bool,
+    pub(crate) timeout: Duration,
+    pub(crate) stale_age: Option<Duration>,
+}
+
+impl FileLocks {
+    pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub(crate) fn new(mode: LockMode, read_only: bool) -> FileLocks {
+        FileLocks {
+            mode,
+            read_only,
+            timeout: Self::DEFAULT_TIMEOUT,
+            stale_age: None,
+        }
+    }
+
+    /// Open a file and lock it.
+    pub(crate) fn open(self, path: &Path, options: &fs::OpenOptions, exclusive: bool) -> Result<LockedFile> {
+        let lock = match self.mode {
+            LockMode::LockFile if self.read_only => {
+                LockFileGuard::wait(path, &self)?;
+                None
+            },
+            LockMode::LockFile => Some(LockFileGuard::acquire(path, &self)?),
+            LockMode::None | LockMode::Advisory => None,
+        };
+        let file = options.open(path)?;
+        if self.mode == LockMode::Advisory {
+            if exclusive {
+                file.lock_exclusive()?;
+            } else {
+                file.lock_shared()?;
+            }
+        }
+        Ok(LockedFile { file, _lock: lock })
+    }
+}
+
+/// A lock file, which is removed when this is dropped.
+struct LockFileGuard(PathBuf);
+
+impl LockFileGuard {
+    const MAX_WAIT: Duration = Duration::from_millis(100);
+
+    fn lock_path(path: &Path) -> PathBuf {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        lock_path.into()
+    }
+
+    /// Create the lock file for a path, waiting while another process holds
+    /// it.
+    fn acquire(path: &Path, locks: &FileLocks) -> Result<LockFileGuard> {
+        let lock_path = Self::lock_path(path);
+        Self::wait_for(&lock_path, locks, || {
+            fs::OpenOptions::new().write(true).create_new(true).open(&lock_path).map(|_| ())
+        })?;
+        Ok(LockFileGuard(lock_path))
+    }
+
+    /// Wait until no process holds the lock file for a path, without
+    /// creating it.
+    fn wait(path: &Path, locks: &FileLocks) -> Result<()> {
+        let lock_path = Self::lock_path(path);
+        Self::wait_for(&lock_path, locks, || match lock_path.symlink_metadata() {
+            Ok(_) => Err(ErrorKind::AlreadyExists.into()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        })
+    }
+
+    /// Retry `try_lock` while it fails because the lock file exists, removing
+    /// the lock file if it is stale, until `locks.timeout` has passed.
+    fn wait_for<F>(lock_path: &Path, locks: &FileLocks, mut try_lock: F) -> Result<()>
+            where F: FnMut() -> std::io::Result<()> {
+        let start = Instant::now();
+        let mut wait = Duration::from_millis(1);
+        loop {
+            match try_lock() {
+                Ok(()) => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {},
+                Err(e) => return Err(e.into()),
+            }
+
+            if Self::is_stale(lock_path, locks.stale_age) {
+                Self::remove_stale(lock_path, locks.stale_age)?;
+                continue;
+            }
+            if start.elapsed() >= locks.timeout {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("Timed out waiting for lock file {}", lock_path.display())).into());
+            }
+            std::thread::sleep(wait);
+            wait = (wait * 2).min(Self::MAX_WAIT);
+        }
+    }
+
+    /// Remove a lock file found to be stale. Another process may have replaced
+    /// it with a held lock file since, so it is first renamed to a unique
+    /// path and checked again, and restored if it is no longer stale.
+    fn remove_stale(lock_path: &Path, stale_age: Option<Duration>) -> Result<()> {
+        let mut stale_path = lock_path.as_os_str().to_owned();
+        stale_path.push(format!(
+            ".{}-{}.stale",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let stale_path = PathBuf::from(stale_path);
+        match fs::rename(lock_path, &stale_path) {
+            Ok(()) => {},
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        if !Self::is_stale(&stale_path, stale_age) {
+            // Linking fails rather than replacing a lock file created since.
+            match fs::hard_link(&stale_path, lock_path) {
+                Ok(()) => {},
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {},
+                Err(e) => {
+                    let _ = fs::remove_file(&stale_path);
+                    return Err(e.into());
+                },
+            }
+        }
+        fs::remove_file(&stale_path)?;
+        Ok(())
+    }
+
+    /// Whether a lock file was last modified longer than `stale_age` ago.
+    fn is_stale(lock_path: &Path, stale_age: Option<Duration>) -> bool {
+        let modified = lock_path.symlink_metadata().and_then(|metadata| metadata.modified());
+        match (stale_age, modified) {
+            (Some(stale_age), Ok(modified)) => modified.elapsed().is_ok_and(|age| age > stale_age),
+            _ => false,
+        }
+    }
+}
+
+impl Drop for LockFileGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Marks a shard as being updated by this process until dropped.
+struct ShardUpdateGuard(PathBuf);
+
+impl ShardUpdateGuard {
+    /// Wait until no other thread of this process is updating a shard.
+    fn acquire(path: &Path) -> ShardUpdateGuard {
+        let mut updating = UPDATING_SHARDS.lock().unwrap_or_else(PoisonError::into_inner);
+        while updating.iter().any(|p| p == path) {
+            updating = UPDATED_SHARD.wait(updating).unwrap_or_else(PoisonError::into_inner);
+        }
+        updating.push(path.to_owned());
+        ShardUpdateGuard(path.to_owned())
+    }
+}
+
+impl Drop for ShardUpdateGuard {
+    fn drop(&mut self) {
+        let mut updating = UPDATING_SHARDS.lock().unwrap_or_else(PoisonError::into_inner);
+        updating.retain(|p| *p != self.0);
+        UPDATED_SHARD.notify_all();
+    }
+}
+
+/// An open file whose lock is released when this is dropped.
+pub(crate) struct LockedFile {
+    file: File,
+    _lock: Option<LockFileGuard>,
+}
+
+impl Deref for LockedFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Read for LockedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for LockedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for LockedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+
+/// Options for opening an `N5Filesystem`, created by `N5Filesystem::builder`.
+///
+/// ```
+/// use n5::filesystem::{LockMode, N5Filesystem};
+/// # let dir = tempdir::TempDir::new("n5_builder").unwrap();
+/// # let path = dir.path().to_str().unwrap();
+///
+/// let n5 = N5Filesystem::builder(path)
+///     .create(true)
+///     .lock_mode(LockMode::LockFile)
+///     .atomic_writes(true)
+///     .open()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct N5FilesystemBuilder {
     base_path: PathBuf,
+    create: bool,
+    lock_mode: LockMode,
+    lock_timeout: Duration,
+    stale_lock_age: Option<Duration>,
+    read_only: bool,
+    atomic_writes: bool,
 }
 
-impl N5Filesystem {
-    /// Open an existing N5 container by path.
-    pub fn open(base_path: &str) -> Result<N5Filesystem> {
-        let reader = N5Filesystem {
-            base_path: PathBuf::from(base_path),
+impl N5FilesystemBuilder {
+    /// Create the container if none exists, as with
+    /// `N5Filesystem::open_or_create`. Disabled by default.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Set how files are locked. `LockMode::Advisory` by default.
+    pub fn lock_mode(mut self, lock_mode: LockMode) -> Self {
+        self.lock_mode = lock_mode;
+        self
+    }
+
+    /// Set how long to wait for a lock file held by another process before
+    /// failing with an `ErrorKind::TimedOut` IO error. One minute by default.
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Remove lock files last modified longer ago than this, as left behind
+    /// by a crashed process, rather than waiting for them. This must be
+    /// longer than any read or write holds a lock. Disabled by default.
+    pub fn stale_lock_age(mut self, stale_lock_age: Option<Duration>) -> Self {
+        self.stale_lock_age = stale_lock_age;
+        self
+    }
+
+    /// Refuse all `N5Writer` calls with `N5Error::ReadOnly`. Disabled by
+    /// default.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Write blocks atomically; see `N5Filesystem::with_atomic_writes`.
+    /// Disabled by default.
+    pub fn atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// Open the container. Creating a container is an error if it is also
+    /// read-only.
+    pub fn open(self) -> Result<N5Filesystem> {
+        let create = self.create;
+        let n5 = N5Filesystem {
+            base_path: self.base_path,
+            locks: FileLocks {
+                timeout: self.lock_timeout,
+                stale_age: self.stale_lock_age,
+                ..FileLocks::new(self.lock_mode, self.read_only)
+            },
+            read_only: self.read_only,
+            atomic_writes: self.atomic_writes,
         };
 
-        if reader.exists("")? {
-            let version = reader.get_version()?;
+        if create {
+            n5.check_writable("")?;
+            fs::create_dir_all(&n5.base_path)?;
+
+            match n5.get_version() {
+                Ok(version) if !is_version_compatible(&crate::VERSION, &version) =>
+                    return Err(N5Error::IncompatibleVersion(version)),
+                _ => n5.set_attribute("", crate::VERSION_ATTRIBUTE_KEY.to_owned(), crate::VERSION.to_string())?,
+            }
+        } else if n5.exists("")? {
+            let version = n5.get_version()?;
 
             if !is_version_compatible(&crate::VERSION, &version) {
-                return Err(Error::new(ErrorKind::Other, "TODO: Incompatible version"))
+                return Err(N5Error::IncompatibleVersion(version))
             }
         }
 
-        Ok(reader)
+        Ok(n5)
+    }
+}
+
+
+/// A filesystem-backed N5 container.
+#[derive(Clone)]
+pub struct N5Filesystem {
+    base_path: PathBuf,
+    locks: FileLocks,
+    read_only: bool,
+    atomic_writes: bool,
+}
+
+impl N5Filesystem {
+    /// Open an existing N5 container by path.
+    pub fn open(base_path: &str) -> Result<N5Filesystem> {
+        N5Filesystem::builder(base_path).open()
     }
 
     /// Open an existing N5 container by path or create one if none exists.
     ///
     /// Note this will update the version attribute for existing containers.
     pub fn open_or_create(base_path: &str) -> Result<N5Filesystem> {
-        let reader = N5Filesystem {
-            base_path: PathBuf::from(base_path),
-        };
-
-        fs::create_dir_all(base_path)?;
+        N5Filesystem::builder(base_path).create(true).open()
+    }
 
-        if reader.get_version().map(|v| !is_version_compatible(&crate::VERSION, &v)).unwrap_or(false) {
-            return Err(Error::new(ErrorKind::Other, "TODO: Incompatible version"))
-        } else {
-            reader.set_attribute("", crate::VERSION_ATTRIBUTE_KEY.to_owned(), crate::VERSION.to_string())?;
+    /// Configure how to open an N5 container by path.
+    pub fn builder(base_path: &str) -> N5FilesystemBuilder {
+        N5FilesystemBuilder {
+            base_path: PathBuf::from(base_path),
+            create: false,
+            lock_mode: LockMode::default(),
+            lock_timeout: FileLocks::DEFAULT_TIMEOUT,
+            stale_lock_age: None,
+            read_only: false,
+            atomic_writes: false,
         }
+    }
 
-        Ok(reader)
+    /// Write blocks atomically: each block, or each shard when a block in it
+    /// is written, is encoded to a temporary file beside its target, synced
+    /// to disk, and renamed over the target. Readers then never observe
+    /// partially written blocks, even where file locks are not effective,
+    /// such as on NFS. Disabled by default.
+    ///
+    /// Without atomic writes, writing a block in a shard appends it to the
+    /// shard and rewrites the shard's index, rather than the whole shard.
+    pub fn with_atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
     }
 
     pub fn get_attributes(&self, path_name: &str) -> Result<Value> {
         if self.exists(path_name)? {
-            let attr_path = self.base_path.join(path_name).join(ATTRIBUTES_FILE);
+            let attr_path = self.get_attributes_path(path_name)?;
 
             if attr_path.exists() && attr_path.is_file() {
-                let file = File::open(attr_path)?;
-                file.lock_shared()?;
+                let file = self.locks.open(&attr_path, fs::OpenOptions::new().read(true), false)?;
                 let reader = BufReader::new(file);
                 Ok(serde_json::from_reader(reader)?)
             } else {
                 Ok(json!({}))
             }
         } else {
-            Err(Error::new(ErrorKind::NotFound, "Path does not exist"))
+            Err(N5Error::PathNotFound(path_name.to_owned()))
         }
     }
 
-    fn get_path(&self, path_name: &str) -> Result<PathBuf> {
-        // Note: cannot use `canonicalize` on both the constructed dataset path
-        // and `base_path` and check `starts_with`, because `canonicalize` also
-        // requires the path exist.
-        use std::path::Component;
-
-        // TODO: cleanup?
-        let data_path = PathBuf::from(path_name);
-        if data_path.is_relative() {
-            let mut nest: i32 = 0;
-            let mut interior = true;
-            for component in data_path.components() {
-                match component {
-                    Component::Prefix(_) => unreachable!(), // Not an absolute path.
-                    Component::RootDir => unreachable!(), // Not an absolute path.
-                    Component::CurDir => continue,
-                    Component::ParentDir => nest -= 1,
-                    Component::Normal(_) => nest += 1,
-                };
-
-                if nest < 0 {
-                    interior = false
-                }
-            }
+    /// Set a nested attribute by a JSON-pointer-style path, creating
+    /// intermediate objects as needed. See [`attributes`](crate::attributes)
+    /// for the path syntax.
+    ///
+    /// Like `set_attributes`, this holds an exclusive lock on the attributes
+    /// file while it is rewritten.
+    pub fn set_nested_attribute<T: Serialize>(
+        &self,
+        path_name: &str,
+        attribute_path: &str,
+        attribute: T,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        let attribute = serde_json::to_value(attribute)?;
+        self.update_attributes(path_name, |attributes| {
+            attributes::set_nested(attributes, attribute_path, attribute)
+        })?
+    }
 
-            if interior {
-                return Ok(self.base_path.join(path_name))
-            }
+    /// Remove a nested attribute by a JSON-pointer-style path, returning it
+    /// if it existed.
+    pub fn remove_nested_attribute(
+        &self,
+        path_name: &str,
+        attribute_path: &str,
+    ) -> Result<Option<Value>> {
+        self.check_writable(path_name)?;
+        if !self.has_attributes_file(path_name)? {
+            return Ok(None);
         }
+        self.update_attributes(path_name, |attributes| {
+            attributes::remove_nested(attributes, attribute_path)
+        })
+    }
+
+    /// Refuse to modify a read-only container.
+    fn check_writable(&self, path_name: &str) -> Result<()> {
+        if self.read_only {
+            Err(N5Error::ReadOnly(path_name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
 
-        Err(Error::new(ErrorKind::NotFound, "Path name is outside this N5 filesystem"))
+    /// Whether a group has an attributes file, so that removing attributes
+    /// does not create one.
+    fn has_attributes_file(&self, path_name: &str) -> Result<bool> {
+        if self.get_attributes_path(path_name)?.is_file() {
+            Ok(true)
+        } else if self.exists(path_name)? {
+            Ok(false)
+        } else {
+            Err(N5Error::PathNotFound(path_name.to_owned()))
+        }
+    }
+
+    /// Modify a group's attributes while holding an exclusive lock on its
+    /// attributes file. The file is only rewritten if they changed.
+    fn update_attributes<R>(
+        &self,
+        path_name: &str,
+        update: impl FnOnce(&mut Value) -> R,
+    ) -> Result<R> {
+        let mut file = self.locks.open(
+            &self.get_attributes_path(path_name)?,
+            fs::OpenOptions::new().read(true).write(true).create(true).truncate(false),
+            true,
+        )?;
+
+        let mut existing_buf = String::new();
+        file.read_to_string(&mut existing_buf)?;
+        let existing = serde_json::from_str(&existing_buf).unwrap_or_else(|_| json!({}));
+        let mut updated = existing.clone();
+
+        let result = update(&mut updated);
+
+        if updated != existing {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            let writer = BufWriter::new(file);
+            serde_json::to_writer(writer, &updated)?;
+        }
+
+        Ok(result)
+    }
+
+    fn get_path(&self, path_name: &str) -> Result<PathBuf> {
+        resolve_path(&self.base_path, path_name)
     }
 
     fn get_data_block_path(&self, path_name: &str, grid_position: &[u64]) -> Result<PathBuf> {
@@ -157,35 +595,198 @@ impl N5Filesystem {
         path.push(ATTRIBUTES_FILE);
         Ok(path)
     }
+
+    /// Open a block for reading under a shared lock, limited to the block's
+    /// bytes if it is stored in a shard.
+    fn open_block(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<Take<BufReader<LockedFile>>>> {
+        let position = shard::locate(data_attrs, grid_position)?;
+        let file_position = position.as_ref().map_or(grid_position, |p| &p.shard);
+        let block_file = self.get_data_block_path(path_name, file_position)?;
+        if !block_file.is_file() {
+            return Ok(None);
+        }
+
+        let file = self.locks.open(&block_file, fs::OpenOptions::new().read(true), false)?;
+        let mut reader = BufReader::new(file);
+        match position {
+            Some(position) => {
+                match ShardIndex::read(&mut reader, data_attrs)?.get(position.index) {
+                    Some((offset, length)) => {
+                        reader.seek(SeekFrom::Start(offset))?;
+                        Ok(Some(reader.take(length)))
+                    },
+                    None => Ok(None),
+                }
+            },
+            None => Ok(Some(reader.take(u64::MAX))),
+        }
+    }
+
+    /// Write an encoded block, produced by `write`, to its block file under
+    /// an exclusive lock or atomically, or to its shard.
+    fn write_encoded_block<F>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+        write: F,
+    ) -> Result<()>
+            where F: FnOnce(&mut dyn Write) -> Result<()> {
+        self.check_writable(path_name)?;
+        if let Some(position) = shard::locate(data_attrs, grid_position)? {
+            let mut buffer = Vec::new();
+            write(&mut buffer)?;
+            return self.update_shard(path_name, data_attrs, &position, Some(buffer));
+        }
+
+        let path = self.get_data_block_path(path_name, grid_position)?;
+        fs::create_dir_all(block_dir(&path)?)?;
+
+        if self.atomic_writes {
+            return write_atomic(&path, |writer| write(writer));
+        }
+
+        let file = self.locks.open(
+            &path,
+            fs::OpenOptions::new().read(true).write(true).create(true).truncate(false),
+            true,
+        )?;
+        // Truncate after the lock is acquired, rather than on opening.
+        file.set_len(0)?;
+
+        write(&mut BufWriter::new(file))
+    }
+
+    /// Replace or remove an encoded block in its shard under an exclusive
+    /// lock, removing the shard if it no longer has any blocks. Updates of a
+    /// shard by this process are serialized regardless of the lock mode.
+    fn update_shard(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        position: &ShardPosition,
+        block: Option<Vec<u8>>,
+    ) -> Result<()> {
+        let path = self.get_data_block_path(path_name, &position.shard)?;
+        if block.is_none() && !path.is_file() {
+            return Ok(());
+        }
+        fs::create_dir_all(block_dir(&path)?)?;
+        let _updating = ShardUpdateGuard::acquire(&path);
+
+        // Another writer may replace or remove the shard while this one waits
+        // for the lock, in which case the lock is on a stale file.
+        let mut file = loop {
+            let file = self.locks.open(
+                &path,
+                fs::OpenOptions::new().read(true).write(true).create(true).truncate(false),
+                true,
+            )?;
+            match Handle::from_path(&path) {
+                Ok(current) if current == Handle::from_file(file.try_clone()?)? => break file,
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        if self.atomic_writes {
+            let updated = {
+                let mut reader = BufReader::new(&*file);
+                let existing = if file.metadata()?.len() > 0 {Some(&mut reader)} else {None};
+                shard::update_shard(existing, data_attrs, position.index, block)?
+            };
+            match updated {
+                Some(updated) => write_atomic(&path, |writer| Ok(writer.write_all(&updated)?))?,
+                None => fs::remove_file(&path)?,
+            }
+            return Ok(());
+        }
+
+        match shard::update_shard_in_place(&mut file, data_attrs, position.index, block)? {
+            ShardUpdate::Written => {},
+            ShardUpdate::Compacted(compacted) => {
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(&compacted)?;
+            },
+            ShardUpdate::Empty => fs::remove_file(&path)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolve a path name relative to a container base path, rejecting path
+/// names outside the container.
+pub(crate) fn resolve_path(base_path: &Path, path_name: &str) -> Result<PathBuf> {
+    // Note: cannot use `canonicalize` on both the constructed dataset path
+    // and `base_path` and check `starts_with`, because `canonicalize` also
+    // requires the path exist.
+    use std::path::Component;
+
+    // TODO: cleanup?
+    let data_path = PathBuf::from(path_name);
+    if data_path.is_relative() {
+        let mut nest: i32 = 0;
+        let mut interior = true;
+        for component in data_path.components() {
+            match component {
+                Component::Prefix(_) => unreachable!(), // Not an absolute path.
+                Component::RootDir => unreachable!(), // Not an absolute path.
+                Component::CurDir => continue,
+                Component::ParentDir => nest -= 1,
+                Component::Normal(_) => nest += 1,
+            };
+
+            if nest < 0 {
+                interior = false
+            }
+        }
+
+        if interior {
+            return Ok(base_path.join(path_name))
+        }
+    }
+
+    Err(N5Error::PathEscape(path_name.to_owned()))
 }
 
 impl N5Reader for N5Filesystem {
     fn get_version(&self) -> Result<Version> {
-        // TODO: dedicated error type should clean this up.
-        Ok(Version::from_str(self
-                .get_attributes("")?
-                .get(crate::VERSION_ATTRIBUTE_KEY)
-                    .ok_or_else(|| Error::new(ErrorKind::NotFound, "Version attribute not present"))?
-                .as_str().unwrap_or("")
-            ).unwrap())
+        let attrs = self.get_attributes("")?;
+        let version = attrs
+            .get(crate::VERSION_ATTRIBUTE_KEY)
+                .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+            .as_str().unwrap_or("");
+        Version::from_str(version)
+            .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
     }
 
     fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
         let attr_path = self.get_attributes_path(path_name)?;
-        let reader = BufReader::new(File::open(attr_path)?);
+        let file = File::open(attr_path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => N5Error::DatasetNotFound(path_name.to_owned()),
+            _ => e.into(),
+        })?;
+        let reader = BufReader::new(file);
         Ok(serde_json::from_reader(reader)?)
     }
 
     fn exists(&self, path_name: &str) -> Result<bool> {
-        let target = self.base_path.join(path_name);
-        Ok(target.is_dir())
+        Ok(self.get_path(path_name)?.is_dir())
     }
 
     fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
         self.get_data_block_path(path_name, grid_position)?.to_str()
             // TODO: could use URL crate and `from_file_path` here.
             .map(|s| format!("file://{}", s))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Paths must be UTF-8"))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Paths must be UTF-8").into())
     }
 
     fn read_block<T>(
@@ -196,17 +797,12 @@ impl N5Reader for N5Filesystem {
     ) -> Result<Option<VecDataBlock<T>>>
             where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
                   T: ReflectedType {
-        let block_file = self.get_data_block_path(path_name, &grid_position)?;
-        if block_file.is_file() {
-            let file = File::open(block_file)?;
-            file.lock_shared()?;
-            let reader = BufReader::new(file);
-            Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+        match self.open_block(path_name, data_attrs, &grid_position)? {
+            Some(reader) => Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
                 reader,
                 data_attrs,
-                grid_position)?))
-        } else {
-            Ok(None)
+                grid_position)?)),
+            None => Ok(None),
         }
     }
 
@@ -217,47 +813,65 @@ impl N5Reader for N5Filesystem {
         grid_position: GridCoord,
         block: &mut B,
     ) -> Result<Option<()>> {
-        let block_file = self.get_data_block_path(path_name, &grid_position)?;
-        if block_file.is_file() {
-            let file = File::open(block_file)?;
-            file.lock_shared()?;
-            let reader = BufReader::new(file);
-            <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block_into(
-                reader,
-                data_attrs,
-                grid_position,
-                block)?;
-            Ok(Some(()))
-        } else {
-            Ok(None)
+        match self.open_block(path_name, data_attrs, &grid_position)? {
+            Some(reader) => {
+                <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block_into(
+                    reader,
+                    data_attrs,
+                    grid_position,
+                    block)?;
+                Ok(Some(()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.open_block(path_name, data_attrs, &grid_position)? {
+            Some(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                Ok(Some(data))
+            },
+            None => Ok(None),
         }
     }
 
     fn block_metadata(
         &self,
         path_name: &str,
-        _data_attrs: &DatasetAttributes,
+        data_attrs: &DatasetAttributes,
         grid_position: &[u64],
     ) -> Result<Option<DataBlockMetadata>> {
-        let block_file = self.get_data_block_path(path_name, grid_position)?;
-        if block_file.is_file() {
-            let metadata = std::fs::metadata(block_file)?;
-            Ok(Some(DataBlockMetadata {
-                created: metadata.created().ok(),
-                accessed: metadata.accessed().ok(),
-                modified: metadata.modified().ok(),
-                size: Some(metadata.len()),
-            }))
-        } else {
-            Ok(None)
+        // Blocks in a shard share the shard file's times.
+        match self.open_block(path_name, data_attrs, grid_position)? {
+            Some(reader) => {
+                let metadata = reader.get_ref().get_ref().metadata()?;
+                Ok(Some(DataBlockMetadata {
+                    created: metadata.created().ok(),
+                    accessed: metadata.accessed().ok(),
+                    modified: metadata.modified().ok(),
+                    size: Some(reader.limit().min(metadata.len())),
+                }))
+            },
+            None => Ok(None),
         }
     }
 
     // TODO: dupe with get_attributes w/ different empty behaviors
     fn list_attributes(&self, path_name: &str) -> Result<Value> {
         let attr_path = self.get_attributes_path(path_name)?;
-        let file = File::open(attr_path)?;
-        file.lock_shared()?;
+        let file = match self.locks.open(&attr_path, fs::OpenOptions::new().read(true), false) {
+            Ok(file) => file,
+            Err(N5Error::Io(ref e)) if e.kind() == ErrorKind::NotFound =>
+                return Err(N5Error::missing_attributes(path_name, self.exists(path_name)?)),
+            Err(e) => return Err(e),
+        };
         let reader = BufReader::new(file);
         Ok(serde_json::from_reader(reader)?)
     }
@@ -283,7 +897,134 @@ impl N5Lister for N5Filesystem {
     }
 }
 
-fn merge_top_level(a: &mut Value, b: serde_json::Map<String, Value>) {
+impl N5BlockLister for N5Filesystem {
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>> {
+        let data_attrs = self.get_dataset_attributes(path_name)?;
+        let mut files = Vec::new();
+        let mut coord = GridCoord::new();
+        list_block_files(&self.get_path(path_name)?, data_attrs.get_ndim(), &mut coord, &mut files)?;
+        if data_attrs.get_shard_size().is_none() {
+            return Ok(files);
+        }
+
+        let mut blocks = Vec::new();
+        for shard_position in files {
+            let file = self.locks.open(
+                &self.get_data_block_path(path_name, &shard_position)?,
+                fs::OpenOptions::new().read(true),
+                false,
+            )?;
+            let index = ShardIndex::read(&mut BufReader::new(file), &data_attrs)?;
+            blocks.extend(shard::block_positions(&data_attrs, &shard_position, index.block_indices())?
+                .unwrap_or_default());
+        }
+        Ok(blocks)
+    }
+}
+
+/// Recursively collect block grid positions from the numeric directory tree
+/// of a dataset, skipping anything that is not a block.
+fn list_block_files(
+    path: &Path,
+    depth: usize,
+    coord: &mut GridCoord,
+    blocks: &mut Vec<GridCoord>,
+) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let index = match entry.file_name().to_str().and_then(|n| u64::from_str(n).ok()) {
+            Some(index) => index,
+            None => continue,
+        };
+        let file_type = entry.file_type()?;
+
+        coord.push(index);
+        if depth == 1 {
+            if file_type.is_file() {
+                blocks.push(coord.clone());
+            }
+        } else if file_type.is_dir() {
+            list_block_files(&entry.path(), depth - 1, coord, blocks)?;
+        }
+        coord.pop();
+    }
+
+    Ok(())
+}
+
+/// Remove a directory and all its contents, waiting on file locks held by
+/// other readers and writers.
+pub(crate) fn remove_dir_locked(path: &Path, locks: FileLocks) -> Result<()> {
+    for entry in WalkDir::new(path).contents_first(true) {
+        let entry = entry.map_err(Error::from)?;
+
+        if entry.file_type().is_dir() {
+            fs::remove_dir(entry.path())?;
+        } else if locks.mode == LockMode::LockFile
+                && entry.path().extension().is_some_and(|extension| extension == "lock") {
+            // Lock files are removed by their holders.
+            continue;
+        } else {
+            let _file = locks.open(entry.path(), fs::OpenOptions::new().read(true), true)?;
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory containing a block or shard file, which has none only if the
+/// file would be a filesystem root.
+fn block_dir(path: &Path) -> Result<&Path> {
+    path.parent().ok_or_else(|| N5Error::PathNotFound(path.display().to_string()))
+}
+
+/// Write a file atomically by writing a temporary file beside it, syncing it,
+/// and renaming it over the target. The temporary file is removed if writing
+/// fails or panics.
+pub(crate) fn write_atomic<F>(path: &Path, write: F) -> Result<()>
+        where F: FnOnce(&mut BufWriter<&File>) -> Result<()> {
+    /// Removes the temporary file unless it has been renamed.
+    struct TempPath(Option<PathBuf>);
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            if let Some(path) = self.0.take() {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    let file_name = path.file_name().expect("Atomic write target has no file name");
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let mut temp_path = TempPath(Some(temp_path));
+    let temp = temp_path.0.as_ref().expect("Temporary path was just set");
+
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)?;
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+
+    fs::rename(temp, path)?;
+    temp_path.0 = None;
+
+    // Sync the directory so that the rename itself is durable.
+    #[cfg(unix)]
+    File::open(path.parent().expect("Atomic write target has no parent"))?.sync_all()?;
+
+    Ok(())
+}
+
+pub(crate) fn merge_top_level(a: &mut Value, b: serde_json::Map<String, Value>) {
     match a {
         &mut Value::Object(ref mut a) => {
             for (k, v) in b {
@@ -302,54 +1043,37 @@ impl N5Writer for N5Filesystem {
         path_name: &str,
         attributes: serde_json::Map<String, Value>,
     ) -> Result<()> {
-        let mut file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(self.get_attributes_path(path_name)?)?;
-        file.lock_exclusive()?;
-
-        let mut existing_buf = String::new();
-        file.read_to_string(&mut existing_buf)?;
-        let existing = serde_json::from_str(&existing_buf).unwrap_or_else(|_| json!({}));
-        let mut merged = existing.clone();
-
-        merge_top_level(&mut merged, attributes);
+        self.check_writable(path_name)?;
+        self.update_attributes(path_name, |existing| merge_top_level(existing, attributes))
+    }
 
-        if merged != existing {
-            file.set_len(0)?;
-            file.seek(SeekFrom::Start(0))?;
-            let writer = BufWriter::new(file);
-            serde_json::to_writer(writer, &merged)?;
+    fn remove_attributes(
+        &self,
+        path_name: &str,
+        keys: &[&str],
+        force: bool,
+    ) -> Result<serde_json::Map<String, Value>> {
+        self.check_writable(path_name)?;
+        if !self.has_attributes_file(path_name)? {
+            return Ok(serde_json::Map::new());
         }
-
-        Ok(())
+        self.update_attributes(path_name, |existing| {
+            crate::remove_attribute_keys(path_name, existing, keys, force)
+        })?
     }
 
     fn create_group(&self, path_name: &str) -> Result<()> {
+        self.check_writable(path_name)?;
         let path = self.get_path(path_name)?;
-        fs::create_dir_all(path)
+        Ok(fs::create_dir_all(path)?)
     }
 
     fn remove(
         &self,
         path_name: &str,
     ) -> Result<()> {
-        let path = self.get_path(path_name)?;
-
-        for entry in WalkDir::new(path).contents_first(true) {
-            let entry = entry?;
-
-            if entry.file_type().is_dir() {
-                fs::remove_dir(entry.path())?;
-            } else {
-                let file = File::open(entry.path())?;
-                file.lock_exclusive()?;
-                fs::remove_file(entry.path())?;
-            }
-        }
-
-        Ok(())
+        self.check_writable(path_name)?;
+        remove_dir_locked(&self.get_path(path_name)?, self.locks)
     }
 
     fn write_block<T, B: DataBlock<T> + WriteableDataBlock>(
@@ -358,23 +1082,24 @@ impl N5Writer for N5Filesystem {
         data_attrs: &DatasetAttributes,
         block: &B,
     ) -> Result<()> {
-        let path = self.get_data_block_path(path_name, block.get_grid_position())?;
-        fs::create_dir_all(path.parent().expect("TODO: root block path?"))?;
-
-        let file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
-        file.lock_exclusive()?;
-        // Truncate after the lock is acquired, rather than on opening.
-        file.set_len(0)?;
+        self.write_encoded_block(path_name, data_attrs, block.get_grid_position(), |writer| {
+            <crate::DefaultBlock as DefaultBlockWriter<T, _, _>>::write_block(
+                    writer,
+                    data_attrs,
+                    block)
+        })
+    }
 
-        let buffer = BufWriter::new(file);
-        <crate::DefaultBlock as DefaultBlockWriter<T, _, _>>::write_block(
-                buffer,
-                data_attrs,
-                block)
+    fn write_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        data: &[u8],
+    ) -> Result<()> {
+        self.write_encoded_block(path_name, data_attrs, &grid_position, |writer| {
+            Ok(writer.write_all(data)?)
+        })
     }
 
     fn delete_block(
@@ -382,13 +1107,23 @@ impl N5Writer for N5Filesystem {
         path_name: &str,
         grid_position: &[u64],
     ) -> Result<bool> {
+        self.check_writable(path_name)?;
+        let data_attrs = match self.get_dataset_attributes(path_name) {
+            Ok(data_attrs) => Some(data_attrs),
+            Err(N5Error::DatasetNotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(data_attrs) = data_attrs {
+            if let Some(position) = shard::locate(&data_attrs, grid_position)? {
+                self.update_shard(path_name, &data_attrs, &position, None)?;
+                return Ok(true);
+            }
+        }
+
         let path = self.get_data_block_path(path_name, grid_position)?;
 
         if path.exists() {
-            let file = fs::OpenOptions::new()
-                .read(true)
-                .open(&path)?;
-            file.lock_exclusive()?;
+            let _file = self.locks.open(&path, fs::OpenOptions::new().read(true), true)?;
             fs::remove_file(&path)?;
         }
 
@@ -396,6 +1131,277 @@ impl N5Writer for N5Filesystem {
     }
 }
 
+#[cfg(feature = "async")]
+mod asynchronous {
+    use super::*;
+    use tokio::io::{
+        AsyncReadExt,
+        AsyncSeekExt,
+        AsyncWriteExt,
+    };
+
+    use crate::asynchronous::{
+        spawn_blocking,
+        to_owned_block,
+        N5AsyncLister,
+        N5AsyncReader,
+        N5AsyncWriter,
+    };
+
+    /// Open a file and lock it on the blocking thread pool. The lock is held
+    /// until both the file and the returned guard are dropped.
+    async fn open_locked(
+        locks: FileLocks,
+        path: PathBuf,
+        options: &fs::OpenOptions,
+        exclusive: bool,
+    ) -> Result<(tokio::fs::File, Option<LockFileGuard>)> {
+        let options = options.clone();
+        spawn_blocking(move || {
+            let LockedFile { file, _lock } = locks.open(&path, &options, exclusive)?;
+            Ok((tokio::fs::File::from_std(file), _lock))
+        }).await
+    }
+
+    async fn read_locked(locks: FileLocks, path: PathBuf) -> Result<Vec<u8>> {
+        let (mut file, _lock) = open_locked(locks, path, fs::OpenOptions::new().read(true), false).await?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await?;
+        Ok(buffer)
+    }
+
+    async fn is_file(path: &Path) -> bool {
+        tokio::fs::metadata(path).await.map(|m| m.is_file()).unwrap_or(false)
+    }
+
+    /// Blocks of sharded datasets are read and written by the synchronous
+    /// implementation on the blocking thread pool.
+    impl N5AsyncReader for N5Filesystem {
+        async fn get_version(&self) -> Result<Version> {
+            let attr_path = self.get_attributes_path("")?;
+            let attrs: Value = if is_file(&attr_path).await {
+                serde_json::from_slice(&read_locked(self.locks, attr_path).await?)?
+            } else {
+                json!({})
+            };
+            let version = attrs
+                .get(crate::VERSION_ATTRIBUTE_KEY)
+                    .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+                .as_str().unwrap_or("");
+            Version::from_str(version)
+                .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
+        }
+
+        async fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+            let attr_path = self.get_attributes_path(path_name)?;
+            let buffer = tokio::fs::read(attr_path).await.map_err(|e| match e.kind() {
+                ErrorKind::NotFound => N5Error::DatasetNotFound(path_name.to_owned()),
+                _ => e.into(),
+            })?;
+            Ok(serde_json::from_slice(&buffer)?)
+        }
+
+        async fn exists(&self, path_name: &str) -> Result<bool> {
+            let path = self.get_path(path_name)?;
+            Ok(tokio::fs::metadata(path).await.map(|m| m.is_dir()).unwrap_or(false))
+        }
+
+        fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+            N5Reader::get_block_uri(self, path_name, grid_position)
+        }
+
+        async fn read_block<T>(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            grid_position: GridCoord,
+        ) -> Result<Option<VecDataBlock<T>>>
+                where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                      T: ReflectedType {
+            if data_attrs.get_shard_size().is_some() {
+                let (n5, path_name, data_attrs) = (self.clone(), path_name.to_owned(), data_attrs.clone());
+                return spawn_blocking(move || N5Reader::read_block(&n5, &path_name, &data_attrs, grid_position))
+                    .await;
+            }
+
+            let block_file = self.get_data_block_path(path_name, &grid_position)?;
+            if !is_file(&block_file).await {
+                return Ok(None);
+            }
+            let buffer = read_locked(self.locks, block_file).await?;
+            Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+                &buffer[..],
+                data_attrs,
+                grid_position)?))
+        }
+
+        async fn block_metadata(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            grid_position: &[u64],
+        ) -> Result<Option<DataBlockMetadata>> {
+            if data_attrs.get_shard_size().is_some() {
+                let (n5, path_name, data_attrs) = (self.clone(), path_name.to_owned(), data_attrs.clone());
+                let grid_position = grid_position.to_vec();
+                return spawn_blocking(move || N5Reader::block_metadata(&n5, &path_name, &data_attrs, &grid_position))
+                    .await;
+            }
+
+            let block_file = self.get_data_block_path(path_name, grid_position)?;
+            match tokio::fs::metadata(block_file).await {
+                Ok(metadata) if metadata.is_file() => Ok(Some(DataBlockMetadata {
+                    created: metadata.created().ok(),
+                    accessed: metadata.accessed().ok(),
+                    modified: metadata.modified().ok(),
+                    size: Some(metadata.len()),
+                })),
+                _ => Ok(None),
+            }
+        }
+
+        async fn list_attributes(&self, path_name: &str) -> Result<Value> {
+            let attr_path = self.get_attributes_path(path_name)?;
+            match read_locked(self.locks, attr_path).await {
+                Ok(data) => Ok(serde_json::from_slice(&data)?),
+                Err(N5Error::Io(ref e)) if e.kind() == ErrorKind::NotFound =>
+                    Err(N5Error::missing_attributes(path_name, N5AsyncReader::exists(self, path_name).await?)),
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    impl N5AsyncLister for N5Filesystem {
+        async fn list(&self, path_name: &str) -> Result<Vec<String>> {
+            let mut entries = tokio::fs::read_dir(self.get_path(path_name)?).await?;
+            let mut groups = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    if let Ok(name) = entry.file_name().into_string() {
+                        groups.push(name);
+                    }
+                }
+            }
+            Ok(groups)
+        }
+    }
+
+    impl N5AsyncWriter for N5Filesystem {
+        async fn set_attributes(
+            &self,
+            path_name: &str,
+            attributes: serde_json::Map<String, Value>,
+        ) -> Result<()> {
+            self.check_writable(path_name)?;
+            let (mut file, _lock) = open_locked(
+                self.locks,
+                self.get_attributes_path(path_name)?,
+                fs::OpenOptions::new().read(true).write(true).create(true).truncate(false),
+                true,
+            ).await?;
+
+            let mut existing_buf = String::new();
+            file.read_to_string(&mut existing_buf).await?;
+            let existing = serde_json::from_str(&existing_buf).unwrap_or_else(|_| json!({}));
+            let mut merged = existing.clone();
+
+            merge_top_level(&mut merged, attributes);
+
+            if merged != existing {
+                file.set_len(0).await?;
+                file.seek(SeekFrom::Start(0)).await?;
+                file.write_all(&serde_json::to_vec(&merged)?).await?;
+                file.flush().await?;
+            }
+
+            Ok(())
+        }
+
+        async fn create_group(&self, path_name: &str) -> Result<()> {
+            self.check_writable(path_name)?;
+            let path = self.get_path(path_name)?;
+            Ok(tokio::fs::create_dir_all(path).await?)
+        }
+
+        async fn remove(&self, path_name: &str) -> Result<()> {
+            self.check_writable(path_name)?;
+            let (path, locks) = (self.get_path(path_name)?, self.locks);
+            spawn_blocking(move || remove_dir_locked(&path, locks)).await
+        }
+
+        async fn write_block<T, B>(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            block: &B,
+        ) -> Result<()>
+                where VecDataBlock<T>: DataBlock<T> + WriteableDataBlock,
+                      B: DataBlock<T> + WriteableDataBlock + Sync,
+                      T: ReflectedType {
+            self.check_writable(path_name)?;
+            if data_attrs.get_shard_size().is_some() {
+                let (n5, path_name, data_attrs) = (self.clone(), path_name.to_owned(), data_attrs.clone());
+                let block = to_owned_block(block);
+                return spawn_blocking(move || N5Writer::write_block(&n5, &path_name, &data_attrs, &block)).await;
+            }
+
+            let path = self.get_data_block_path(path_name, block.get_grid_position())?;
+            // Encode on the blocking thread pool, since compression is CPU-bound.
+            let (data_attrs, block) = (data_attrs.clone(), to_owned_block(block));
+            let buffer = spawn_blocking(move || {
+                let mut buffer = Vec::new();
+                <crate::DefaultBlock as DefaultBlockWriter<T, _, _>>::write_block(
+                        &mut buffer,
+                        &data_attrs,
+                        &block)?;
+                Ok(buffer)
+            }).await?;
+
+            tokio::fs::create_dir_all(block_dir(&path)?).await?;
+            if self.atomic_writes {
+                return spawn_blocking(move || write_atomic(&path, |writer| Ok(writer.write_all(&buffer)?))).await;
+            }
+            let (mut file, _lock) = open_locked(
+                self.locks,
+                path,
+                fs::OpenOptions::new().read(true).write(true).create(true).truncate(false),
+                true,
+            ).await?;
+            // Truncate after the lock is acquired, rather than on opening.
+            file.set_len(0).await?;
+            file.write_all(&buffer).await?;
+            file.flush().await?;
+
+            Ok(())
+        }
+
+        async fn delete_block(
+            &self,
+            path_name: &str,
+            grid_position: &[u64],
+        ) -> Result<bool> {
+            self.check_writable(path_name)?;
+            let sharded = match N5AsyncReader::get_dataset_attributes(self, path_name).await {
+                Ok(data_attrs) => data_attrs.get_shard_size().is_some(),
+                Err(N5Error::DatasetNotFound(_)) => false,
+                Err(e) => return Err(e),
+            };
+            if sharded {
+                let (n5, path_name, grid_position) = (self.clone(), path_name.to_owned(), grid_position.to_vec());
+                return spawn_blocking(move || N5Writer::delete_block(&n5, &path_name, &grid_position)).await;
+            }
+
+            let path = self.get_data_block_path(path_name, grid_position)?;
+            if tokio::fs::metadata(&path).await.is_ok() {
+                let _file = open_locked(self.locks, path.clone(), fs::OpenOptions::new().read(true), true).await?;
+                tokio::fs::remove_file(&path).await?;
+            }
+
+            Ok(tokio::fs::metadata(&path).await.is_err())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -427,6 +1433,11 @@ mod tests {
 
     test_backend!(N5Filesystem);
 
+    #[test]
+    fn list_blocks() {
+        crate::tests::list_blocks::<N5Filesystem>()
+    }
+
     #[test]
     fn reject_exterior_paths() {
         let wrapper = N5Filesystem::temp_new_rw();
@@ -438,6 +1449,22 @@ mod tests {
         assert!(create.get_path("foo/bar/baz/../../../..").is_err());
     }
 
+    #[test]
+    fn reject_incompatible_version() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let incompatible = Version::new(crate::VERSION.major + 1, 0, 0);
+        create.set_attribute("", crate::VERSION_ATTRIBUTE_KEY.to_owned(), incompatible.to_string())
+            .unwrap();
+
+        let path_str = create.base_path.to_str().unwrap();
+        match N5Filesystem::open(path_str) {
+            Err(N5Error::IncompatibleVersion(version)) => assert_eq!(version, incompatible),
+            _ => panic!("Expected IncompatibleVersion"),
+        }
+        assert!(matches!(N5Filesystem::open_or_create(path_str), Err(N5Error::IncompatibleVersion(_))));
+    }
+
     #[test]
     fn test_get_block_uri() {
         let dir = TempDir::new("rust_n5_tests").unwrap();
@@ -499,4 +1526,502 @@ mod tests {
             (header_len + block_data.len() * std::mem::size_of::<i32>()) as u64);
 
     }
+
+    #[test]
+    fn sharded_blocks() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10, 10],
+            smallvec![2, 2, 2],
+            crate::DataType::INT32,
+            crate::compression::CompressionType::default(),
+        ).with_shard_size(smallvec![4, 4, 10]);
+        create.create_dataset("foo/bar", &data_attrs)
+            .expect("Failed to create dataset");
+        assert_eq!(create.get_dataset_attributes("foo/bar").unwrap(), data_attrs);
+
+        let mut coords: Vec<GridCoord> = vec![
+            smallvec![0, 0, 0],
+            smallvec![1, 0, 0],
+            smallvec![1, 1, 4],
+            smallvec![2, 1, 3],
+        ];
+        for (i, coord) in coords.iter().enumerate() {
+            let block_data: Vec<i32> = (0..8).map(|v| v + 10 * i as i32).collect();
+            let block_in = crate::SliceDataBlock::new(
+                data_attrs.block_size.clone(),
+                coord.clone(),
+                &block_data);
+            create.write_block("foo/bar", &data_attrs, &block_in)
+                .expect("Failed to write block");
+        }
+
+        // Blocks share shard files rather than having one file each.
+        assert!(create.get_data_block_path("foo/bar", &[0, 0, 0]).unwrap().is_file());
+        assert!(create.get_data_block_path("foo/bar", &[1, 0, 0]).unwrap().is_file());
+        assert!(!create.get_data_block_path("foo/bar", &[2, 1, 3]).unwrap().exists());
+
+        let read = create.open_reader();
+        for (i, coord) in coords.iter().enumerate() {
+            let block_data: Vec<i32> = (0..8).map(|v| v + 10 * i as i32).collect();
+            let block_out = read.read_block::<i32>("foo/bar", &data_attrs, coord.clone())
+                .expect("Failed to read block")
+                .expect("Block is empty");
+            assert_eq!(block_out.get_data(), &block_data[..]);
+        }
+        assert!(read.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 1, 0]).unwrap().is_none());
+        assert!(read.read_block::<i32>("foo/bar", &data_attrs, smallvec![4, 4, 4]).unwrap().is_none());
+
+        let metadata = read.block_metadata("foo/bar", &data_attrs, &[1, 1, 4]).unwrap().unwrap();
+        assert!(metadata.size.unwrap() > 0);
+        assert!(metadata.size.unwrap() < File::open(create.get_data_block_path("foo/bar", &[0, 0, 0]).unwrap())
+            .unwrap().metadata().unwrap().len());
+
+        let mut listed = read.list_blocks("foo/bar").unwrap();
+        listed.sort();
+        coords.sort();
+        assert_eq!(listed, coords);
+
+        assert!(create.delete_block("foo/bar", &[1, 0, 0]).unwrap());
+        assert!(create.delete_block("foo/bar", &[1, 0, 0]).unwrap());
+        assert!(read.read_block::<i32>("foo/bar", &data_attrs, smallvec![1, 0, 0]).unwrap().is_none());
+        assert!(read.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 0, 0]).unwrap().is_some());
+
+        // Removing the last block of a shard removes the shard.
+        assert!(create.delete_block("foo/bar", &[2, 1, 3]).unwrap());
+        assert!(!create.get_data_block_path("foo/bar", &[1, 0, 0]).unwrap().exists());
+    }
+
+    #[test]
+    fn read_shard_index_at_start() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            crate::DataType::UINT8,
+            crate::compression::CompressionType::Raw(crate::compression::raw::RawCompression),
+        ).with_shard_size(smallvec![4, 4])
+            .with_index_location(crate::shard::ShardIndexLocation::Start);
+        create.create_dataset("foo", &data_attrs)
+            .expect("Failed to create dataset");
+        assert_eq!(
+            create.get_attributes("foo").unwrap()["indexLocation"],
+            json!("start"));
+
+        let block_in = crate::SliceDataBlock::new(
+            data_attrs.block_size.clone(),
+            smallvec![1, 1],
+            &[1u8, 2, 3, 4][..]);
+        create.write_block("foo", &data_attrs, &block_in)
+            .expect("Failed to write block");
+
+        let shard = fs::read(create.get_data_block_path("foo", &[0, 0]).unwrap()).unwrap();
+        // The index is followed by the one block: a 2D raw header and data.
+        assert_eq!(shard.len(), 4 * 16 + 12 + 4);
+        assert_eq!(&shard[48..56], &[0, 0, 0, 0, 0, 0, 0, 64]);
+        assert_eq!(&shard[56..64], &[0, 0, 0, 0, 0, 0, 0, 16]);
+
+        let block_out = create.read_block::<u8>("foo", &data_attrs, smallvec![1, 1])
+            .expect("Failed to read block")
+            .expect("Block is empty");
+        assert_eq!(block_out.get_data(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn atomic_writes() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref().clone().with_atomic_writes(true);
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            crate::DataType::UINT8,
+            crate::compression::CompressionType::Raw(crate::compression::raw::RawCompression),
+        );
+        let sharded_attrs = data_attrs.clone().with_shard_size(smallvec![4, 4]);
+        create.create_dataset("foo", &data_attrs)
+            .expect("Failed to create dataset");
+        create.create_dataset("sharded", &sharded_attrs)
+            .expect("Failed to create dataset");
+
+        for (path_name, attrs) in [("foo", &data_attrs), ("sharded", &sharded_attrs)] {
+            for (grid_position, data) in [([0u64, 1], [1u8, 2, 3, 4]), ([1, 1], [5, 6, 7, 8]), ([0, 1], [9, 10, 11, 12])] {
+                let block_in = crate::SliceDataBlock::new(
+                    attrs.block_size.clone(),
+                    GridCoord::from_slice(&grid_position),
+                    &data[..]);
+                create.write_block(path_name, attrs, &block_in)
+                    .expect("Failed to write block");
+            }
+            let block_out = create.read_block::<u8>(path_name, attrs, smallvec![0, 1])
+                .expect("Failed to read block")
+                .expect("Block is empty");
+            assert_eq!(block_out.get_data(), &[9, 10, 11, 12]);
+            let mut blocks = create.list_blocks(path_name).unwrap();
+            blocks.sort();
+            let expected: Vec<GridCoord> = vec![smallvec![0, 1], smallvec![1, 1]];
+            assert_eq!(blocks, expected);
+        }
+
+        // A failed write leaves the existing block intact and removes its
+        // temporary file.
+        let path = create.get_data_block_path("foo", &[0, 1]).unwrap();
+        let existing = fs::read(&path).unwrap();
+        let result = write_atomic(&path, |writer| {
+            writer.write_all(&[0; 3])?;
+            Err(N5Error::MalformedHeader("Encoder failed".to_owned()))
+        });
+        assert!(matches!(result, Err(N5Error::MalformedHeader(_))));
+        assert_eq!(fs::read(&path).unwrap(), existing);
+        let temp_files = WalkDir::new(create.get_path("").unwrap()).into_iter()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(temp_files, 0);
+    }
+
+    #[test]
+    fn read_only() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            crate::DataType::UINT8,
+            crate::compression::CompressionType::Raw(crate::compression::raw::RawCompression),
+        );
+        create.create_dataset("foo", &data_attrs)
+            .expect("Failed to create dataset");
+        create.set_attribute("foo", "bar".to_owned(), 1)
+            .expect("Failed to set attribute");
+        let block_in = crate::SliceDataBlock::new(
+            data_attrs.block_size.clone(),
+            smallvec![0, 0],
+            &[1u8, 2, 3, 4][..]);
+        create.write_block("foo", &data_attrs, &block_in)
+            .expect("Failed to write block");
+
+        let base_path = create.base_path.to_str().unwrap();
+        assert!(matches!(
+            N5Filesystem::builder(base_path).create(true).read_only(true).open(),
+            Err(N5Error::ReadOnly(_))));
+
+        let read = N5Filesystem::builder(base_path)
+            .read_only(true)
+            .open()
+            .expect("Failed to open read-only container");
+        assert_eq!(read.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+        assert!(read.read_block::<u8>("foo", &data_attrs, smallvec![0, 0]).unwrap().is_some());
+
+        let is_read_only = |result: Result<_>| matches!(result, Err(N5Error::ReadOnly(_)));
+        assert!(is_read_only(read.set_attribute("foo", "bar".to_owned(), 2)));
+        assert!(is_read_only(read.remove_attribute("foo", "bar").map(|_| ())));
+        assert!(is_read_only(read.set_nested_attribute("foo", "baz/0", 2)));
+        assert!(is_read_only(read.remove_nested_attribute("foo", "bar").map(|_| ())));
+        assert!(is_read_only(read.create_group("baz")));
+        assert!(is_read_only(read.create_dataset("baz", &data_attrs)));
+        assert!(is_read_only(read.write_block("foo", &data_attrs, &block_in)));
+        assert!(is_read_only(read.delete_block("foo", &[0, 0]).map(|_| ())));
+        assert!(is_read_only(read.remove("foo")));
+        assert!(is_read_only(read.remove_all()));
+
+        assert_eq!(read.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+        assert!(read.read_block::<u8>("foo", &data_attrs, smallvec![0, 0]).unwrap().is_some());
+        assert!(!read.exists("baz").unwrap());
+    }
+
+    #[test]
+    fn lock_modes() {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            crate::DataType::UINT8,
+            crate::compression::CompressionType::Raw(crate::compression::raw::RawCompression),
+        );
+        let sharded_attrs = data_attrs.clone().with_shard_size(smallvec![4, 4]);
+
+        for lock_mode in [LockMode::None, LockMode::Advisory, LockMode::LockFile] {
+            let dir = TempDir::new("rust_n5_tests").unwrap();
+            let n5 = N5Filesystem::builder(dir.path().to_str().unwrap())
+                .create(true)
+                .lock_mode(lock_mode)
+                .open()
+                .expect("Failed to create N5 filesystem");
+
+            for (path_name, attrs) in [("foo", &data_attrs), ("sharded", &sharded_attrs)] {
+                n5.create_dataset(path_name, attrs)
+                    .expect("Failed to create dataset");
+                let block_in = crate::SliceDataBlock::new(
+                    attrs.block_size.clone(),
+                    smallvec![1, 0],
+                    &[1u8, 2, 3, 4][..]);
+                n5.write_block(path_name, attrs, &block_in)
+                    .expect("Failed to write block");
+                let block_out = n5.read_block::<u8>(path_name, attrs, smallvec![1, 0])
+                    .expect("Failed to read block")
+                    .expect("Block is empty");
+                assert_eq!(block_out.get_data(), &[1, 2, 3, 4]);
+                let expected: Vec<GridCoord> = vec![smallvec![1, 0]];
+                assert_eq!(n5.list_blocks(path_name).unwrap(), expected);
+                assert!(n5.delete_block(path_name, &[1, 0]).unwrap());
+                n5.set_attribute(path_name, "bar".to_owned(), 1)
+                    .expect("Failed to set attribute");
+                assert_eq!(n5.get_attribute::<u8>(path_name, "bar").unwrap(), 1);
+            }
+            n5.remove("sharded").expect("Failed to remove dataset");
+            assert!(!n5.exists("sharded").unwrap());
+
+            let lock_files = WalkDir::new(dir.path()).into_iter()
+                .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".lock"))
+                .count();
+            assert_eq!(lock_files, 0);
+        }
+    }
+
+    #[test]
+    fn block_dirs() {
+        assert_eq!(block_dir(Path::new("/foo/0")).unwrap(), Path::new("/foo"));
+        assert!(matches!(block_dir(Path::new("/")), Err(N5Error::PathNotFound(_))));
+    }
+
+    #[test]
+    fn parallel_shard_writes() {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![16, 16],
+            smallvec![2, 2],
+            crate::DataType::UINT8,
+            crate::compression::CompressionType::Raw(crate::compression::raw::RawCompression),
+        ).with_shard_size(smallvec![16, 16]);
+
+        for atomic_writes in [false, true] {
+            let dir = TempDir::new("rust_n5_tests").unwrap();
+            let n5 = N5Filesystem::builder(dir.path().to_str().unwrap())
+                .create(true)
+                .lock_mode(LockMode::None)
+                .atomic_writes(atomic_writes)
+                .open()
+                .expect("Failed to create N5 filesystem");
+            n5.create_dataset("sharded", &data_attrs)
+                .expect("Failed to create dataset");
+
+            let writers: Vec<_> = (0..8u8)
+                .map(|x| {
+                    let n5 = n5.clone();
+                    let data_attrs = data_attrs.clone();
+                    std::thread::spawn(move || for y in 0..8u8 {
+                        let block = crate::SliceDataBlock::new(
+                            data_attrs.block_size.clone(),
+                            smallvec![u64::from(x), u64::from(y)],
+                            [x, y, x, y]);
+                        n5.write_block("sharded", &data_attrs, &block)
+                            .expect("Failed to write block");
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+
+            for x in 0..8u8 {
+                for y in 0..8u8 {
+                    let block = n5.read_block::<u8>("sharded", &data_attrs, smallvec![u64::from(x), u64::from(y)])
+                        .expect("Failed to read block")
+                        .expect("Block is empty");
+                    assert_eq!(block.get_data(), &[x, y, x, y]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wait_for_lock_file() {
+        let dir = TempDir::new("rust_n5_tests").unwrap();
+        let n5 = N5Filesystem::builder(dir.path().to_str().unwrap())
+            .create(true)
+            .lock_mode(LockMode::LockFile)
+            .open()
+            .expect("Failed to create N5 filesystem");
+        n5.create_group("foo").expect("Failed to create group");
+
+        let attr_path = n5.get_attributes_path("foo").unwrap();
+        let lock = LockFileGuard::acquire(&attr_path, &n5.locks).unwrap();
+        assert!(LockFileGuard::lock_path(&attr_path).is_file());
+
+        let writer = {
+            let n5 = n5.clone();
+            std::thread::spawn(move || n5.set_attribute("foo", "bar".to_owned(), 1))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!attr_path.exists());
+
+        drop(lock);
+        writer.join().unwrap().expect("Failed to set attribute");
+        assert_eq!(n5.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+    }
+
+    #[test]
+    fn lock_file_timeout() {
+        let dir = TempDir::new("rust_n5_tests").unwrap();
+        let base_path = dir.path().to_str().unwrap();
+        let n5 = N5Filesystem::builder(base_path)
+            .create(true)
+            .lock_mode(LockMode::LockFile)
+            .lock_timeout(Duration::from_millis(20))
+            .open()
+            .expect("Failed to create N5 filesystem");
+        n5.create_group("foo").expect("Failed to create group");
+        n5.set_attribute("foo", "baz".to_owned(), 0).expect("Failed to set attribute");
+        let attr_path = n5.get_attributes_path("foo").unwrap();
+        let lock = LockFileGuard::acquire(&attr_path, &n5.locks).unwrap();
+
+        match n5.set_attribute("foo", "bar".to_owned(), 1) {
+            Err(N5Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+
+        // Read-only containers wait for lock files without creating them.
+        let read = N5Filesystem::builder(base_path)
+            .lock_mode(LockMode::LockFile)
+            .lock_timeout(Duration::from_millis(20))
+            .read_only(true)
+            .open()
+            .expect("Failed to open read-only container");
+        assert!(matches!(read.list_attributes("foo"), Err(N5Error::Io(_))));
+        drop(lock);
+        assert_eq!(read.list_attributes("foo").unwrap(), json!({"baz": 0}));
+        assert!(!LockFileGuard::lock_path(&attr_path).exists());
+
+        // Stale lock files are removed rather than waited for.
+        let stale = N5Filesystem::builder(base_path)
+            .lock_mode(LockMode::LockFile)
+            .lock_timeout(Duration::from_secs(10))
+            .stale_lock_age(Some(Duration::from_millis(10)))
+            .open()
+            .expect("Failed to open N5 filesystem");
+        std::mem::forget(LockFileGuard::acquire(&attr_path, &stale.locks).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        stale.set_attribute("foo", "bar".to_owned(), 1)
+            .expect("Failed to set attribute");
+        assert_eq!(stale.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+        assert!(!LockFileGuard::lock_path(&attr_path).exists());
+
+        // A lock file acquired after another was found stale is restored
+        // rather than removed.
+        let lock = LockFileGuard::acquire(&attr_path, &stale.locks).unwrap();
+        LockFileGuard::remove_stale(&lock.0, Some(Duration::from_secs(60))).unwrap();
+        assert!(lock.0.is_file());
+        drop(lock);
+        let files = fs::read_dir(attr_path.parent().unwrap()).unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![ATTRIBUTES_FILE]);
+    }
+
+    #[test]
+    fn nested_attributes() {
+        let wrapper = N5Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        create.create_group("foo").expect("Failed to create group");
+        create.set_attribute("foo", "multiscales".to_owned(), json!([{"name": "raw"}]))
+            .expect("Failed to set attribute");
+
+        create.set_nested_attribute("foo", "multiscales/0/datasets", ["s0", "s1"])
+            .expect("Failed to set nested attribute");
+        create.set_nested_attribute("foo", "/a/b~1c", 2)
+            .expect("Failed to set nested attribute");
+        assert_eq!(
+            create.list_attributes("foo").unwrap(),
+            json!({
+                "multiscales": [{"name": "raw", "datasets": ["s0", "s1"]}],
+                "a": {"b/c": 2},
+            }));
+
+        let read = create.open_reader();
+        let datasets: Vec<String> = read.get_nested_attribute("foo", "multiscales/0/datasets")
+            .expect("Failed to get nested attribute");
+        assert_eq!(datasets, vec!["s0", "s1"]);
+        let missing: Option<String> = read.get_nested_attribute("foo", "multiscales/1/name")
+            .expect("Failed to get nested attribute");
+        assert_eq!(missing, None);
+        assert!(matches!(
+            read.get_nested_attribute::<String>("foo", "a/b"),
+            Err(N5Error::AttributeNotFound { .. })));
+
+        assert_eq!(
+            create.remove_nested_attribute("foo", "multiscales/0/datasets/0").unwrap(),
+            Some(json!("s0")));
+        assert_eq!(create.remove_nested_attribute("foo", "a").unwrap(), Some(json!({"b/c": 2})));
+        assert_eq!(create.remove_nested_attribute("foo", "a").unwrap(), None);
+        assert_eq!(
+            read.list_attributes("foo").unwrap(),
+            json!({"multiscales": [{"name": "raw", "datasets": ["s1"]}]}));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_rw() {
+        use crate::asynchronous::tests::{
+            dataset_rw,
+            runtime,
+        };
+
+        let wrapper = N5Filesystem::temp_new_rw();
+        runtime().block_on(dataset_rw(wrapper.as_ref()));
+
+        let wrapper = N5Filesystem::temp_new_rw();
+        runtime().block_on(dataset_rw(&wrapper.as_ref().clone().with_atomic_writes(true)));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_sync_interop() {
+        use crate::asynchronous::{
+            N5AsyncReader,
+            N5AsyncWriter,
+        };
+
+        let wrapper = N5Filesystem::temp_new_rw();
+        let n5 = wrapper.as_ref();
+        let runtime = crate::asynchronous::tests::runtime();
+        assert_eq!(runtime.block_on(N5AsyncReader::get_version(n5)).unwrap(), crate::VERSION);
+
+        for data_attrs in [
+            DatasetAttributes::new(
+                smallvec![10, 10],
+                smallvec![5, 5],
+                crate::DataType::UINT8,
+                crate::compression::CompressionType::default(),
+            ),
+            DatasetAttributes::new(
+                smallvec![10, 10],
+                smallvec![5, 5],
+                crate::DataType::UINT8,
+                crate::compression::CompressionType::default(),
+            ).with_shard_size(smallvec![10, 10]),
+        ] {
+            N5Writer::create_dataset(n5, "foo", &data_attrs).unwrap();
+            let block_data: Vec<u8> = (0..25).collect();
+            let block_in = crate::SliceDataBlock::new(smallvec![5, 5], smallvec![1, 1], &block_data[..]);
+            runtime.block_on(N5AsyncWriter::write_block(n5, "foo", &data_attrs, &block_in)).unwrap();
+            let block_out = N5Reader::read_block::<u8>(n5, "foo", &data_attrs, smallvec![1, 1])
+                .unwrap()
+                .expect("Block is empty");
+            assert_eq!(block_out.get_data(), &block_data[..]);
+
+            let block_in = crate::SliceDataBlock::new(smallvec![5, 5], smallvec![0, 1], &block_data[..]);
+            N5Writer::write_block(n5, "foo", &data_attrs, &block_in).unwrap();
+            let block_out = runtime.block_on(N5AsyncReader::read_block::<u8>(n5, "foo", &data_attrs, smallvec![0, 1]))
+                .unwrap()
+                .expect("Block is empty");
+            assert_eq!(block_out.get_data(), &block_data[..]);
+            assert_eq!(
+                runtime.block_on(N5AsyncReader::block_metadata(n5, "foo", &data_attrs, &[0, 1])).unwrap()
+                    .unwrap().size,
+                N5Reader::block_metadata(n5, "foo", &data_attrs, &[0, 1]).unwrap().unwrap().size);
+
+            assert!(runtime.block_on(N5AsyncWriter::delete_block(n5, "foo", &[0, 1])).unwrap());
+            assert!(N5Reader::read_block::<u8>(n5, "foo", &data_attrs, smallvec![0, 1]).unwrap().is_none());
+            N5Writer::remove(n5, "foo").unwrap();
+        }
+    }
 }
@@ -0,0 +1,603 @@
+//! A read-only N5 container served over HTTP.
+//!
+//! Files are fetched by URL exactly as they are laid out by `N5Filesystem`,
+//! so any static web server can serve a container. Missing files (404s) are
+//! missing attributes or blocks.
+
+use std::convert::TryFrom;
+use std::io::{
+    BufReader,
+    Error,
+    Read,
+};
+use std::str::FromStr;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::in_memory::normalize_path;
+use crate::shard::check_unsharded;
+use crate::{
+    is_version_compatible,
+    DataBlock,
+    DataBlockMetadata,
+    DatasetAttributes,
+    DefaultBlockReader,
+    GridCoord,
+    N5Error,
+    N5Reader,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    Version,
+};
+
+
+/// Name of the attributes file stored in the container root and dataset dirs.
+const ATTRIBUTES_FILE: &str = "attributes.json";
+
+
+/// A read-only N5 container served over HTTP.
+#[derive(Clone)]
+pub struct N5Http {
+    base_url: String,
+    agent: ureq::Agent,
+    /// Client for requests made through `N5AsyncReader`.
+    #[cfg(feature = "http-async")]
+    client: reqwest::Client,
+}
+
+impl N5Http {
+    /// Open an N5 container by its base URL.
+    pub fn open(base_url: &str) -> Result<N5Http> {
+        N5Http::open_with_agent(base_url, ureq::Agent::new())
+    }
+
+    /// Open an N5 container by its base URL, making requests with a
+    /// configured agent, e.g., for timeouts or proxies.
+    pub fn open_with_agent(base_url: &str, agent: ureq::Agent) -> Result<N5Http> {
+        let reader = N5Http {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            agent,
+            #[cfg(feature = "http-async")]
+            client: reqwest::Client::new(),
+        };
+
+        if let Some(attrs) = reader.get_json("", ATTRIBUTES_FILE)? {
+            if attrs.get(crate::VERSION_ATTRIBUTE_KEY).is_some() {
+                let version = reader.get_version()?;
+
+                if !is_version_compatible(&crate::VERSION, &version) {
+                    return Err(N5Error::IncompatibleVersion(version))
+                }
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Get the URL of a `/`-separated file path within a group.
+    fn get_url(&self, path_name: &str, file: &str) -> Result<String> {
+        let mut url = self.base_url.clone();
+        let path = normalize_path(path_name)?;
+        for component in path.split('/').chain(file.split('/')).filter(|c| !c.is_empty()) {
+            url.push('/');
+            url.push_str(&percent_encode(component));
+        }
+        Ok(url)
+    }
+
+    fn get_data_block_url(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        let coords: Vec<String> = grid_position.iter().map(u64::to_string).collect();
+        self.get_url(path_name, &coords.join("/"))
+    }
+
+    /// Send a request, treating 404 responses as `None`.
+    fn call(&self, method: &str, url: &str) -> Result<Option<ureq::Response>> {
+        match self.agent.request(method, url).call() {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(ureq::Error::Status(status, _)) =>
+                Err(Error::other(format!("HTTP {} for {} {}", status, method, url)).into()),
+            Err(ureq::Error::Transport(e)) => Err(Error::other(e).into()),
+        }
+    }
+
+    fn get_json(&self, path_name: &str, file: &str) -> Result<Option<Value>> {
+        match self.call("GET", &self.get_url(path_name, file)?)? {
+            Some(response) => Ok(Some(serde_json::from_reader(response.into_reader())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_block_reader(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<BufReader<impl Read>>> {
+        check_unsharded(data_attrs, "N5Http")?;
+        let url = self.get_data_block_url(path_name, grid_position)?;
+        Ok(self.call("GET", &url)?.map(|response| BufReader::new(response.into_reader())))
+    }
+}
+
+/// Percent-encode a path component, leaving only unreserved characters.
+pub(crate) fn percent_encode(component: &str) -> String {
+    let mut encoded = String::with_capacity(component.len());
+    for b in component.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Parse an HTTP date in the preferred IMF-fixdate format, e.g.,
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let mut parts = date.split_whitespace().skip(1);
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1, "Feb" => 2, "Mar" => 3, "Apr" => 4, "May" => 5, "Jun" => 6,
+        "Jul" => 7, "Aug" => 8, "Sep" => 9, "Oct" => 10, "Nov" => 11, "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" {
+        return None;
+    }
+
+    // Days since the epoch of a proleptic Gregorian date.
+    let y = if month <= 2 {year - 1} else {year};
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    u64::try_from(secs).ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+impl N5Reader for N5Http {
+    fn get_version(&self) -> Result<Version> {
+        let attrs = self.list_attributes("")?;
+        let version = attrs.get(crate::VERSION_ATTRIBUTE_KEY)
+            .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+            .as_str().unwrap_or("");
+
+        Version::from_str(version)
+            .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+        let attrs = self.get_json(path_name, ATTRIBUTES_FILE)?
+            .ok_or_else(|| N5Error::DatasetNotFound(path_name.to_owned()))?;
+        Ok(serde_json::from_value(attrs)?)
+    }
+
+    /// Test whether a group or dataset exists. Static web servers often do
+    /// not serve directories, so groups without attributes may not be found.
+    fn exists(&self, path_name: &str) -> Result<bool> {
+        if self.call("HEAD", &self.get_url(path_name, ATTRIBUTES_FILE)?)?.is_some() {
+            return Ok(true);
+        }
+        match self.agent.head(&format!("{}/", self.get_url(path_name, "")?)).call() {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::Status(_, _)) => Ok(false),
+            Err(ureq::Error::Transport(e)) => Err(Error::other(e).into()),
+        }
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        self.get_data_block_url(path_name, grid_position)
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<VecDataBlock<T>>>
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        match self.get_block_reader(path_name, data_attrs, &grid_position)? {
+            Some(reader) => Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+                reader,
+                data_attrs,
+                grid_position)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_into<T: ReflectedType, B: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        block: &mut B,
+    ) -> Result<Option<()>> {
+        match self.get_block_reader(path_name, data_attrs, &grid_position)? {
+            Some(reader) => {
+                <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block_into(
+                    reader,
+                    data_attrs,
+                    grid_position,
+                    block)?;
+                Ok(Some(()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.get_block_reader(path_name, data_attrs, &grid_position)? {
+            Some(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                Ok(Some(data))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Read block metadata from the `Content-Length` and `Last-Modified`
+    /// headers of a `HEAD` request.
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<DataBlockMetadata>> {
+        check_unsharded(data_attrs, "N5Http")?;
+        let url = self.get_data_block_url(path_name, grid_position)?;
+        Ok(self.call("HEAD", &url)?.map(|response| DataBlockMetadata {
+            created: None,
+            accessed: None,
+            modified: response.header("Last-Modified").and_then(parse_http_date),
+            size: response.header("Content-Length").and_then(|l| l.parse().ok()),
+        }))
+    }
+
+    fn list_attributes(&self, path_name: &str) -> Result<Value> {
+        match self.get_json(path_name, ATTRIBUTES_FILE)? {
+            Some(attrs) => Ok(attrs),
+            None => Err(N5Error::missing_attributes(path_name, self.exists(path_name)?)),
+        }
+    }
+}
+
+
+#[cfg(feature = "http-async")]
+mod asynchronous {
+    use super::*;
+    use reqwest::Method;
+
+    use crate::asynchronous::N5AsyncReader;
+
+    impl N5Http {
+        /// Make asynchronous requests with a configured client, e.g., for
+        /// timeouts or proxies.
+        pub fn with_async_client(mut self, client: reqwest::Client) -> N5Http {
+            self.client = client;
+            self
+        }
+
+        /// Send a request, treating 404 responses as `None`.
+        async fn call_async(&self, method: Method, url: &str) -> Result<Option<reqwest::Response>> {
+            let response = self.client.request(method.clone(), url).send().await
+                .map_err(Error::other)?;
+            let status = response.status();
+            if status == reqwest::StatusCode::NOT_FOUND {
+                Ok(None)
+            } else if status.is_success() {
+                Ok(Some(response))
+            } else {
+                Err(Error::other(format!("HTTP {} for {} {}", status.as_u16(), method, url)).into())
+            }
+        }
+
+        async fn get_bytes_async(&self, url: &str) -> Result<Option<Vec<u8>>> {
+            match self.call_async(Method::GET, url).await? {
+                Some(response) => Ok(Some(response.bytes().await.map_err(Error::other)?.to_vec())),
+                None => Ok(None),
+            }
+        }
+
+        async fn get_json_async(&self, path_name: &str, file: &str) -> Result<Option<Value>> {
+            match self.get_bytes_async(&self.get_url(path_name, file)?).await? {
+                Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl N5AsyncReader for N5Http {
+        async fn get_version(&self) -> Result<Version> {
+            let attrs = N5AsyncReader::list_attributes(self, "").await?;
+            let version = attrs.get(crate::VERSION_ATTRIBUTE_KEY)
+                .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+                .as_str().unwrap_or("");
+
+            Version::from_str(version)
+                .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
+        }
+
+        async fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+            let attrs = self.get_json_async(path_name, ATTRIBUTES_FILE).await?
+                .ok_or_else(|| N5Error::DatasetNotFound(path_name.to_owned()))?;
+            Ok(serde_json::from_value(attrs)?)
+        }
+
+        async fn exists(&self, path_name: &str) -> Result<bool> {
+            if self.call_async(Method::HEAD, &self.get_url(path_name, ATTRIBUTES_FILE)?).await?.is_some() {
+                return Ok(true);
+            }
+            let response = self.client.head(format!("{}/", self.get_url(path_name, "")?)).send().await
+                .map_err(Error::other)?;
+            Ok(response.status().is_success())
+        }
+
+        fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+            self.get_data_block_url(path_name, grid_position)
+        }
+
+        async fn read_block<T>(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            grid_position: GridCoord,
+        ) -> Result<Option<VecDataBlock<T>>>
+                where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                      T: ReflectedType {
+            check_unsharded(data_attrs, "N5Http")?;
+            let url = self.get_data_block_url(path_name, &grid_position)?;
+            match self.get_bytes_async(&url).await? {
+                Some(bytes) => Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+                    &bytes[..],
+                    data_attrs,
+                    grid_position)?)),
+                None => Ok(None),
+            }
+        }
+
+        async fn block_metadata(
+            &self,
+            path_name: &str,
+            data_attrs: &DatasetAttributes,
+            grid_position: &[u64],
+        ) -> Result<Option<DataBlockMetadata>> {
+            check_unsharded(data_attrs, "N5Http")?;
+            let url = self.get_data_block_url(path_name, grid_position)?;
+            let header = |response: &reqwest::Response, name: &str| response.headers().get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned);
+            Ok(self.call_async(Method::HEAD, &url).await?.map(|response| DataBlockMetadata {
+                created: None,
+                accessed: None,
+                modified: header(&response, "Last-Modified").as_deref().and_then(parse_http_date),
+                size: header(&response, "Content-Length").and_then(|l| l.parse().ok()),
+            }))
+        }
+
+        async fn list_attributes(&self, path_name: &str) -> Result<Value> {
+            match self.get_json_async(path_name, ATTRIBUTES_FILE).await? {
+                Some(attrs) => Ok(attrs),
+                None => Err(N5Error::missing_attributes(path_name, N5AsyncReader::exists(self, path_name).await?)),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::N5Filesystem;
+    use crate::N5Writer;
+    use std::io::{
+        BufRead,
+        Write,
+    };
+    use std::net::{
+        TcpListener,
+        TcpStream,
+    };
+    use std::path::{
+        Path,
+        PathBuf,
+    };
+    use tempdir::TempDir;
+
+    /// Serve a directory as a minimal static web server, returning its URL.
+    fn serve_dir(root: &Path) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let root = root.to_owned();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let root = root.clone();
+                std::thread::spawn(move || handle_request(&root, stream.unwrap()));
+            }
+        });
+        url
+    }
+
+    fn handle_request(root: &Path, mut stream: TcpStream) {
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap();
+        let path: PathBuf = parts.next().unwrap().split('/').filter(|c| !c.is_empty()).collect();
+        let path = root.join(path);
+        let (status, body) = if path.is_file() {
+            ("200 OK", std::fs::read(path).unwrap())
+        } else if path.is_dir() {
+            ("403 Forbidden", Vec::new())
+        } else {
+            ("404 Not Found", Vec::new())
+        };
+
+        write!(stream,
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nLast-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\nConnection: close\r\n\r\n",
+            status, body.len()).unwrap();
+        if method != "HEAD" {
+            stream.write_all(&body).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_served_container() {
+        let dir = TempDir::new("rust_n5_http_tests").unwrap();
+        let n5 = N5Filesystem::open_or_create(dir.path().to_str().unwrap()).unwrap();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10, 10],
+            smallvec![5, 5, 5],
+            crate::DataType::INT32,
+            crate::compression::CompressionType::default(),
+        );
+        let block_data: Vec<i32> = (0..125_i32).collect();
+        let block_in = crate::SliceDataBlock::new(
+            data_attrs.get_block_size().into(),
+            smallvec![0, 1, 0],
+            &block_data);
+        n5.create_dataset("foo/bar", &data_attrs).unwrap();
+        n5.write_block("foo/bar", &data_attrs, &block_in).unwrap();
+        n5.set_attribute("foo", "baz".to_owned(), 42).unwrap();
+
+        let url = serve_dir(dir.path());
+        let http = N5Http::open(&url).unwrap();
+
+        assert_eq!(http.get_version().unwrap(), crate::VERSION);
+        assert_eq!(http.list_attributes("foo").unwrap()["baz"], 42);
+        assert_eq!(http.get_dataset_attributes("foo/bar").unwrap(), data_attrs);
+        assert!(matches!(http.get_dataset_attributes("foo/qux"), Err(N5Error::DatasetNotFound(_))));
+        assert!(http.exists("foo").unwrap());
+        assert!(!http.exists("qux").unwrap());
+        assert!(http.dataset_exists("foo/bar").unwrap());
+        assert!(!http.dataset_exists("foo").unwrap());
+        assert!(matches!(http.exists("../foo"), Err(N5Error::PathEscape(_))));
+        assert_eq!(http.get_block_uri("foo/bar", &[0, 1, 0]).unwrap(), format!("{}/foo/bar/0/1/0", url));
+
+        let block_out = http.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 1, 0])
+            .unwrap()
+            .expect("Block is missing");
+        assert_eq!(block_out.get_data(), &block_data[..]);
+        assert!(http.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 0, 0]).unwrap().is_none());
+        assert_eq!(
+            http.read_block_raw("foo/bar", &data_attrs, smallvec![0, 1, 0]).unwrap(),
+            n5.read_block_raw("foo/bar", &data_attrs, smallvec![0, 1, 0]).unwrap());
+        assert!(http.read_block_raw("foo/bar", &data_attrs, smallvec![0, 0, 0]).unwrap().is_none());
+        let sharded_attrs = data_attrs.clone().with_shard_size(data_attrs.get_block_size().into());
+        assert!(matches!(
+            http.read_block::<i32>("foo/bar", &sharded_attrs, smallvec![0, 1, 0]),
+            Err(N5Error::InvalidMetadata(_))));
+        assert!(matches!(
+            http.read_block_raw("foo/bar", &sharded_attrs, smallvec![0, 1, 0]),
+            Err(N5Error::InvalidMetadata(_))));
+
+        let metadata = http.block_metadata("foo/bar", &data_attrs, &[0, 1, 0])
+            .unwrap()
+            .expect("Block is missing");
+        assert_eq!(
+            metadata.size,
+            Some(std::fs::metadata(dir.path().join("foo/bar/0/1/0")).unwrap().len()));
+        assert_eq!(metadata.modified, Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+        assert!(http.block_metadata("foo/bar", &data_attrs, &[0, 0, 0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn encode_urls() {
+        let http = N5Http {
+            base_url: "http://example.com/data.n5".to_owned(),
+            agent: ureq::Agent::new(),
+            #[cfg(feature = "http-async")]
+            client: reqwest::Client::new(),
+        };
+        assert_eq!(
+            http.get_url("foo bar/./baz%", ATTRIBUTES_FILE).unwrap(),
+            "http://example.com/data.n5/foo%20bar/baz%25/attributes.json");
+        assert_eq!(http.get_url("", "").unwrap(), "http://example.com/data.n5");
+    }
+
+    #[test]
+    fn parse_http_dates() {
+        assert_eq!(
+            parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(UNIX_EPOCH));
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 12:00:01 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(951_825_601)));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    }
+
+    #[cfg(feature = "http-async")]
+    #[test]
+    fn read_served_container_async() {
+        use crate::asynchronous::N5AsyncReader;
+
+        let dir = TempDir::new("rust_n5_http_tests").unwrap();
+        let n5 = N5Filesystem::open_or_create(dir.path().to_str().unwrap()).unwrap();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![5, 5],
+            crate::DataType::UINT16,
+            crate::compression::CompressionType::default(),
+        );
+        let block_data: Vec<u16> = (0..25).collect();
+        let block_in = crate::SliceDataBlock::new(smallvec![5, 5], smallvec![1, 0], &block_data);
+        n5.create_dataset("foo/bar", &data_attrs).unwrap();
+        n5.write_block("foo/bar", &data_attrs, &block_in).unwrap();
+
+        let url = serve_dir(dir.path());
+        let http = N5Http::open(&url).unwrap();
+        let runtime = crate::asynchronous::tests::runtime();
+        runtime.block_on(async {
+            assert_eq!(N5AsyncReader::get_version(&http).await.unwrap(), crate::VERSION);
+            assert_eq!(N5AsyncReader::get_dataset_attributes(&http, "foo/bar").await.unwrap(), data_attrs);
+            assert!(N5AsyncReader::exists(&http, "foo/bar").await.unwrap());
+            assert!(!N5AsyncReader::exists(&http, "qux").await.unwrap());
+            assert!(N5AsyncReader::dataset_exists(&http, "foo/bar").await.unwrap());
+
+            let block_out = N5AsyncReader::read_block::<u16>(&http, "foo/bar", &data_attrs, smallvec![1, 0]).await
+                .unwrap()
+                .expect("Block is missing");
+            assert_eq!(block_out.get_data(), &block_data[..]);
+            assert!(N5AsyncReader::read_block::<u16>(&http, "foo/bar", &data_attrs, smallvec![0, 0]).await
+                .unwrap()
+                .is_none());
+
+            let metadata = N5AsyncReader::block_metadata(&http, "foo/bar", &data_attrs, &[1, 0]).await
+                .unwrap()
+                .expect("Block is missing");
+            assert_eq!(
+                metadata.size,
+                Some(std::fs::metadata(dir.path().join("foo/bar/1/0")).unwrap().len()));
+            assert_eq!(metadata.modified, Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+        });
+    }
+}
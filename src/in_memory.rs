@@ -0,0 +1,495 @@
+//! An in-memory N5 container.
+//!
+//! Blocks are stored encoded exactly as they would be by `N5Filesystem`, so
+//! this backend is useful for tests and short-lived pipelines that should not
+//! touch disk.
+
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::str::FromStr;
+use std::sync::{
+    Arc,
+    PoisonError,
+    RwLock,
+    RwLockReadGuard,
+    RwLockWriteGuard,
+};
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::shard::check_unsharded;
+use crate::{
+    DataBlock,
+    DataBlockMetadata,
+    DatasetAttributes,
+    DefaultBlockReader,
+    DefaultBlockWriter,
+    GridCoord,
+    N5BlockLister,
+    N5Error,
+    N5Lister,
+    N5Reader,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    Version,
+    WriteableDataBlock,
+};
+
+
+/// An encoded block and its timestamps.
+struct StoredBlock {
+    data: Vec<u8>,
+    created: SystemTime,
+    modified: SystemTime,
+}
+
+/// A group (or dataset) and its contents.
+#[derive(Default)]
+struct Group {
+    /// Attributes of the group. `None` if attributes have never been set,
+    /// which is distinct from an empty attributes object.
+    attributes: Option<serde_json::Map<String, Value>>,
+    blocks: HashMap<GridCoord, StoredBlock>,
+}
+
+#[derive(Default)]
+struct Container {
+    /// Groups keyed by normalized path name. The root group is `""`.
+    groups: BTreeMap<String, Group>,
+}
+
+impl Container {
+    /// Create a group and all of its ancestors if they do not exist.
+    fn create_group(&mut self, path_name: &str) -> &mut Group {
+        let mut ancestor = String::new();
+        self.groups.entry(ancestor.clone()).or_default();
+        for component in path_name.split('/').filter(|c| !c.is_empty()) {
+            if !ancestor.is_empty() {
+                ancestor.push('/');
+            }
+            ancestor.push_str(component);
+            self.groups.entry(ancestor.clone()).or_default();
+        }
+
+        self.groups.get_mut(path_name).expect("Impossible: group was just created")
+    }
+}
+
+/// An in-memory N5 container.
+///
+/// Clones of an `N5InMemory` share the same underlying container, similar to
+/// how clones of an `N5Filesystem` share the same directory.
+#[derive(Clone)]
+pub struct N5InMemory {
+    container: Arc<RwLock<Container>>,
+}
+
+impl N5InMemory {
+    /// Create a new, empty in-memory N5 container.
+    pub fn new() -> N5InMemory {
+        let n5 = N5InMemory {
+            container: Arc::new(RwLock::new(Container::default())),
+        };
+
+        n5.create_group("").expect("Impossible: root path is always valid");
+        n5.set_attribute("", crate::VERSION_ATTRIBUTE_KEY.to_owned(), crate::VERSION.to_string())
+            .expect("Impossible: root group was just created");
+
+        n5
+    }
+
+    /// Get all attributes of a group, returning an empty object if the group
+    /// exists but has no attributes set.
+    pub fn get_attributes(&self, path_name: &str) -> Result<Value> {
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        let group = container.groups.get(&path)
+            .ok_or_else(|| N5Error::PathNotFound(path_name.to_owned()))?;
+
+        Ok(Value::Object(group.attributes.clone().unwrap_or_default()))
+    }
+
+    /// Store an encoded block, keeping its creation time if it is replaced.
+    fn store_block(&self, path_name: &str, grid_position: &[u64], data: Vec<u8>) -> Result<()> {
+        let path = normalize_path(path_name)?;
+        let now = SystemTime::now();
+        let mut container = self.write();
+        let created = container.groups.get(&path)
+            .and_then(|g| g.blocks.get(grid_position))
+            .map_or(now, |b| b.created);
+        container.create_group(&path).blocks.insert(
+            grid_position.into(),
+            StoredBlock {
+                data,
+                created,
+                modified: now,
+            });
+
+        Ok(())
+    }
+
+    // The container is never left in an inconsistent state by a panicking
+    // writer, so it is safe to ignore lock poisoning.
+    fn read(&self) -> RwLockReadGuard<'_, Container> {
+        self.container.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Container> {
+        self.container.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for N5InMemory {
+    fn default() -> N5InMemory {
+        N5InMemory::new()
+    }
+}
+
+/// Normalize a path name to `/`-separated components without `.` or `..`,
+/// rejecting paths outside the container.
+pub(crate) fn normalize_path(path_name: &str) -> Result<String> {
+    if path_name.starts_with('/') {
+        return Err(N5Error::PathEscape(path_name.to_owned()));
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path_name.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(N5Error::PathEscape(path_name.to_owned()));
+                }
+            },
+            _ => components.push(component),
+        }
+    }
+
+    Ok(components.join("/"))
+}
+
+/// Whether `path` is `ancestor` or contained within it.
+pub(crate) fn is_descendant(path: &str, ancestor: &str) -> bool {
+    ancestor.is_empty() ||
+    path == ancestor ||
+    (path.starts_with(ancestor) && path[ancestor.len()..].starts_with('/'))
+}
+
+impl N5Reader for N5InMemory {
+    fn get_version(&self) -> Result<Version> {
+        let attrs = self.get_attributes("")?;
+        let version = attrs.get(crate::VERSION_ATTRIBUTE_KEY)
+            .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+            .as_str().unwrap_or("");
+
+        Version::from_str(version)
+            .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+        let attrs = self.list_attributes(path_name)
+            .map_err(|_| N5Error::DatasetNotFound(path_name.to_owned()))?;
+        Ok(serde_json::from_value(attrs)?)
+    }
+
+    fn exists(&self, path_name: &str) -> Result<bool> {
+        let path = normalize_path(path_name)?;
+        Ok(self.read().groups.contains_key(&path))
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        let mut uri = format!("memory://{}", normalize_path(path_name)?);
+        for coord in grid_position {
+            uri.push('/');
+            uri.push_str(&coord.to_string());
+        }
+        Ok(uri)
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<VecDataBlock<T>>>
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        match container.groups.get(&path).and_then(|g| g.blocks.get(&grid_position)) {
+            Some(block) => Ok(Some(<crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+                &block.data[..],
+                data_attrs,
+                grid_position)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_into<T: ReflectedType, B: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        block: &mut B,
+    ) -> Result<Option<()>> {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        match container.groups.get(&path).and_then(|g| g.blocks.get(&grid_position)) {
+            Some(stored) => {
+                <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block_into(
+                    &stored.data[..],
+                    data_attrs,
+                    grid_position,
+                    block)?;
+                Ok(Some(()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<Vec<u8>>> {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        Ok(container.groups.get(&path)
+            .and_then(|g| g.blocks.get(&grid_position))
+            .map(|block| block.data.clone()))
+    }
+
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<DataBlockMetadata>> {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        Ok(container.groups.get(&path)
+            .and_then(|g| g.blocks.get(grid_position))
+            .map(|block| DataBlockMetadata {
+                created: Some(block.created),
+                accessed: None,
+                modified: Some(block.modified),
+                size: Some(block.data.len() as u64),
+            }))
+    }
+
+    fn list_attributes(&self, path_name: &str) -> Result<Value> {
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        match container.groups.get(&path) {
+            Some(group) => group.attributes.clone()
+                .map(Value::Object)
+                .ok_or_else(|| N5Error::missing_attributes(path_name, true)),
+            None => Err(N5Error::missing_attributes(path_name, false)),
+        }
+    }
+}
+
+impl N5Lister for N5InMemory {
+    fn list(&self, path_name: &str) -> Result<Vec<String>> {
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        if !container.groups.contains_key(&path) {
+            return Err(N5Error::PathNotFound(path_name.to_owned()));
+        }
+
+        Ok(container.groups.keys()
+            .filter(|k| !k.is_empty() && k.as_str() != path && is_descendant(k, &path))
+            .filter_map(|k| {
+                let child = if path.is_empty() { &k[..] } else { &k[path.len() + 1..] };
+                if child.contains('/') {
+                    None
+                } else {
+                    Some(child.to_owned())
+                }
+            })
+            .collect())
+    }
+}
+
+impl N5BlockLister for N5InMemory {
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>> {
+        check_unsharded(&self.get_dataset_attributes(path_name)?, "N5InMemory")?;
+        let path = normalize_path(path_name)?;
+        let container = self.read();
+        Ok(container.groups.get(&path)
+            .map(|g| g.blocks.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+impl N5Writer for N5InMemory {
+    fn set_attributes(
+        &self,
+        path_name: &str,
+        attributes: serde_json::Map<String, Value>,
+    ) -> Result<()> {
+        let path = normalize_path(path_name)?;
+        let mut container = self.write();
+        let group = container.groups.get_mut(&path)
+            .ok_or_else(|| N5Error::PathNotFound(path_name.to_owned()))?;
+
+        group.attributes.get_or_insert_with(Default::default).extend(attributes);
+
+        Ok(())
+    }
+
+    fn remove_attributes(
+        &self,
+        path_name: &str,
+        keys: &[&str],
+        force: bool,
+    ) -> Result<serde_json::Map<String, Value>> {
+        let path = normalize_path(path_name)?;
+        let mut container = self.write();
+        let group = container.groups.get_mut(&path)
+            .ok_or_else(|| N5Error::PathNotFound(path_name.to_owned()))?;
+
+        match group.attributes.take() {
+            Some(attributes) => {
+                let mut attributes = Value::Object(attributes);
+                let removed = crate::remove_attribute_keys(path_name, &mut attributes, keys, force);
+                if let Value::Object(attributes) = attributes {
+                    group.attributes = Some(attributes);
+                }
+                removed
+            },
+            None => Ok(serde_json::Map::new()),
+        }
+    }
+
+    fn create_group(&self, path_name: &str) -> Result<()> {
+        let path = normalize_path(path_name)?;
+        self.write().create_group(&path);
+        Ok(())
+    }
+
+    fn remove(
+        &self,
+        path_name: &str,
+    ) -> Result<()> {
+        let path = normalize_path(path_name)?;
+        self.write().groups.retain(|k, _| !is_descendant(k, &path));
+        Ok(())
+    }
+
+    fn write_block<T, B: DataBlock<T> + WriteableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        block: &B,
+    ) -> Result<()> {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        let mut data = Vec::new();
+        <crate::DefaultBlock as DefaultBlockWriter<T, _, _>>::write_block(
+            &mut data,
+            data_attrs,
+            block)?;
+
+        self.store_block(path_name, block.get_grid_position(), data)
+    }
+
+    fn write_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        data: &[u8],
+    ) -> Result<()> {
+        check_unsharded(data_attrs, "N5InMemory")?;
+        self.store_block(path_name, &grid_position, data.to_vec())
+    }
+
+    fn delete_block(
+        &self,
+        path_name: &str,
+        grid_position: &[u64],
+    ) -> Result<bool> {
+        let path = normalize_path(path_name)?;
+        let mut container = self.write();
+        if let Some(group) = container.groups.get_mut(&path) {
+            group.blocks.remove(grid_position);
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_backend;
+    use crate::tests::{ContextWrapper, N5Testable};
+
+    impl crate::tests::N5Testable for N5InMemory {
+        type Wrapper = ContextWrapper<(), N5InMemory>;
+
+        fn temp_new_rw() -> Self::Wrapper {
+            ContextWrapper {
+                context: (),
+                n5: N5InMemory::new(),
+            }
+        }
+
+        fn open_reader(&self) -> Self {
+            self.clone()
+        }
+    }
+
+    test_backend!(N5InMemory);
+
+    #[test]
+    fn list_blocks() {
+        crate::tests::list_blocks::<N5InMemory>()
+    }
+
+    #[test]
+    fn sharded_unsupported() {
+        crate::tests::sharded_unsupported::<N5InMemory>()
+    }
+
+    #[test]
+    fn reject_exterior_paths() {
+        assert!(normalize_path("/").is_err());
+        assert!(normalize_path("..").is_err());
+        assert_eq!(normalize_path("foo/bar/baz/../../..").unwrap(), "");
+        assert_eq!(normalize_path("./foo//bar/").unwrap(), "foo/bar");
+        assert!(normalize_path("foo/bar/baz/../../../..").is_err());
+    }
+
+    #[test]
+    fn list_and_remove_groups() {
+        let wrapper = N5InMemory::temp_new_rw();
+        let n5 = wrapper.as_ref();
+        n5.create_group("foo/bar").unwrap();
+        n5.create_group("foo/baz/qux").unwrap();
+        n5.create_group("foobar").unwrap();
+
+        let mut root = n5.list("").unwrap();
+        root.sort();
+        assert_eq!(root, vec!["foo", "foobar"]);
+        let mut foo = n5.list("foo").unwrap();
+        foo.sort();
+        assert_eq!(foo, vec!["bar", "baz"]);
+
+        n5.remove("foo").unwrap();
+        assert!(!n5.exists("foo/baz/qux").unwrap());
+        assert!(n5.exists("foobar").unwrap());
+    }
+}
@@ -14,10 +14,6 @@ doc_comment::doctest!("../README.md");
 pub extern crate smallvec;
 
 
-use std::io::{
-    Error,
-    ErrorKind,
-};
 use std::marker::PhantomData;
 use std::time::SystemTime;
 
@@ -27,7 +23,9 @@ use byteorder::{
     ReadBytesExt,
     WriteBytesExt,
 };
+use itertools::Itertools;
 use serde::{
+    de::DeserializeOwned,
     Deserialize,
     Serialize,
 };
@@ -35,15 +33,36 @@ use smallvec::SmallVec;
 
 use crate::compression::Compression;
 
-pub mod compression;
 #[macro_use]
 pub mod data_type;
 pub use data_type::*;
+#[cfg(feature = "use_ndarray")]
+pub mod append;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod attributes;
+pub mod cache;
+pub mod compression;
+pub mod copy;
+pub mod error;
+pub use error::N5Error;
 #[cfg(feature = "filesystem")]
 pub mod filesystem;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod in_memory;
 #[cfg(feature = "use_ndarray")]
 pub mod ndarray;
 pub mod prelude;
+#[cfg(feature = "use_ndarray")]
+pub mod pyramid;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod shard;
+#[cfg(feature = "zarr")]
+pub mod zarr;
+#[cfg(feature = "zip")]
+pub mod zip;
 
 #[cfg(test)]
 #[macro_use]
@@ -74,6 +93,17 @@ pub fn is_version_compatible(s: &Version, other: &Version) -> bool {
 /// Key name for the version attribute in the container root.
 pub const VERSION_ATTRIBUTE_KEY: &str = "n5";
 
+/// Keys of the mandatory dataset attributes, which describe the layout of a
+/// dataset's blocks.
+pub const DATASET_ATTRIBUTE_KEYS: [&str; 6] = [
+    "dimensions",
+    "blockSize",
+    "dataType",
+    "compression",
+    "shardSize",
+    "indexLocation",
+];
+
 #[derive(Clone, Debug)]
 pub struct DataBlockMetadata {
     pub created: Option<SystemTime>,
@@ -85,16 +115,16 @@ pub struct DataBlockMetadata {
 /// Non-mutating operations on N5 containers.
 pub trait N5Reader {
     /// Get the N5 specification version of the container.
-    fn get_version(&self) -> Result<Version, Error>;
+    fn get_version(&self) -> Result<Version, N5Error>;
 
     /// Get attributes for a dataset.
-    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes, Error>;
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes, N5Error>;
 
     /// Test whether a group or dataset exists.
-    fn exists(&self, path_name: &str) -> Result<bool, Error>;
+    fn exists(&self, path_name: &str) -> Result<bool, N5Error>;
 
     /// Test whether a dataset exists.
-    fn dataset_exists(&self, path_name: &str) -> Result<bool, Error> {
+    fn dataset_exists(&self, path_name: &str) -> Result<bool, N5Error> {
         Ok(self.exists(path_name)? && self.get_dataset_attributes(path_name).is_ok())
     }
 
@@ -103,7 +133,7 @@ pub trait N5Reader {
     /// Whether this requires that the dataset and block exist is currently
     /// implementation dependent. Whether this URI is a URL is implementation
     /// dependent.
-    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String, Error>;
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String, N5Error>;
 
     /// Read a single dataset block into a linear vec.
     fn read_block<T>(
@@ -111,7 +141,7 @@ pub trait N5Reader {
         path_name: &str,
         data_attrs: &DatasetAttributes,
         grid_position: GridCoord,
-    ) -> Result<Option<VecDataBlock<T>>, Error>
+    ) -> Result<Option<VecDataBlock<T>>, N5Error>
         where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
               T: ReflectedType;
 
@@ -122,7 +152,33 @@ pub trait N5Reader {
         data_attrs: &DatasetAttributes,
         grid_position: GridCoord,
         block: &mut B,
-    ) -> Result<Option<()>, Error>;
+    ) -> Result<Option<()>, N5Error>;
+
+    /// Read a single block as encoded bytes: its N5 block header followed by
+    /// its compressed data.
+    ///
+    /// By default this decodes the block and encodes it again. Backends that
+    /// store N5-encoded blocks return the stored bytes instead.
+    fn read_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<Vec<u8>>, N5Error> {
+        data_type_match!(data_attrs.data_type, {
+            match self.read_block::<RsType>(path_name, data_attrs, grid_position)? {
+                Some(block) => {
+                    let mut buffer = Vec::new();
+                    <DefaultBlock as DefaultBlockWriter<RsType, _, _>>::write_block(
+                        &mut buffer,
+                        data_attrs,
+                        &block)?;
+                    Ok(Some(buffer))
+                },
+                None => Ok(None),
+            }
+        })
+    }
 
     /// Read metadata about a block.
     fn block_metadata(
@@ -130,15 +186,138 @@ pub trait N5Reader {
         path_name: &str,
         data_attrs: &DatasetAttributes,
         grid_position: &[u64],
-    ) -> Result<Option<DataBlockMetadata>, Error>;
+    ) -> Result<Option<DataBlockMetadata>, N5Error>;
 
     /// List all attributes of a group.
-    fn list_attributes(&self, path_name: &str) -> Result<serde_json::Value, Error>;
+    fn list_attributes(&self, path_name: &str) -> Result<serde_json::Value, N5Error>;
+
+    /// Get a single attribute of a group, deserialized as `T`.
+    ///
+    /// A missing attribute is an `AttributeNotFound` error, unless `T` can
+    /// be deserialized from `null`, e.g., an `Option`, in which case it is
+    /// read as `null`.
+    /// ```
+    /// use n5::prelude::*;
+    ///
+    /// let n5 = N5InMemory::new();
+    /// n5.create_group("foo").unwrap();
+    /// n5.set_attribute("foo", "resolution".to_owned(), [4.0, 4.0, 40.0]).unwrap();
+    /// let resolution: [f64; 3] = n5.get_attribute("foo", "resolution").unwrap();
+    /// assert_eq!(resolution, [4.0, 4.0, 40.0]);
+    /// let offset: Option<Vec<f64>> = n5.get_attribute("foo", "offset").unwrap();
+    /// assert_eq!(offset, None);
+    /// ```
+    fn get_attribute<T: DeserializeOwned>(&self, path_name: &str, key: &str) -> Result<T, N5Error> {
+        deserialize_attribute(path_name, key, self.list_attributes(path_name)?)
+    }
+
+    /// Get a nested attribute of a group by a JSON-pointer-style path, such
+    /// as `multiscales/0/datasets`, deserialized as `T`. See
+    /// [`attributes`](crate::attributes) for the path syntax.
+    ///
+    /// Missing attributes are treated as in `get_attribute`.
+    fn get_nested_attribute<T: DeserializeOwned>(
+        &self,
+        path_name: &str,
+        attribute_path: &str,
+    ) -> Result<T, N5Error> {
+        deserialize_nested_attribute(path_name, attribute_path, self.list_attributes(path_name)?)
+    }
+
+    /// Get all attributes of a group, deserialized as `T`.
+    fn get_attributes_as<T: DeserializeOwned>(&self, path_name: &str) -> Result<T, N5Error> {
+        deserialize_attributes(path_name, self.list_attributes(path_name)?)
+    }
+}
+
+pub(crate) fn deserialize_attribute<T: DeserializeOwned>(
+    path_name: &str,
+    key: &str,
+    mut attributes: serde_json::Value,
+) -> Result<T, N5Error> {
+    let attribute = attributes.get_mut(key).map(serde_json::Value::take);
+    deserialize_attribute_value(path_name, key, attribute)
+}
+
+pub(crate) fn deserialize_nested_attribute<T: DeserializeOwned>(
+    path_name: &str,
+    attribute_path: &str,
+    attributes: serde_json::Value,
+) -> Result<T, N5Error> {
+    let attribute = attributes::get_nested(&attributes, attribute_path).cloned();
+    deserialize_attribute_value(path_name, attribute_path, attribute)
+}
+
+fn deserialize_attribute_value<T: DeserializeOwned>(
+    path_name: &str,
+    key: &str,
+    attribute: Option<serde_json::Value>,
+) -> Result<T, N5Error> {
+    let invalid = |source| N5Error::InvalidAttribute {
+        path: path_name.to_owned(),
+        key: Some(key.to_owned()),
+        source,
+    };
+    match attribute {
+        Some(value) => serde_json::from_value(value).map_err(invalid),
+        None => serde_json::from_value(serde_json::Value::Null).map_err(|_| N5Error::AttributeNotFound {
+            path: path_name.to_owned(),
+            key: Some(key.to_owned()),
+        }),
+    }
+}
+
+/// Remove attributes from an attributes object for `N5Writer::remove_attributes`.
+pub(crate) fn remove_attribute_keys(
+    path_name: &str,
+    attributes: &mut serde_json::Value,
+    keys: &[&str],
+    force: bool,
+) -> Result<serde_json::Map<String, serde_json::Value>, N5Error> {
+    let attributes = match attributes {
+        serde_json::Value::Object(attributes) => attributes,
+        _ => return Ok(serde_json::Map::new()),
+    };
+
+    if !force {
+        let mandatory = keys.iter()
+            .find(|key| DATASET_ATTRIBUTE_KEYS.contains(key) && attributes.contains_key(**key));
+        if let Some(key) = mandatory {
+            let data_attrs = serde_json::from_value::<DatasetAttributes>(attributes.clone().into());
+            if data_attrs.is_ok() {
+                return Err(N5Error::MandatoryAttribute {
+                    path: path_name.to_owned(),
+                    key: (*key).to_owned(),
+                });
+            }
+        }
+    }
+
+    Ok(keys.iter()
+        .filter_map(|key| attributes.remove(*key).map(|value| ((*key).to_owned(), value)))
+        .collect())
+}
+
+pub(crate) fn deserialize_attributes<T: DeserializeOwned>(
+    path_name: &str,
+    attributes: serde_json::Value,
+) -> Result<T, N5Error> {
+    serde_json::from_value(attributes).map_err(|source| N5Error::InvalidAttribute {
+        path: path_name.to_owned(),
+        key: None,
+        source,
+    })
 }
 
 pub trait N5Lister : N5Reader {
     /// List all groups (including datasets) in a group.
-    fn list(&self, path_name: &str) -> Result<Vec<String>, Error>;
+    fn list(&self, path_name: &str) -> Result<Vec<String>, N5Error>;
+}
+
+pub trait N5BlockLister : N5Reader {
+    /// List the grid positions of all blocks that exist in a dataset, in no
+    /// particular order.
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>, N5Error>;
 }
 
 /// Mutating operations on N5 containers.
@@ -149,7 +328,7 @@ pub trait N5Writer : N5Reader {
         path_name: &str,
         key: String,
         attribute: T,
-    ) -> Result<(), Error> {
+    ) -> Result<(), N5Error> {
         self.set_attributes(
             path_name,
             vec![(key, serde_json::to_value(attribute)?)].into_iter().collect())
@@ -160,14 +339,36 @@ pub trait N5Writer : N5Reader {
         &self, // TODO: should this be mut for semantics?
         path_name: &str,
         attributes: serde_json::Map<String, serde_json::Value>,
-    ) -> Result<(), Error>;
+    ) -> Result<(), N5Error>;
+
+    /// Remove a single attribute, returning its value if it existed.
+    /// Mandatory dataset attributes are not removed; see `remove_attributes`.
+    fn remove_attribute(
+        &self,
+        path_name: &str,
+        key: &str,
+    ) -> Result<Option<serde_json::Value>, N5Error> {
+        Ok(self.remove_attributes(path_name, &[key], false)?.remove(key))
+    }
+
+    /// Remove attributes, returning the values of those that existed.
+    ///
+    /// Unless `force` is set, removing any of the mandatory dataset
+    /// attributes, `DATASET_ATTRIBUTE_KEYS`, from a dataset is a
+    /// `MandatoryAttribute` error and no attributes are removed.
+    fn remove_attributes(
+        &self,
+        path_name: &str,
+        keys: &[&str],
+        force: bool,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, N5Error>;
 
     /// Set mandatory dataset attributes.
     fn set_dataset_attributes(
         &self,
         path_name: &str,
         data_attrs: &DatasetAttributes,
-    ) -> Result<(), Error> {
+    ) -> Result<(), N5Error> {
         if let serde_json::Value::Object(map) = serde_json::to_value(data_attrs)? {
             self.set_attributes(path_name, map)
         } else {
@@ -176,7 +377,7 @@ pub trait N5Writer : N5Reader {
     }
 
     /// Create a group (directory).
-    fn create_group(&self, path_name: &str) -> Result<(), Error>;
+    fn create_group(&self, path_name: &str) -> Result<(), N5Error>;
 
     /// Create a dataset. This will create the dataset group and attributes,
     /// but not populate any block data.
@@ -184,13 +385,13 @@ pub trait N5Writer : N5Reader {
         &self,
         path_name: &str,
         data_attrs: &DatasetAttributes,
-    ) -> Result<(), Error> {
+    ) -> Result<(), N5Error> {
         self.create_group(path_name)?;
         self.set_dataset_attributes(path_name, data_attrs)
     }
 
     /// Remove the N5 container.
-    fn remove_all(&self) -> Result<(), Error> {
+    fn remove_all(&self) -> Result<(), N5Error> {
         self.remove("")
     }
 
@@ -200,14 +401,35 @@ pub trait N5Writer : N5Reader {
     fn remove(
         &self,
         path_name: &str,
-    ) -> Result<(), Error>;
+    ) -> Result<(), N5Error>;
 
     fn write_block<T, B: DataBlock<T> + WriteableDataBlock>(
         &self,
         path_name: &str,
         data_attrs: &DatasetAttributes,
         block: &B,
-    ) -> Result<(), Error>;
+    ) -> Result<(), N5Error>;
+
+    /// Write a single block from encoded bytes, as read by `read_block_raw`.
+    ///
+    /// The bytes are not checked against the dataset attributes. By default
+    /// this decodes the block and writes it with `write_block`. Backends that
+    /// store N5-encoded blocks write the bytes as they are instead.
+    fn write_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        data: &[u8],
+    ) -> Result<(), N5Error> {
+        data_type_match!(data_attrs.data_type, {
+            let block = <DefaultBlock as DefaultBlockReader<RsType, _>>::read_block(
+                data,
+                data_attrs,
+                grid_position)?;
+            self.write_block(path_name, data_attrs, &block)
+        })
+    }
 
     /// Delete a block from a dataset.
     ///
@@ -217,7 +439,169 @@ pub trait N5Writer : N5Reader {
         &self,
         path_name: &str,
         grid_position: &[u64],
-    ) -> Result<bool, Error>;
+    ) -> Result<bool, N5Error>;
+
+    /// Change the dimensions of a dataset.
+    ///
+    /// When a dataset shrinks, blocks entirely outside its new dimensions are
+    /// deleted and blocks crossing them are trimmed to its new bounds, except
+    /// for object and varlength blocks. Growing a dataset does not change any
+    /// blocks.
+    ///
+    /// Resizing is not atomic. Blocks are deleted and trimmed before the new
+    /// dimensions are written, so if resizing fails part way the dataset
+    /// keeps its old dimensions, but may have lost data outside the new ones.
+    /// ```
+    /// use n5::prelude::*;
+    /// use n5::smallvec::smallvec;
+    ///
+    /// let n5 = N5InMemory::new();
+    /// let data_attrs = DatasetAttributes::new(
+    ///     smallvec![10, 10],
+    ///     smallvec![4, 4],
+    ///     DataType::UINT8,
+    ///     n5::compression::CompressionType::default(),
+    /// );
+    /// n5.create_dataset("foo", &data_attrs).unwrap();
+    /// n5.resize_dataset("foo", smallvec![10, 20]).unwrap();
+    /// assert_eq!(n5.get_dataset_attributes("foo").unwrap().get_dimensions(), &[10, 20]);
+    /// ```
+    fn resize_dataset(
+        &self,
+        path_name: &str,
+        new_dimensions: GridCoord,
+    ) -> Result<(), N5Error> {
+        let data_attrs = self.get_dataset_attributes(path_name)?;
+        if new_dimensions.len() != data_attrs.get_ndim() {
+            return Err(N5Error::DimensionMismatch {
+                expected: data_attrs.get_ndim(),
+                found: new_dimensions.len(),
+            });
+        }
+        let new_attrs = DatasetAttributes {
+            dimensions: new_dimensions,
+            ..data_attrs.clone()
+        };
+        if data_attrs.dimensions.iter().zip(new_attrs.dimensions.iter()).any(|(old, new)| new < old) {
+            data_type_match!(data_attrs.data_type, {
+                shrink_blocks::<_, RsType>(self, path_name, &data_attrs, &new_attrs)
+            })?;
+        }
+
+        self.set_dataset_attributes(path_name, &new_attrs)
+    }
+}
+
+/// Delete and trim blocks of a dataset for `N5Writer::resize_dataset`.
+fn shrink_blocks<N, T>(
+    n5: &N,
+    path_name: &str,
+    old_attrs: &DatasetAttributes,
+    new_attrs: &DatasetAttributes,
+) -> Result<(), N5Error>
+        where N: N5Writer + ?Sized,
+              VecDataBlock<T>: DataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+              T: ReflectedType {
+    let old_extent = old_attrs.get_grid_extent();
+    let new_extent = new_attrs.get_grid_extent();
+    let ndim = old_extent.len();
+    // Extent of blocks both inside the old and new dimensions.
+    let kept_extent: GridCoord = old_extent.iter().zip(new_extent.iter())
+        .map(|(&o, &n)| o.min(n))
+        .collect();
+
+    // Delete the slab of blocks outside the new extent along each shrunk
+    // axis. Slabs only span the kept extent of earlier axes, so that blocks
+    // outside along several axes are deleted once.
+    for d in (0..ndim).filter(|&d| new_extent[d] < old_extent[d]) {
+        let floor: GridCoord = (0..ndim).map(|i| if i == d {new_extent[d]} else {0}).collect();
+        let ceil: GridCoord = (0..ndim).map(|i| if i < d {kept_extent[i]} else {old_extent[i]}).collect();
+        for grid_position in CoordIterator::floor_ceil(&floor, &ceil) {
+            n5.delete_block(path_name, &grid_position)?;
+        }
+    }
+
+    if old_attrs.data_type == DataType::OBJECT {
+        return Ok(());
+    }
+    // Axes along which blocks on the new boundary extend past it.
+    let trimmed_axes: SmallVec<[usize; 6]> = (0..ndim)
+        .filter(|&d| {
+            let block_size = u64::from(old_attrs.block_size[d]);
+            new_attrs.dimensions[d] < old_attrs.dimensions[d] &&
+                !new_attrs.dimensions[d].is_multiple_of(block_size)
+        })
+        .collect();
+
+    // Trim the layer of blocks on the new boundary along each trimmed axis,
+    // excluding the boundary of earlier trimmed axes, already trimmed.
+    for (k, &d) in trimmed_axes.iter().enumerate() {
+        let mut floor: GridCoord = smallvec::smallvec![0; ndim];
+        floor[d] = new_extent[d] - 1;
+        let mut ceil = kept_extent.clone();
+        for &e in &trimmed_axes[..k] {
+            ceil[e] -= 1;
+        }
+        for grid_position in CoordIterator::floor_ceil(&floor, &ceil) {
+            trim_block::<N, T>(n5, path_name, old_attrs, new_attrs, &grid_position.into())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite a block cropped to the bounds of a shrunk dataset.
+fn trim_block<N, T>(
+    n5: &N,
+    path_name: &str,
+    old_attrs: &DatasetAttributes,
+    new_attrs: &DatasetAttributes,
+    grid_position: &GridCoord,
+) -> Result<(), N5Error>
+        where N: N5Writer + ?Sized,
+              VecDataBlock<T>: DataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+              T: ReflectedType {
+    let block = match n5.read_block::<T>(path_name, old_attrs, grid_position.clone())? {
+        Some(block) => block,
+        None => return Ok(()),
+    };
+    let size = block.get_size();
+    // Varlength blocks do not have an element per voxel, so can not be cropped.
+    if size.iter().map(|&s| s as usize).product::<usize>() != block.get_data().len() {
+        return Ok(());
+    }
+    let new_size: BlockCoord = size.iter()
+        .zip(grid_position.iter())
+        .zip(new_attrs.block_size.iter().zip(new_attrs.dimensions.iter()))
+        .map(|((&s, &c), (&b, &d))| s.min((d - c * u64::from(b)) as u32))
+        .collect();
+    if new_size[..] == size[..] {
+        return Ok(());
+    }
+
+    // Copy the elements inside the new size, first axis fastest.
+    let data = block.get_data();
+    let num_elements = new_size.iter().map(|&s| s as usize).product();
+    let mut cropped = Vec::with_capacity(num_elements);
+    let mut index: SmallVec<[u32; 6]> = smallvec::smallvec![0; new_size.len()];
+    for _ in 0..num_elements {
+        let offset = index.iter().zip(size.iter()).rev()
+            .fold(0, |offset, (&i, &s)| offset * s as usize + i as usize);
+        cropped.push(data[offset].clone());
+
+        for (i, &s) in index.iter_mut().zip(new_size.iter()) {
+            *i += 1;
+            if *i < s {
+                break;
+            }
+            *i = 0;
+        }
+    }
+
+    n5.write_block(
+        path_name,
+        new_attrs,
+        &SliceDataBlock::new(new_size, grid_position.clone(), cropped))
 }
 
 
@@ -233,6 +617,12 @@ pub struct DatasetAttributes {
     data_type: DataType,
     /// Compression scheme for voxel data in each block.
     compression: compression::CompressionType,
+    /// Size of each shard, in voxels, if blocks are stored in shards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shard_size: Option<BlockCoord>,
+    /// Location of the block index within each shard.
+    #[serde(default, skip_serializing_if = "shard::ShardIndexLocation::is_default")]
+    index_location: shard::ShardIndexLocation,
 }
 
 impl DatasetAttributes {
@@ -249,9 +639,28 @@ impl DatasetAttributes {
             block_size,
             data_type,
             compression,
+            shard_size: None,
+            index_location: shard::ShardIndexLocation::default(),
         }
     }
 
+    /// Store blocks of this dataset in shards of `shard_size` voxels, which
+    /// must be a multiple of the block size.
+    pub fn with_shard_size(mut self, shard_size: BlockCoord) -> DatasetAttributes {
+        assert_eq!(self.block_size.len(), shard_size.len(),
+            "Number of shard size dimensions must match number of block size dimensions.");
+        assert!(shard_size.iter().zip(self.block_size.iter()).all(|(&s, &b)| s > 0 && s % b == 0),
+            "Shard size must be a multiple of block size.");
+        self.shard_size = Some(shard_size);
+        self
+    }
+
+    /// Place the block index of each shard at `index_location`.
+    pub fn with_index_location(mut self, index_location: shard::ShardIndexLocation) -> DatasetAttributes {
+        self.index_location = index_location;
+        self
+    }
+
     pub fn get_dimensions(&self) -> &[u64] {
         &self.dimensions
     }
@@ -268,6 +677,15 @@ impl DatasetAttributes {
         &self.compression
     }
 
+    /// Size of each shard, in voxels, or `None` if the dataset is not sharded.
+    pub fn get_shard_size(&self) -> Option<&[u32]> {
+        self.shard_size.as_deref()
+    }
+
+    pub fn get_index_location(&self) -> shard::ShardIndexLocation {
+        self.index_location
+    }
+
     pub fn get_ndim(&self) -> usize {
         self.dimensions.len()
     }
@@ -283,10 +701,21 @@ impl DatasetAttributes {
     }
 
     /// Get the upper bound extent of grid coordinates.
+    /// ```
+    /// use n5::prelude::*;
+    /// use n5::smallvec::smallvec;
+    /// let attrs = DatasetAttributes::new(
+    ///     smallvec![49, 40, 31],
+    ///     smallvec![10, 10, 10],
+    ///     DataType::UINT8,
+    ///     n5::compression::CompressionType::default(),
+    /// );
+    /// assert_eq!(attrs.get_grid_extent().as_slice(), &[5, 4, 4]);
+    /// ```
     pub fn get_grid_extent(&self) -> GridCoord {
         self.dimensions.iter()
             .zip(self.block_size.iter().cloned().map(u64::from))
-            .map(|(d, b)| (d + 1) / b + (if d % b != 0 {1} else {0}))
+            .map(|(d, b)| d / b + (if d % b != 0 {1} else {0}))
             .collect()
     }
 
@@ -306,6 +735,11 @@ impl DatasetAttributes {
         self.get_grid_extent().iter().product()
     }
 
+    /// Iterate over the grid positions of all possible blocks.
+    pub fn coord_iter(&self) -> impl ExactSizeIterator<Item = Vec<u64>> {
+        CoordIterator::new(&self.get_grid_extent())
+    }
+
     /// Check whether a block grid position is in the bounds of this dataset.
     /// ```
     /// use n5::prelude::*;
@@ -325,6 +759,72 @@ impl DatasetAttributes {
             .zip(grid_position.iter())
             .all(|(&bound, &coord)| coord < bound)
     }
+
+    /// Check that a block of element type `T` can be read from or written to
+    /// this dataset. Object datasets are read and written as `u8`.
+    pub fn check_data_type<T: ReflectedType>(&self) -> Result<(), N5Error> {
+        if self.data_type == T::VARIANT ||
+                (self.data_type == DataType::OBJECT && T::VARIANT == DataType::UINT8) {
+            Ok(())
+        } else {
+            Err(N5Error::WrongDataType {
+                expected: self.data_type,
+                found: T::VARIANT,
+            })
+        }
+    }
+}
+
+
+/// Iterates over grid positions within bounds, last axis fastest.
+pub(crate) struct CoordIterator<T: Iterator<Item = Vec<u64>>> {
+    iter: T,
+    accumulator: usize,
+    total_coords: usize,
+}
+
+impl CoordIterator<itertools::MultiProduct<std::ops::Range<u64>>> {
+    pub(crate) fn new(ceil: &[u64]) -> Self {
+        CoordIterator {
+            iter: ceil.iter()
+                .map(|&c| 0..c)
+                .multi_cartesian_product(),
+            accumulator: 0,
+            total_coords: ceil.iter().product::<u64>() as usize,
+        }
+    }
+
+    pub(crate) fn floor_ceil(floor: &[u64], ceil: &[u64]) -> Self {
+        let total_coords = floor.iter()
+                .zip(ceil.iter())
+                .map(|(&f, &c)| c - f)
+                .product::<u64>() as usize;
+        CoordIterator {
+            iter: floor.iter()
+                .zip(ceil.iter())
+                .map(|(&f, &c)| f..c)
+                .multi_cartesian_product(),
+            accumulator: 0,
+            total_coords,
+        }
+    }
+}
+
+impl<T: Iterator<Item = Vec<u64>>> Iterator for CoordIterator<T> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.accumulator += 1;
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total_coords - self.accumulator;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Iterator<Item = Vec<u64>>> ExactSizeIterator for CoordIterator<T> {
 }
 
 
@@ -334,6 +834,23 @@ pub struct BlockHeader {
     num_el: usize,
 }
 
+impl BlockHeader {
+    /// Size of the block, in voxels. Empty for object (mode 2) blocks.
+    pub fn get_size(&self) -> &[u32] {
+        &self.size
+    }
+
+    pub fn get_grid_position(&self) -> &[u64] {
+        &self.grid_position
+    }
+
+    /// Number of elements stored in the block, which for varlength (mode 1)
+    /// blocks may differ from the number of voxels.
+    pub fn get_num_elements(&self) -> usize {
+        self.num_el
+    }
+}
+
 pub trait ReinitDataBlock<T> {
     fn reinitialize(&mut self, header: BlockHeader);
 
@@ -386,6 +903,9 @@ pub struct SliceDataBlock<T: ReflectedType, C> {
 pub type VecDataBlock<T> = SliceDataBlock<T, Vec<T>>;
 
 impl<T: ReflectedType, C> SliceDataBlock<T, C> {
+    /// Create a block of the given size. If the number of elements in `data`
+    /// differs from the number of voxels in `size`, the block is written as a
+    /// varlength (mode 1) block.
     pub fn new(size: BlockCoord, grid_position: GridCoord, data: C) -> SliceDataBlock<T, C> {
         SliceDataBlock {
             data_type: PhantomData,
@@ -395,6 +915,12 @@ impl<T: ReflectedType, C> SliceDataBlock<T, C> {
         }
     }
 
+    /// Create a block without a size, which is written as an object (mode 2)
+    /// block. Typically `T` is `u8` and `data` is an opaque byte payload.
+    pub fn new_object(grid_position: GridCoord, data: C) -> SliceDataBlock<T, C> {
+        SliceDataBlock::new(smallvec![], grid_position, data)
+    }
+
     pub fn into_data(self) -> C {
         self.data
     }
@@ -505,16 +1031,25 @@ pub trait DefaultBlockHeaderReader<R: std::io::Read> {
     fn read_block_header(
         buffer: &mut R,
         grid_position: GridCoord,
-    ) -> std::io::Result<BlockHeader> {
+    ) -> Result<BlockHeader, N5Error> {
 
         let mode = buffer.read_u16::<BigEndian>()?;
+        if mode == 2 {
+            let num_el = buffer.read_u32::<BigEndian>()?;
+            return Ok(BlockHeader {
+                size: smallvec![],
+                grid_position,
+                num_el: num_el as usize,
+            });
+        }
+
         let ndim = buffer.read_u16::<BigEndian>()?;
         let mut size = smallvec![0; ndim as usize];
         buffer.read_u32_into::<BigEndian>(&mut size)?;
         let num_el = match mode {
             0 => size.iter().product(),
             1 => buffer.read_u32::<BigEndian>()?,
-            _ => return Err(Error::new(ErrorKind::InvalidData, "Unsupported block mode"))
+            _ => return Err(N5Error::MalformedHeader(format!("Unsupported block mode {}", mode)))
         };
 
         Ok(BlockHeader {
@@ -531,19 +1066,16 @@ pub trait DefaultBlockReader<T: ReflectedType, R: std::io::Read>: DefaultBlockHe
         mut buffer: R,
         data_attrs: &DatasetAttributes,
         grid_position: GridCoord,
-    ) -> std::io::Result<VecDataBlock<T>>
+    ) -> Result<VecDataBlock<T>, N5Error>
             where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock {
 
-        if data_attrs.data_type != T::VARIANT {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Attempt to create data block for wrong type."))
-        }
+        data_attrs.check_data_type::<T>()?;
         let header = Self::read_block_header(&mut buffer, grid_position)?;
 
         let mut block = T::create_data_block(header);
-        let mut decompressed = data_attrs.compression.decoder(buffer);
-        block.read_data(&mut decompressed)?;
+        let mut decompressed = data_attrs.compression.decoder(buffer)
+            .map_err(N5Error::Codec)?;
+        block.read_data(&mut decompressed).map_err(N5Error::Codec)?;
 
         Ok(block)
     }
@@ -553,18 +1085,15 @@ pub trait DefaultBlockReader<T: ReflectedType, R: std::io::Read>: DefaultBlockHe
         data_attrs: &DatasetAttributes,
         grid_position: GridCoord,
         block: &mut B,
-    ) -> std::io::Result<()> {
+    ) -> Result<(), N5Error> {
 
-        if data_attrs.data_type != T::VARIANT {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Attempt to create data block for wrong type."))
-        }
+        data_attrs.check_data_type::<T>()?;
         let header = Self::read_block_header(&mut buffer, grid_position)?;
 
         block.reinitialize(header);
-        let mut decompressed = data_attrs.compression.decoder(buffer);
-        block.read_data(&mut decompressed)?;
+        let mut decompressed = data_attrs.compression.decoder(buffer)
+            .map_err(N5Error::Codec)?;
+        block.read_data(&mut decompressed).map_err(N5Error::Codec)?;
 
         Ok(())
     }
@@ -576,23 +1105,30 @@ pub trait DefaultBlockWriter<T, W: std::io::Write, B: DataBlock<T> + WriteableDa
         mut buffer: W,
         data_attrs: &DatasetAttributes,
         block: &B,
-    ) -> std::io::Result<()> {
-        let mode: u16 = if block.get_num_elements() == block.get_size().iter().product::<u32>()
-            {0} else {1};
+    ) -> Result<(), N5Error> {
+        let mode: u16 = if block.get_size().is_empty() {
+            2
+        } else if block.get_num_elements() == block.get_size().iter().product::<u32>() {
+            0
+        } else {
+            1
+        };
         buffer.write_u16::<BigEndian>(mode)?;
-        buffer.write_u16::<BigEndian>(data_attrs.get_ndim() as u16)?;
-        for i in block.get_size() {
-            buffer.write_u32::<BigEndian>(*i)?;
+        if mode != 2 {
+            buffer.write_u16::<BigEndian>(data_attrs.get_ndim() as u16)?;
+            for i in block.get_size() {
+                buffer.write_u32::<BigEndian>(*i)?;
+            }
         }
 
         if mode != 0 {
             buffer.write_u32::<BigEndian>(block.get_num_elements())?;
         }
 
-        let mut compressor = data_attrs.compression.encoder(buffer);
-        block.write_data(&mut compressor)?;
-
-        Ok(())
+        let mut compressor = data_attrs.compression.typed_encoder(buffer, data_attrs.data_type)
+            .map_err(N5Error::Codec)?;
+        block.write_data(&mut compressor).map_err(N5Error::Codec)?;
+        compressor.finish().map_err(N5Error::Codec)
     }
 }
 
@@ -1,13 +1,13 @@
 use std::cmp;
-use std::io::{
-    Error,
-    ErrorKind,
-};
 use std::ops::{
     Sub,
 };
+#[cfg(feature = "rayon")]
+use std::sync::{
+    Mutex,
+    PoisonError,
+};
 
-use itertools::Itertools;
 use ndarray::{
     Array,
     ArrayView,
@@ -15,13 +15,17 @@ use ndarray::{
     ShapeBuilder,
     SliceInfo,
 };
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 use crate::{
     BlockCoord,
+    CoordIterator,
     CoordVec,
     DataBlock,
     DatasetAttributes,
     GridCoord,
+    N5Error,
     N5Reader,
     N5Writer,
     ReadableDataBlock,
@@ -58,6 +62,14 @@ impl BoundingBox {
         }
     }
 
+    pub fn get_offset(&self) -> &[u64] {
+        &self.offset
+    }
+
+    pub fn get_size(&self) -> &[u64] {
+        &self.size
+    }
+
     pub fn size_block(&self) -> BlockCoord {
         self.size.iter().map(|n| *n as u32).collect()
     }
@@ -150,12 +162,15 @@ pub trait N5NdarrayReader : N5Reader {
         path_name: &str,
         data_attrs: &DatasetAttributes,
         bbox: &BoundingBox,
-    ) -> Result<ndarray::Array<T, ndarray::Dim<ndarray::IxDynImpl>>, Error>
+    ) -> Result<ndarray::Array<T, ndarray::Dim<ndarray::IxDynImpl>>, N5Error>
         where VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock,
               T: ReflectedType + num_traits::identities::Zero {
 
         if bbox.offset.len() != data_attrs.get_ndim() {
-            return Err(Error::new(ErrorKind::InvalidData, "Wrong number of dimensions"));
+            return Err(N5Error::DimensionMismatch {
+                expected: data_attrs.get_ndim(),
+                found: bbox.offset.len(),
+            });
         }
 
         let mut arr = Array::zeros(bbox.size_ndarray_shape().f());
@@ -178,31 +193,79 @@ pub trait N5NdarrayReader : N5Reader {
             if !is_block { continue; }
 
             if let Some(ref block) = block_buff_opt {
+                assign_block_to_ndarray(&mut arr, bbox, data_attrs, block);
+            }
+        }
 
-                let block_bb = block.get_bounds(data_attrs);
-                let mut read_bb = bbox.clone();
-                read_bb.intersect(&block_bb);
-                let arr_read_bb = read_bb.clone() - &bbox.offset;
-                let block_read_bb = read_bb.clone() - &block_bb.offset;
+        Ok(arr)
+    }
 
-                let arr_slice = arr_read_bb.to_ndarray_slice();
-                let mut arr_view = arr.slice_mut(SliceInfo::<_, IxDyn>::new(arr_slice).unwrap().as_ref());
+    /// Read an abitrary bounding box from an N5 volume in an ndarray, reading
+    /// and decoding blocks in parallel.
+    ///
+    /// Assumes blocks are column-major and returns a column-major ndarray.
+    #[cfg(feature = "rayon")]
+    fn par_read_ndarray<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        bbox: &BoundingBox,
+    ) -> Result<ndarray::Array<T, ndarray::Dim<ndarray::IxDynImpl>>, N5Error>
+        where VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock,
+              T: ReflectedType + num_traits::identities::Zero,
+              Self: Sync {
 
-                let block_slice = block_read_bb.to_ndarray_slice();
+        if bbox.offset.len() != data_attrs.get_ndim() {
+            return Err(N5Error::DimensionMismatch {
+                expected: data_attrs.get_ndim(),
+                found: bbox.offset.len(),
+            });
+        }
 
-                // N5 datasets are stored f-order/column-major.
-                let block_data = ArrayView::from_shape(block_bb.size_ndarray_shape().f(), block.get_data())
-                    .expect("TODO: block ndarray failed");
-                let block_view = block_data.slice(SliceInfo::<_, IxDyn>::new(block_slice).unwrap().as_ref());
+        // Blocks are copied into the array under a lock, since decoding
+        // rather than copying dominates the cost of reading.
+        let arr = Mutex::new(Array::zeros(bbox.size_ndarray_shape().f()));
+        let coords: Vec<Vec<u64>> = data_attrs.bounded_coord_iter(bbox).collect();
 
-                arr_view.assign(&block_view);
+        coords.into_par_iter().try_for_each(|coord| -> Result<(), N5Error> {
+            if let Some(block) = self.read_block::<T>(path_name, data_attrs, coord.into())? {
+                let mut arr = arr.lock().unwrap_or_else(PoisonError::into_inner);
+                assign_block_to_ndarray(&mut arr, bbox, data_attrs, &block);
             }
-        }
+            Ok(())
+        })?;
 
-        Ok(arr)
+        Ok(arr.into_inner().unwrap_or_else(PoisonError::into_inner))
     }
 }
 
+/// Copy the intersection of a block with a bounding box into an array
+/// covering that bounding box.
+pub(crate) fn assign_block_to_ndarray<T: ReflectedType>(
+    arr: &mut Array<T, IxDyn>,
+    bbox: &BoundingBox,
+    data_attrs: &DatasetAttributes,
+    block: &VecDataBlock<T>,
+) {
+    let block_bb = block.get_bounds(data_attrs);
+    let mut read_bb = bbox.clone();
+    read_bb.intersect(&block_bb);
+    let arr_read_bb = read_bb.clone() - &bbox.offset;
+    let block_read_bb = read_bb.clone() - &block_bb.offset;
+
+    let arr_slice = arr_read_bb.to_ndarray_slice();
+    let mut arr_view = arr.slice_mut(SliceInfo::<_, IxDyn>::new(arr_slice).unwrap().as_ref());
+
+    let block_slice = block_read_bb.to_ndarray_slice();
+
+    // N5 datasets are stored f-order/column-major.
+    let block_data = ArrayView::from_shape(block_bb.size_ndarray_shape().f(), block.get_data())
+        .expect("TODO: block ndarray failed");
+    let block_view = block_data.slice(SliceInfo::<_, IxDyn>::new(block_slice).unwrap().as_ref());
+
+    arr_view.assign(&block_view);
+}
+
 impl<T: N5Reader> N5NdarrayReader for T {}
 
 
@@ -216,14 +279,17 @@ pub trait N5NdarrayWriter : N5Writer {
         offset: GridCoord,
         array: A,
         fill_val: T,
-    ) -> Result<(), Error>
+    ) -> Result<(), N5Error>
         where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock + WriteableDataBlock,
               T: ReflectedType + num_traits::identities::Zero,
               A: ndarray::AsArray<'a, T, ndarray::Dim<ndarray::IxDynImpl>> {
 
         let array = array.into();
         if array.ndim() != data_attrs.get_ndim() {
-            return Err(Error::new(ErrorKind::InvalidData, "Wrong number of dimensions"));
+            return Err(N5Error::DimensionMismatch {
+                expected: data_attrs.get_ndim(),
+                found: array.ndim(),
+            });
         }
         let bbox = BoundingBox {
             offset,
@@ -231,82 +297,136 @@ pub trait N5NdarrayWriter : N5Writer {
         };
 
         for coord in data_attrs.bounded_coord_iter(&bbox) {
+            write_ndarray_block(self, path_name, data_attrs, &bbox, &array, coord, &fill_val)?;
+        }
 
-            let grid_coord = GridCoord::from(&coord[..]);
-            let nom_block_bb = data_attrs.get_block_bounds(&grid_coord);
-            let mut write_bb = nom_block_bb.clone();
-            write_bb.intersect(&bbox);
-            let arr_bb = write_bb.clone() - &bbox.offset;
+        Ok(())
+    }
 
-            let arr_slice = arr_bb.to_ndarray_slice();
-            let arr_view = array.slice(SliceInfo::<_, IxDyn>::new(arr_slice).unwrap().as_ref());
+    /// Write an abitrary bounding box from an ndarray into an N5 volume,
+    /// encoding and writing blocks in parallel.
+    ///
+    /// Unlike `write_ndarray`, no blocks are written if any are out of
+    /// bounds.
+    #[cfg(feature = "rayon")]
+    fn par_write_ndarray<'a, T, A>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        offset: GridCoord,
+        array: A,
+        fill_val: T,
+    ) -> Result<(), N5Error>
+        where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+              T: ReflectedType + num_traits::identities::Zero,
+              A: ndarray::AsArray<'a, T, ndarray::Dim<ndarray::IxDynImpl>>,
+              Self: Sync {
 
-            if write_bb == nom_block_bb {
+        let array = array.into();
+        if array.ndim() != data_attrs.get_ndim() {
+            return Err(N5Error::DimensionMismatch {
+                expected: data_attrs.get_ndim(),
+                found: array.ndim(),
+            });
+        }
+        let bbox = BoundingBox {
+            offset,
+            size: array.shape().iter().map(|n| *n as u64).collect(),
+        };
 
-                // No need to read whether there is an extant block if it is
-                // going to be entirely overwrriten.
-                let block_vec = arr_view.t().iter().cloned().collect();
-                let block = VecDataBlock::new(write_bb.size_block(), coord.into(), block_vec);
+        let coords: Vec<Vec<u64>> = data_attrs.bounded_coord_iter(&bbox).collect();
+        if let Some(coord) = coords.iter().find(|c| !data_attrs.in_bounds(&GridCoord::from(&c[..]))) {
+            return Err(N5Error::OutOfBounds(GridCoord::from(&coord[..])));
+        }
 
-                self.write_block(path_name, data_attrs, &block)?;
+        coords.into_par_iter().try_for_each(|coord|
+            write_ndarray_block(self, path_name, data_attrs, &bbox, &array, coord, &fill_val))
+    }
+}
 
-            } else {
+/// Write the part of an array covering a bounding box to a single block,
+/// merging with any existing block data if the block is only partially
+/// covered.
+fn write_ndarray_block<N, T>(
+    n5: &N,
+    path_name: &str,
+    data_attrs: &DatasetAttributes,
+    bbox: &BoundingBox,
+    array: &ArrayView<T, IxDyn>,
+    coord: Vec<u64>,
+    fill_val: &T,
+) -> Result<(), N5Error>
+    where N: N5Writer + ?Sized,
+          VecDataBlock<T>: DataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+          T: ReflectedType {
+
+    let grid_coord = GridCoord::from(&coord[..]);
+    if !data_attrs.in_bounds(&grid_coord) {
+        return Err(N5Error::OutOfBounds(grid_coord));
+    }
+    let nom_block_bb = data_attrs.get_block_bounds(&grid_coord);
+    let mut write_bb = nom_block_bb.clone();
+    write_bb.intersect(bbox);
+    let arr_bb = write_bb.clone() - &bbox.offset;
 
-                let block_opt = self.read_block(path_name, data_attrs, grid_coord.clone())?;
+    let arr_slice = arr_bb.to_ndarray_slice();
+    let arr_view = array.slice(SliceInfo::<_, IxDyn>::new(arr_slice).unwrap().as_ref());
 
-                let (block_bb, mut block_array) = match block_opt {
-                    Some(block) => {
-                        let block_bb = block.get_bounds(data_attrs);
-                        let block_array = Array::from_shape_vec(block_bb.size_ndarray_shape().f(), block.into_data())
-                            .expect("TODO: block ndarray failed");
-                        (block_bb, block_array)
-                    },
-                    None => {
-                        // If no block exists, need to write from its origin.
-                        let mut block_bb = write_bb.clone();
-                        block_bb.size.iter_mut()
-                            .zip(write_bb.offset.iter())
-                            .zip(nom_block_bb.offset.iter())
-                            .for_each(|((s, o), g)| *s += *o - *g);
-                        block_bb.offset = nom_block_bb.offset.clone();
-                        let block_size_usize = block_bb.size_ndarray_shape();
+    if write_bb == nom_block_bb {
 
-                        let block_array = Array::from_elem(&block_size_usize[..], fill_val.clone()).into_dyn();
-                        (block_bb, block_array)
-                    }
-                };
+        // No need to read whether there is an extant block if it is
+        // going to be entirely overwrriten.
+        let block_vec = arr_view.t().iter().cloned().collect();
+        let block = VecDataBlock::new(write_bb.size_block(), coord.into(), block_vec);
 
-                let block_write_bb = write_bb.clone() - &block_bb.offset;
-                let block_slice = block_write_bb.to_ndarray_slice();
-                let mut block_view = block_array.slice_mut(SliceInfo::<_, IxDyn>::new(block_slice).unwrap().as_ref());
+        n5.write_block(path_name, data_attrs, &block)?;
 
-                block_view.assign(&arr_view);
+    } else {
 
-                let block_vec = block_array.t().iter().cloned().collect();
-                let block = VecDataBlock::new(block_bb.size_block(), coord.into(), block_vec);
+        let block_opt = n5.read_block(path_name, data_attrs, grid_coord.clone())?;
 
-                self.write_block(path_name, data_attrs, &block)?;
+        let (block_bb, mut block_array) = match block_opt {
+            Some(block) => {
+                let block_bb = block.get_bounds(data_attrs);
+                let block_array = Array::from_shape_vec(block_bb.size_ndarray_shape().f(), block.into_data())
+                    .expect("TODO: block ndarray failed");
+                (block_bb, block_array)
+            },
+            None => {
+                // If no block exists, need to write from its origin.
+                let mut block_bb = write_bb.clone();
+                block_bb.size.iter_mut()
+                    .zip(write_bb.offset.iter())
+                    .zip(nom_block_bb.offset.iter())
+                    .for_each(|((s, o), g)| *s += *o - *g);
+                block_bb.offset = nom_block_bb.offset.clone();
+                let block_size_usize = block_bb.size_ndarray_shape();
+
+                let block_array = Array::from_elem(&block_size_usize[..], fill_val.clone()).into_dyn();
+                (block_bb, block_array)
             }
-        }
+        };
 
-        Ok(())
+        let block_write_bb = write_bb.clone() - &block_bb.offset;
+        let block_slice = block_write_bb.to_ndarray_slice();
+        let mut block_view = block_array.slice_mut(SliceInfo::<_, IxDyn>::new(block_slice).unwrap().as_ref());
+
+        block_view.assign(&arr_view);
+
+        let block_vec = block_array.t().iter().cloned().collect();
+        let block = VecDataBlock::new(block_bb.size_block(), coord.into(), block_vec);
+
+        n5.write_block(path_name, data_attrs, &block)?;
     }
+
+    Ok(())
 }
 
 impl<T: N5Writer> N5NdarrayWriter for T {}
 
 
 impl DatasetAttributes {
-    pub fn coord_iter(&self) -> impl Iterator<Item = Vec<u64>> + ExactSizeIterator {
-        let coord_ceil = self.get_dimensions().iter()
-            .zip(self.get_block_size().iter())
-            .map(|(&d, &s)| (d + u64::from(s) - 1) / u64::from(s))
-            .collect::<GridCoord>();
-
-        CoordIterator::new(&coord_ceil)
-    }
-
-    pub fn bounded_coord_iter(&self, bbox: &BoundingBox) -> impl Iterator<Item = Vec<u64>> + ExactSizeIterator {
+    pub fn bounded_coord_iter(&self, bbox: &BoundingBox) -> impl ExactSizeIterator<Item = Vec<u64>> {
         let floor_coord: GridCoord = bbox.offset.iter()
             .zip(&self.block_size)
             .map(|(&o, &bs)| o / u64::from(bs))
@@ -314,7 +434,7 @@ impl DatasetAttributes {
         let ceil_coord: GridCoord = bbox.offset.iter()
             .zip(&bbox.size)
             .zip(self.block_size.iter().cloned().map(u64::from))
-            .map(|((&o, &s), bs)| (o + s + bs - 1) / bs)
+            .map(|((&o, &s), bs)| (o + s).div_ceil(bs))
             .collect();
 
         CoordIterator::floor_ceil(&floor_coord, &ceil_coord)
@@ -348,59 +468,6 @@ impl<T: ReflectedType> VecDataBlock<T> {
     }
 }
 
-/// Iterator wrapper to provide exact size when iterating over coordinate
-/// ranges.
-struct CoordIterator<T: Iterator<Item = Vec<u64>>> {
-    iter: T,
-    accumulator: usize,
-    total_coords: usize,
-}
-
-impl CoordIterator<itertools::MultiProduct<std::ops::Range<u64>>> {
-    fn new(ceil: &[u64]) -> Self {
-        CoordIterator {
-            iter: ceil.iter()
-                .map(|&c| 0..c)
-                .multi_cartesian_product(),
-            accumulator: 0,
-            total_coords: ceil.iter().product::<u64>() as usize,
-        }
-    }
-
-    fn floor_ceil(floor: &[u64], ceil: &[u64]) -> Self {
-        let total_coords = floor.iter()
-                .zip(ceil.iter())
-                .map(|(&f, &c)| c - f)
-                .product::<u64>() as usize;
-        CoordIterator {
-            iter: floor.iter()
-                .zip(ceil.iter())
-                .map(|(&f, &c)| f..c)
-                .multi_cartesian_product(),
-            accumulator: 0,
-            total_coords,
-        }
-    }
-}
-
-impl<T: Iterator<Item = Vec<u64>>> Iterator for CoordIterator<T> {
-    type Item = Vec<u64>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.accumulator += 1;
-        self.iter.next()
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = self.total_coords - self.accumulator;
-        (remaining, Some(remaining))
-    }
-}
-
-impl<T: Iterator<Item = Vec<u64>>> ExactSizeIterator for CoordIterator<T> {
-}
-
-
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -415,6 +482,8 @@ pub(crate) mod tests {
             block_size: smallvec![1, 2, 3],
             data_type: DataType::INT16,
             compression: crate::compression::CompressionType::default(),
+            shard_size: None,
+            index_location: Default::default(),
         };
 
         let coords: HashSet<Vec<u64>> = data_attrs.coord_iter().collect();
@@ -17,6 +17,8 @@ pub use crate::{
     DataBlockMetadata,
     DataType,
     GridCoord,
+    N5BlockLister,
+    N5Error,
     N5Lister,
     N5Reader,
     N5Writer,
@@ -25,6 +27,8 @@ pub use crate::{
     VecDataBlock,
 };
 #[doc(no_inline)]
+pub use crate::cache::CachedReader;
+#[doc(no_inline)]
 pub use crate::compression::{
     self,
     CompressionType,
@@ -32,3 +36,17 @@ pub use crate::compression::{
 #[cfg(feature = "filesystem")]
 #[doc(no_inline)]
 pub use crate::filesystem::N5Filesystem;
+#[cfg(feature = "http")]
+#[doc(no_inline)]
+pub use crate::http::N5Http;
+#[doc(no_inline)]
+pub use crate::in_memory::N5InMemory;
+#[cfg(feature = "s3")]
+#[doc(no_inline)]
+pub use crate::s3::N5S3;
+#[cfg(feature = "zarr")]
+#[doc(no_inline)]
+pub use crate::zarr::Zarr2Filesystem;
+#[cfg(feature = "zip")]
+#[doc(no_inline)]
+pub use crate::zip::N5Zip;
@@ -0,0 +1,507 @@
+//! Multiscale pyramids of downsampled datasets.
+//!
+//! Scale levels are sibling datasets `s0`, `s1`, ... in a group, using the
+//! metadata conventions of n5-viewer and BigDataViewer: each downsampled
+//! dataset has a `downsamplingFactors` attribute relative to `s0`, and the
+//! group lists the factors of all levels in its `scales` and
+//! `downsamplingFactors` attributes.
+
+use std::cmp;
+
+use ndarray::{
+    Array,
+    ArrayView,
+    IxDyn,
+    SliceInfo,
+    SliceOrIndex,
+};
+use num_traits::NumCast;
+use serde_json::Value;
+
+use crate::ndarray::{
+    BoundingBox,
+    N5NdarrayReader,
+};
+use crate::{
+    CoordVec,
+    DataBlock,
+    DataType,
+    DatasetAttributes,
+    GridCoord,
+    N5Error,
+    N5Reader,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    WriteableDataBlock,
+};
+
+
+pub mod prelude {
+    pub use super::{
+        Downsampling,
+        N5PyramidReader,
+        N5PyramidWriter,
+        ScaleLevel,
+    };
+}
+
+
+/// Key of the per-dataset and per-group downsampling factor attributes.
+pub const DOWNSAMPLING_FACTORS_KEY: &str = "downsamplingFactors";
+/// Key of the group attribute listing the factors of all scale levels.
+pub const SCALES_KEY: &str = "scales";
+/// Key of the group attribute marking a multiscale group.
+pub const MULTISCALE_KEY: &str = "multiScale";
+
+
+/// Method to reduce each window of voxels to a single downsampled voxel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Downsampling {
+    /// Arithmetic mean, rounded for integer types.
+    Mean,
+    /// Most frequent value, with ties going to the lowest value. Appropriate
+    /// for label volumes.
+    Mode,
+    Min,
+    Max,
+    /// The voxel at the origin of each window.
+    Nearest,
+}
+
+impl Downsampling {
+    fn reduce<'a, T>(self, mut values: impl Iterator<Item = &'a T>) -> Result<T, N5Error>
+        where T: ReflectedType + PartialOrd + NumCast {
+
+        let first = values.next()
+            .ok_or_else(|| N5Error::InvalidMetadata("Downsampling window is empty".to_owned()))?
+            .clone();
+        Ok(match self {
+            Downsampling::Mean => {
+                let mut count = 1.0;
+                let mut sum = first.to_f64().unwrap_or(0.0);
+                for v in values {
+                    sum += v.to_f64().unwrap_or(0.0);
+                    count += 1.0;
+                }
+                let mean = sum / count;
+                let mean = match T::VARIANT {
+                    DataType::FLOAT32 | DataType::FLOAT64 => mean,
+                    _ => mean.round(),
+                };
+                NumCast::from(mean).unwrap_or(first)
+            },
+            Downsampling::Mode => {
+                let mut sorted: Vec<T> = std::iter::once(first).chain(values.cloned()).collect();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+
+                let mut mode = 0;
+                let mut mode_count = 0;
+                let mut run_start = 0;
+                for i in 1..=sorted.len() {
+                    if i == sorted.len() || sorted[i] != sorted[run_start] {
+                        if i - run_start > mode_count {
+                            mode = run_start;
+                            mode_count = i - run_start;
+                        }
+                        run_start = i;
+                    }
+                }
+                sorted.swap_remove(mode)
+            },
+            Downsampling::Min => values.fold(first, |m, v| if *v < m {v.clone()} else {m}),
+            Downsampling::Max => values.fold(first, |m, v| if *v > m {v.clone()} else {m}),
+            Downsampling::Nearest => first,
+        })
+    }
+
+    /// Downsample an array by integer factors along each axis. Windows at the
+    /// far edges may be truncated.
+    fn downsample<T>(self, array: &ArrayView<T, IxDyn>, factors: &[u64]) -> Result<Array<T, IxDyn>, N5Error>
+        where T: ReflectedType + PartialOrd + NumCast {
+
+        let shape: CoordVec<usize> = array.shape().iter()
+            .zip(factors.iter())
+            .map(|(&s, &f)| (s as u64).div_ceil(f) as usize)
+            .collect();
+
+        let mut values = Vec::with_capacity(shape.iter().product());
+        for idx in ndarray::indices(IxDyn(&shape)) {
+            let window: CoordVec<SliceOrIndex> = array.shape().iter()
+                .zip(factors.iter())
+                .enumerate()
+                .map(|(d, (&s, &f))| {
+                    let start = idx[d] * f as usize;
+                    SliceOrIndex::Slice {
+                        start: start as isize,
+                        end: Some(cmp::min(start + f as usize, s) as isize),
+                        step: 1,
+                    }
+                }).collect();
+            let window = array.slice(SliceInfo::<_, IxDyn>::new(window).unwrap().as_ref());
+            values.push(self.reduce(window.iter())?);
+        }
+
+        Ok(Array::from_shape_vec(IxDyn(&shape), values).expect("Downsampled values fill their shape"))
+    }
+}
+
+
+/// A scale level of a multiscale pyramid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaleLevel {
+    /// Path of the scale level dataset.
+    pub path: String,
+    /// Downsampling factors of this level relative to `s0`.
+    pub downsampling_factors: Vec<f64>,
+}
+
+/// Get the parent group of a dataset path.
+fn parent_group(path_name: &str) -> &str {
+    let path_name = path_name.trim_end_matches('/');
+    match path_name.rfind('/') {
+        Some(i) => &path_name[..i],
+        None => "",
+    }
+}
+
+fn scale_path(group: &str, level: usize) -> String {
+    if group.is_empty() {
+        format!("s{}", level)
+    } else {
+        format!("{}/s{}", group, level)
+    }
+}
+
+fn parse_factor_list(value: &Value) -> Option<Vec<Vec<f64>>> {
+    serde_json::from_value(value.clone()).ok()
+}
+
+
+pub trait N5PyramidReader : N5Reader {
+    /// List the scale levels of a multiscale group, from finest to coarsest.
+    ///
+    /// Factors are taken from the group's `scales` or `downsamplingFactors`
+    /// attributes if present, or otherwise from the `downsamplingFactors`
+    /// attributes of the `s0`, `s1`, ... datasets.
+    fn list_scales(&self, group: &str) -> Result<Vec<ScaleLevel>, N5Error> {
+        if !self.exists(group)? {
+            return Err(N5Error::PathNotFound(group.to_owned()));
+        }
+
+        // Groups without any attributes are not an error here.
+        let attrs = self.list_attributes(group).unwrap_or(Value::Null);
+        let group_factors = attrs.get(SCALES_KEY)
+            .and_then(parse_factor_list)
+            .or_else(|| attrs.get(DOWNSAMPLING_FACTORS_KEY).and_then(parse_factor_list));
+
+        if let Some(group_factors) = group_factors {
+            return Ok(group_factors.into_iter()
+                .enumerate()
+                .map(|(level, downsampling_factors)| ScaleLevel {
+                    path: scale_path(group, level),
+                    downsampling_factors,
+                })
+                .collect());
+        }
+
+        let mut levels = Vec::new();
+        loop {
+            let path = scale_path(group, levels.len());
+            if !self.dataset_exists(&path)? {
+                break;
+            }
+            let ndim = self.get_dataset_attributes(&path)?.get_ndim();
+            let downsampling_factors = self.list_attributes(&path)?
+                .get(DOWNSAMPLING_FACTORS_KEY)
+                .and_then(|f| serde_json::from_value(f.clone()).ok())
+                .unwrap_or_else(|| vec![1.0; ndim]);
+            levels.push(ScaleLevel {
+                path,
+                downsampling_factors,
+            });
+        }
+
+        Ok(levels)
+    }
+
+    /// Select the coarsest scale level of a multiscale group that is at
+    /// least as fine as the requested downsampling factors relative to `s0`
+    /// along every axis. If no level is fine enough, the finest is selected.
+    fn select_scale(&self, group: &str, downsampling_factors: &[f64]) -> Result<ScaleLevel, N5Error> {
+        let levels = self.list_scales(group)?;
+        let finest = levels.first()
+            .ok_or_else(|| N5Error::DatasetNotFound(scale_path(group, 0)))?
+            .clone();
+        if finest.downsampling_factors.len() != downsampling_factors.len() {
+            return Err(N5Error::DimensionMismatch {
+                expected: finest.downsampling_factors.len(),
+                found: downsampling_factors.len(),
+            });
+        }
+
+        let volume = |l: &ScaleLevel| l.downsampling_factors.iter().product::<f64>();
+        Ok(levels.into_iter()
+            .filter(|l| l.downsampling_factors.iter()
+                .zip(downsampling_factors.iter())
+                .all(|(&f, &r)| f <= r + f64::EPSILON))
+            .max_by(|a, b| volume(a).partial_cmp(&volume(b)).unwrap_or(cmp::Ordering::Equal))
+            .unwrap_or(finest))
+    }
+}
+
+impl<T: N5Reader> N5PyramidReader for T {}
+
+
+pub trait N5PyramidWriter : N5Writer {
+    /// Build downsampled scale levels `s1`, `s2`, ... next to a source
+    /// dataset `s0`, writing one block of each level at a time.
+    ///
+    /// Each entry of `factors` gives the downsampling factors of a level
+    /// relative to the previous level, which must be positive. Levels have
+    /// the same block size, data type and compression as the source. Returns
+    /// the paths of the created datasets, or `N5Error::InvalidMetadata` if
+    /// the source is not named `s0`.
+    fn write_pyramid<T>(
+        &self,
+        path_name: &str,
+        factors: &[GridCoord],
+        downsampling: Downsampling,
+    ) -> Result<Vec<String>, N5Error>
+        where VecDataBlock<T>: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock + WriteableDataBlock,
+              T: ReflectedType + num_traits::identities::Zero + PartialOrd + NumCast,
+              Self: Sized {
+
+        let group = parent_group(path_name);
+        if path_name.trim_end_matches('/') != scale_path(group, 0) {
+            return Err(N5Error::InvalidMetadata(format!(
+                "Pyramid source must be scale level s0: {}", path_name)));
+        }
+        let mut source_path = path_name.to_owned();
+        let mut source_attrs = self.get_dataset_attributes(path_name)?;
+        let ndim = source_attrs.get_ndim();
+        let mut cumulative: GridCoord = smallvec![1; ndim];
+        let mut scales: Vec<GridCoord> = vec![cumulative.clone()];
+        let mut paths = Vec::with_capacity(factors.len());
+
+        for (level, level_factors) in factors.iter().enumerate() {
+            if level_factors.len() != ndim {
+                return Err(N5Error::DimensionMismatch {
+                    expected: ndim,
+                    found: level_factors.len(),
+                });
+            }
+            if level_factors.contains(&0) {
+                return Err(N5Error::InvalidMetadata(format!(
+                    "Downsampling factors must be positive: {:?}", level_factors)));
+            }
+
+            let dimensions: GridCoord = source_attrs.get_dimensions().iter()
+                .zip(level_factors.iter())
+                .map(|(&d, &f)| d.div_ceil(f))
+                .collect();
+            let target_attrs = DatasetAttributes::new(
+                dimensions,
+                source_attrs.get_block_size().into(),
+                *source_attrs.get_data_type(),
+                source_attrs.get_compression().clone(),
+            );
+            let target_path = scale_path(group, level + 1);
+            self.create_dataset(&target_path, &target_attrs)?;
+
+            for coord in target_attrs.coord_iter() {
+                let grid_position = GridCoord::from(&coord[..]);
+                let target_bb = target_attrs.get_block_bounds(&grid_position);
+                let offset: GridCoord = target_bb.get_offset().iter()
+                    .zip(level_factors.iter())
+                    .map(|(o, f)| o * f)
+                    .collect();
+                let size: GridCoord = target_bb.end()
+                    .zip(level_factors.iter())
+                    .zip(source_attrs.get_dimensions().iter())
+                    .zip(offset.iter())
+                    .map(|(((e, f), &d), o)| cmp::min(e * f, d) - o)
+                    .collect();
+                let source = self.read_ndarray::<T>(
+                    &source_path,
+                    &source_attrs,
+                    &BoundingBox::new(offset, size))?;
+
+                let target = downsampling.downsample(&source.view(), level_factors)?;
+                let block = VecDataBlock::new(
+                    target_bb.size_block(),
+                    grid_position,
+                    target.t().iter().cloned().collect());
+                self.write_block(&target_path, &target_attrs, &block)?;
+            }
+
+            cumulative.iter_mut()
+                .zip(level_factors.iter())
+                .for_each(|(c, f)| *c *= f);
+            self.set_attribute(&target_path, DOWNSAMPLING_FACTORS_KEY.to_owned(), &cumulative)?;
+            scales.push(cumulative.clone());
+
+            source_path = target_path.clone();
+            source_attrs = target_attrs;
+            paths.push(target_path);
+        }
+
+        let mut group_attrs = serde_json::Map::new();
+        group_attrs.insert(SCALES_KEY.to_owned(), serde_json::to_value(&scales)?);
+        group_attrs.insert(DOWNSAMPLING_FACTORS_KEY.to_owned(), serde_json::to_value(&scales)?);
+        group_attrs.insert(MULTISCALE_KEY.to_owned(), Value::Bool(true));
+        self.set_attributes(group, group_attrs)?;
+
+        Ok(paths)
+    }
+}
+
+impl<T: N5Writer> N5PyramidWriter for T {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+    use crate::in_memory::N5InMemory;
+    use crate::ndarray::N5NdarrayWriter;
+
+    fn reduce_all(values: &[u8]) -> Vec<u8> {
+        [
+            Downsampling::Mean,
+            Downsampling::Mode,
+            Downsampling::Min,
+            Downsampling::Max,
+            Downsampling::Nearest,
+        ].iter().map(|d| d.reduce(values.iter()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_reductions() {
+        assert_eq!(reduce_all(&[4, 1, 4, 2]), vec![3, 4, 1, 4, 4]);
+        assert_eq!(reduce_all(&[7, 3, 3, 7]), vec![5, 3, 3, 7, 7]);
+        assert_eq!(reduce_all(&[9]), vec![9, 9, 9, 9, 9]);
+        assert_eq!(Downsampling::Mean.reduce([0.5f32, 1.0].iter()).unwrap(), 0.75);
+        assert!(matches!(
+            Downsampling::Max.reduce(std::iter::empty::<&u8>()),
+            Err(N5Error::InvalidMetadata(_))));
+    }
+
+    #[test]
+    fn test_downsample_truncated_windows() {
+        let array = Array::from_shape_vec(IxDyn(&[3, 5]), (0..15u16).collect()).unwrap();
+        let down = Downsampling::Max.downsample(&array.view(), &[2, 2]).unwrap();
+        assert_eq!(down.shape(), &[2, 3]);
+        assert_eq!(down.into_raw_vec(), vec![6, 8, 9, 11, 13, 14]);
+    }
+
+    #[test]
+    fn test_write_and_select_pyramid() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 7],
+            smallvec![3, 2],
+            DataType::UINT16,
+            CompressionType::default(),
+        );
+        n5.create_dataset("raw/s0", &data_attrs).unwrap();
+        let array = Array::from_shape_fn(IxDyn(&[10, 7]), |idx| (idx[0] * 10 + idx[1]) as u16);
+        n5.write_ndarray("raw/s0", &data_attrs, smallvec![0, 0], &array, 0).unwrap();
+
+        let paths = n5.write_pyramid::<u16>(
+            "raw/s0",
+            &[smallvec![2, 2], smallvec![2, 1]],
+            Downsampling::Min,
+        ).unwrap();
+        assert_eq!(paths, vec!["raw/s1", "raw/s2"]);
+
+        let s2_attrs = n5.get_dataset_attributes("raw/s2").unwrap();
+        assert_eq!(s2_attrs.get_dimensions(), &[3, 4]);
+        assert_eq!(s2_attrs.get_block_size(), &[3, 2]);
+        let s2 = n5.read_ndarray::<u16>("raw/s2", &s2_attrs, &s2_attrs.get_bounds()).unwrap();
+        let expected = Array::from_shape_fn(IxDyn(&[3, 4]), |idx| (idx[0] * 40 + idx[1] * 2) as u16);
+        assert_eq!(s2, expected);
+
+        assert_eq!(
+            n5.list_attributes("raw/s2").unwrap()[DOWNSAMPLING_FACTORS_KEY],
+            serde_json::json!([4, 2]));
+        assert_eq!(
+            n5.list_attributes("raw").unwrap()[SCALES_KEY],
+            serde_json::json!([[1, 1], [2, 2], [4, 2]]));
+
+        let scales = n5.list_scales("raw").unwrap();
+        assert_eq!(scales.len(), 3);
+        assert_eq!(scales[1].path, "raw/s1");
+        assert_eq!(scales[1].downsampling_factors, vec![2.0, 2.0]);
+
+        assert_eq!(n5.select_scale("raw", &[1.0, 1.0]).unwrap().path, "raw/s0");
+        assert_eq!(n5.select_scale("raw", &[3.0, 3.0]).unwrap().path, "raw/s1");
+        assert_eq!(n5.select_scale("raw", &[4.0, 2.0]).unwrap().path, "raw/s2");
+        assert_eq!(n5.select_scale("raw", &[0.5, 0.5]).unwrap().path, "raw/s0");
+        assert!(matches!(
+            n5.select_scale("raw", &[1.0]),
+            Err(N5Error::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn test_write_pyramid_zero_factor() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 7],
+            smallvec![3, 2],
+            DataType::UINT16,
+            CompressionType::default(),
+        );
+        n5.create_dataset("raw/s0", &data_attrs).unwrap();
+
+        assert!(matches!(
+            n5.write_pyramid::<u16>("raw/s0", &[smallvec![2, 0]], Downsampling::Mean),
+            Err(N5Error::InvalidMetadata(_))));
+        assert!(!n5.exists("raw/s1").unwrap());
+    }
+
+    #[test]
+    fn test_write_pyramid_source_not_s0() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 7],
+            smallvec![3, 2],
+            DataType::UINT16,
+            CompressionType::default(),
+        );
+        n5.create_dataset("raw/s0", &data_attrs).unwrap();
+        n5.create_dataset("raw/s2", &data_attrs).unwrap();
+        n5.create_dataset("raw/data", &data_attrs).unwrap();
+
+        for source in &["raw/s2", "raw/data"] {
+            assert!(matches!(
+                n5.write_pyramid::<u16>(source, &[smallvec![2, 2]], Downsampling::Mean),
+                Err(N5Error::InvalidMetadata(_))));
+        }
+        assert!(!n5.exists("raw/s1").unwrap());
+        assert!(n5.list_attributes("raw").ok().and_then(|a| a.get(SCALES_KEY).cloned()).is_none());
+    }
+
+    #[test]
+    fn test_list_scales_from_datasets() {
+        let n5 = N5InMemory::new();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![8, 8, 8],
+            smallvec![4, 4, 4],
+            DataType::UINT8,
+            CompressionType::default(),
+        );
+        n5.create_dataset("em/s0", &data_attrs).unwrap();
+        n5.create_dataset("em/s1", &data_attrs).unwrap();
+        n5.set_attribute("em/s1", DOWNSAMPLING_FACTORS_KEY.to_owned(), [2.0, 2.0, 1.0]).unwrap();
+
+        let scales = n5.list_scales("em").unwrap();
+        assert_eq!(scales, vec![
+            ScaleLevel { path: "em/s0".to_owned(), downsampling_factors: vec![1.0, 1.0, 1.0] },
+            ScaleLevel { path: "em/s1".to_owned(), downsampling_factors: vec![2.0, 2.0, 1.0] },
+        ]);
+        assert_eq!(n5.select_scale("em", &[2.0, 2.0, 2.0]).unwrap().path, "em/s1");
+    }
+}
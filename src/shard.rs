@@ -0,0 +1,501 @@
+//! Sharded block storage, where each shard holds a grid of blocks.
+//!
+//! This follows the N5 sharding proposal. A dataset is sharded if its
+//! attributes have a `shardSize`, in voxels, which must be a multiple of its
+//! `blockSize`. Each shard is stored where an unsharded block at the shard's
+//! grid position would be, and contains encoded blocks, exactly as they would
+//! be stored unsharded, in any order and an index of the offset and length of
+//! each block. The index is a big-endian `u64` pair of offset and length for
+//! each block, ordered with the first dimension fastest. Missing blocks have
+//! an offset and length of `u64::MAX`. The index is at the start or end of
+//! the shard according to the `indexLocation` attribute, by default the end.
+// Only the filesystem backend reads and writes shards.
+#![cfg_attr(not(feature = "filesystem"), allow(dead_code))]
+
+use std::io::{
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+
+use byteorder::{
+    BigEndian,
+    ByteOrder,
+    ReadBytesExt,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    BlockCoord,
+    DatasetAttributes,
+    GridCoord,
+    N5Error,
+};
+
+
+/// Size of each index entry, in bytes.
+const INDEX_ENTRY_SIZE: u64 = 16;
+/// Offset and length of missing blocks.
+const MISSING: u64 = u64::MAX;
+
+/// Location of the block index within each shard.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+pub enum ShardIndexLocation {
+    #[serde(rename = "start", alias = "START")]
+    Start,
+    #[default]
+    #[serde(rename = "end", alias = "END")]
+    End,
+}
+
+impl ShardIndexLocation {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == ShardIndexLocation::default()
+    }
+}
+
+/// Location of a block within a sharded dataset.
+pub(crate) struct ShardPosition {
+    /// Grid position of the shard, in shards.
+    pub shard: GridCoord,
+    /// Index of the block within the shard.
+    pub index: usize,
+}
+
+/// Get the number of blocks along each dimension of a shard.
+fn blocks_per_shard(data_attrs: &DatasetAttributes, shard_size: &[u32]) -> Result<BlockCoord, N5Error> {
+    if shard_size.len() != data_attrs.get_ndim() {
+        return Err(N5Error::DimensionMismatch {
+            expected: data_attrs.get_ndim(),
+            found: shard_size.len(),
+        });
+    }
+    shard_size.iter()
+        .zip(data_attrs.get_block_size().iter())
+        .map(|(&s, &b)| if s > 0 && s % b == 0 {
+            Ok(s / b)
+        } else {
+            Err(N5Error::InvalidMetadata(format!(
+                "Shard size {:?} is not a multiple of block size {:?}", shard_size, data_attrs.get_block_size())))
+        })
+        .collect()
+}
+
+/// Reject sharded datasets in backends which do not read or write shards.
+pub(crate) fn check_unsharded(data_attrs: &DatasetAttributes, backend: &str) -> Result<(), N5Error> {
+    if data_attrs.get_shard_size().is_some() {
+        return Err(N5Error::InvalidMetadata(format!("{} does not support sharded datasets", backend)));
+    }
+    Ok(())
+}
+
+/// Locate a block in its shard, or `None` if the dataset is not sharded.
+pub(crate) fn locate(data_attrs: &DatasetAttributes, grid_position: &[u64]) -> Result<Option<ShardPosition>, N5Error> {
+    let shard_size = match data_attrs.get_shard_size() {
+        Some(shard_size) => shard_size,
+        None => return Ok(None),
+    };
+    let blocks = blocks_per_shard(data_attrs, shard_size)?;
+    if grid_position.len() != blocks.len() {
+        return Err(N5Error::DimensionMismatch {
+            expected: blocks.len(),
+            found: grid_position.len(),
+        });
+    }
+
+    let shard = grid_position.iter().zip(blocks.iter())
+        .map(|(&g, &b)| g / u64::from(b))
+        .collect();
+    let index = grid_position.iter().zip(blocks.iter())
+        .rev()
+        .fold(0, |acc, (&g, &b)| acc * b as usize + (g % u64::from(b)) as usize);
+
+    Ok(Some(ShardPosition {shard, index}))
+}
+
+/// Get the grid positions of blocks in a shard by their index, or `None` if
+/// the dataset is not sharded.
+pub(crate) fn block_positions(
+    data_attrs: &DatasetAttributes,
+    shard: &[u64],
+    indices: impl Iterator<Item = usize>,
+) -> Result<Option<Vec<GridCoord>>, N5Error> {
+    let shard_size = match data_attrs.get_shard_size() {
+        Some(shard_size) => shard_size,
+        None => return Ok(None),
+    };
+    let blocks = blocks_per_shard(data_attrs, shard_size)?;
+
+    Ok(Some(indices.map(|mut index| shard.iter().zip(blocks.iter())
+            .map(|(&s, &b)| {
+                let local = index % b as usize;
+                index /= b as usize;
+                s * u64::from(b) + local as u64
+            })
+            .collect())
+        .collect()))
+}
+
+/// Offsets and lengths of the blocks in a shard.
+pub(crate) struct ShardIndex {
+    entries: Vec<Option<(u64, u64)>>,
+}
+
+impl ShardIndex {
+    fn num_blocks(data_attrs: &DatasetAttributes) -> Result<usize, N5Error> {
+        let shard_size = data_attrs.get_shard_size()
+            .ok_or_else(|| N5Error::InvalidMetadata("Dataset is not sharded".to_owned()))?;
+        Ok(blocks_per_shard(data_attrs, shard_size)?.iter().map(|&b| b as usize).product())
+    }
+
+    /// Read the index of a shard, checking that its blocks are within the
+    /// shard.
+    pub(crate) fn read<R: Read + Seek>(reader: &mut R, data_attrs: &DatasetAttributes) -> Result<ShardIndex, N5Error> {
+        let num_blocks = ShardIndex::num_blocks(data_attrs)?;
+        let index_size = num_blocks as u64 * INDEX_ENTRY_SIZE;
+        let shard_size = reader.seek(SeekFrom::End(0))?;
+        if shard_size < index_size {
+            return Err(N5Error::MalformedHeader("Shard is smaller than its index".to_owned()));
+        }
+        match data_attrs.index_location {
+            ShardIndexLocation::Start => reader.seek(SeekFrom::Start(0))?,
+            ShardIndexLocation::End => reader.seek(SeekFrom::Start(shard_size - index_size))?,
+        };
+
+        let mut entries = Vec::with_capacity(num_blocks);
+        for _ in 0..num_blocks {
+            let offset = reader.read_u64::<BigEndian>()?;
+            let length = reader.read_u64::<BigEndian>()?;
+            if offset == MISSING && length == MISSING {
+                entries.push(None);
+            } else if offset.checked_add(length).is_none_or(|end| end > shard_size) {
+                return Err(N5Error::MalformedHeader("Shard block extends past the shard".to_owned()));
+            } else {
+                entries.push(Some((offset, length)));
+            }
+        }
+
+        Ok(ShardIndex {entries})
+    }
+
+    fn size(&self) -> u64 {
+        self.entries.len() as u64 * INDEX_ENTRY_SIZE
+    }
+
+    /// Total length of the blocks in the shard.
+    fn blocks_size(&self) -> u64 {
+        self.entries.iter().flatten().map(|&(_, length)| length).sum()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut index = vec![0; self.size() as usize];
+        for (entry, block) in index.chunks_exact_mut(INDEX_ENTRY_SIZE as usize).zip(self.entries.iter()) {
+            let (offset, length) = block.unwrap_or((MISSING, MISSING));
+            BigEndian::write_u64(&mut entry[..8], offset);
+            BigEndian::write_u64(&mut entry[8..], length);
+        }
+        index
+    }
+
+    /// Get the offset and length of a block, if it is in the shard.
+    pub(crate) fn get(&self, index: usize) -> Option<(u64, u64)> {
+        self.entries.get(index).cloned().flatten()
+    }
+
+    /// Iterate over the indices of blocks in the shard.
+    pub(crate) fn block_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries.iter().enumerate().filter_map(|(i, e)| e.map(|_| i))
+    }
+}
+
+/// Read the encoded blocks of a shard, by their index.
+pub(crate) fn read_shard<R: Read + Seek>(
+    reader: &mut R,
+    data_attrs: &DatasetAttributes,
+) -> Result<Vec<Option<Vec<u8>>>, N5Error> {
+    let index = ShardIndex::read(reader, data_attrs)?;
+    index.entries.iter()
+        .map(|entry| match entry {
+            Some((offset, length)) => {
+                reader.seek(SeekFrom::Start(*offset))?;
+                let mut block = Vec::with_capacity(*length as usize);
+                reader.by_ref().take(*length).read_to_end(&mut block)?;
+                if block.len() as u64 != *length {
+                    return Err(N5Error::MalformedHeader("Shard block extends past the shard".to_owned()));
+                }
+                Ok(Some(block))
+            },
+            None => Ok(None),
+        })
+        .collect()
+}
+
+/// Encode a shard from encoded blocks by their index, or `None` if there are
+/// no blocks.
+pub(crate) fn write_shard(
+    blocks: &[Option<Vec<u8>>],
+    data_attrs: &DatasetAttributes,
+) -> Option<Vec<u8>> {
+    if blocks.iter().all(Option::is_none) {
+        return None;
+    }
+
+    let index_size = blocks.len() * INDEX_ENTRY_SIZE as usize;
+    let blocks_size: usize = blocks.iter().flatten().map(Vec::len).sum();
+    let mut offset = match data_attrs.index_location {
+        ShardIndexLocation::Start => index_size as u64,
+        ShardIndexLocation::End => 0,
+    };
+    let index = ShardIndex {
+        entries: blocks.iter()
+            .map(|block| block.as_ref().map(|block| {
+                let block_offset = offset;
+                offset += block.len() as u64;
+                (block_offset, block.len() as u64)
+            }))
+            .collect(),
+    }.to_bytes();
+
+    let mut shard = Vec::with_capacity(index_size + blocks_size);
+    if data_attrs.index_location == ShardIndexLocation::Start {
+        shard.extend_from_slice(&index);
+    }
+    for block in blocks.iter().flatten() {
+        shard.extend_from_slice(block);
+    }
+    if data_attrs.index_location == ShardIndexLocation::End {
+        shard.extend_from_slice(&index);
+    }
+
+    Some(shard)
+}
+
+/// Replace or remove a block in an encoded shard, returning the new shard or
+/// `None` if it has no blocks. This rewrites every block of the shard; see
+/// `update_shard_in_place` to only write the changed block.
+pub(crate) fn update_shard<R: Read + Seek>(
+    existing: Option<&mut R>,
+    data_attrs: &DatasetAttributes,
+    index: usize,
+    block: Option<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, N5Error> {
+    let mut blocks = match existing {
+        Some(reader) => read_shard(reader, data_attrs)?,
+        None => vec![None; ShardIndex::num_blocks(data_attrs)?],
+    };
+    blocks[index] = block;
+
+    Ok(write_shard(&blocks, data_attrs))
+}
+
+
+/// Outcome of `update_shard_in_place`.
+pub(crate) enum ShardUpdate {
+    /// The block and index were written to the shard.
+    Written,
+    /// The shard has more unused bytes than block bytes, so should be
+    /// replaced by this compacted shard.
+    Compacted(Vec<u8>),
+    /// The shard has no blocks left, so should be removed.
+    Empty,
+}
+
+/// Replace or remove a block in a shard, which may be empty, without
+/// rewriting its other blocks.
+///
+/// A written block is appended after the other blocks, and only the index is
+/// rewritten, leaving any bytes of the replaced block unused. Once unused
+/// bytes outnumber block bytes, the shard is compacted instead, so that the
+/// cost of writing is amortized over the size of the written blocks.
+pub(crate) fn update_shard_in_place<F: Read + Write + Seek>(
+    file: &mut F,
+    data_attrs: &DatasetAttributes,
+    index: usize,
+    block: Option<Vec<u8>>,
+) -> Result<ShardUpdate, N5Error> {
+    let shard_size = file.seek(SeekFrom::End(0))?;
+    let mut shard_index = if shard_size > 0 {
+        ShardIndex::read(file, data_attrs)?
+    } else {
+        ShardIndex {entries: vec![None; ShardIndex::num_blocks(data_attrs)?]}
+    };
+    let (blocks_start, blocks_end) = match data_attrs.index_location {
+        ShardIndexLocation::Start => (shard_index.size(), shard_size.max(shard_index.size())),
+        ShardIndexLocation::End => (0, shard_size.saturating_sub(shard_index.size())),
+    };
+
+    let block_size = block.as_ref().map_or(0, |block| block.len() as u64);
+    shard_index.entries[index] = block.as_ref().map(|_| (blocks_end, block_size));
+    if shard_index.entries.iter().all(Option::is_none) {
+        return Ok(ShardUpdate::Empty);
+    }
+    let used = shard_index.blocks_size();
+    if (blocks_end + block_size - blocks_start).saturating_sub(used) > used {
+        let mut blocks = read_shard(file, data_attrs)?;
+        blocks[index] = block;
+        return Ok(write_shard(&blocks, data_attrs).map_or(ShardUpdate::Empty, ShardUpdate::Compacted));
+    }
+
+    if let Some(block) = block {
+        file.seek(SeekFrom::Start(blocks_end))?;
+        file.write_all(&block)?;
+    }
+    let index_offset = match data_attrs.index_location {
+        ShardIndexLocation::Start => 0,
+        ShardIndexLocation::End => blocks_end + block_size,
+    };
+    file.seek(SeekFrom::Start(index_offset))?;
+    file.write_all(&shard_index.to_bytes())?;
+
+    Ok(ShardUpdate::Written)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+    use crate::DataType;
+    use std::io::Cursor;
+
+    fn sharded_attributes(index_location: ShardIndexLocation) -> DatasetAttributes {
+        let mut data_attrs = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![2, 3],
+            DataType::UINT8,
+            CompressionType::default(),
+        ).with_shard_size(smallvec![4, 6]);
+        data_attrs.index_location = index_location;
+        data_attrs
+    }
+
+    #[test]
+    fn locate_blocks() {
+        let data_attrs = sharded_attributes(ShardIndexLocation::End);
+        let position = locate(&data_attrs, &[3, 3]).unwrap().unwrap();
+        assert_eq!(position.shard.as_slice(), &[1, 1]);
+        assert_eq!(position.index, 3);
+        let expected: Vec<GridCoord> = vec![
+            smallvec![2, 2],
+            smallvec![3, 2],
+            smallvec![2, 3],
+            smallvec![3, 3],
+        ];
+        assert_eq!(block_positions(&data_attrs, &[1, 1], 0..4).unwrap().unwrap(), expected);
+
+        let unsharded = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![2, 3],
+            DataType::UINT8,
+            CompressionType::default(),
+        );
+        assert!(locate(&unsharded, &[3, 2]).unwrap().is_none());
+    }
+
+    #[test]
+    fn shard_layout() {
+        let data_attrs = sharded_attributes(ShardIndexLocation::End);
+        let shard = write_shard(&[None, Some(vec![1, 2, 3]), None, Some(vec![4])], &data_attrs).unwrap();
+        assert_eq!(shard, vec![
+            1, 2, 3, 4,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 3,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 3,
+            0, 0, 0, 0, 0, 0, 0, 1,
+        ]);
+        assert!(write_shard(&[None, None, None, None], &data_attrs).is_none());
+    }
+
+    #[test]
+    fn update_shards() {
+        for &location in &[ShardIndexLocation::Start, ShardIndexLocation::End] {
+            let data_attrs = sharded_attributes(location);
+            let shard = update_shard::<Cursor<Vec<u8>>>(None, &data_attrs, 2, Some(vec![5, 6])).unwrap().unwrap();
+            let shard = update_shard(Some(&mut Cursor::new(shard)), &data_attrs, 0, Some(vec![7])).unwrap().unwrap();
+            let shard = update_shard(Some(&mut Cursor::new(shard)), &data_attrs, 2, Some(vec![8, 9, 10])).unwrap().unwrap();
+
+            let mut reader = Cursor::new(shard.clone());
+            let index = ShardIndex::read(&mut reader, &data_attrs).unwrap();
+            assert_eq!(index.block_indices().collect::<Vec<_>>(), vec![0, 2]);
+            assert!(index.get(1).is_none());
+            assert_eq!(
+                read_shard(&mut reader, &data_attrs).unwrap(),
+                vec![Some(vec![7]), None, Some(vec![8, 9, 10]), None]);
+
+            let shard = update_shard(Some(&mut Cursor::new(shard)), &data_attrs, 0, None).unwrap().unwrap();
+            assert!(update_shard(Some(&mut Cursor::new(shard)), &data_attrs, 2, None).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn update_shards_in_place() {
+        for &location in &[ShardIndexLocation::Start, ShardIndexLocation::End] {
+            let data_attrs = sharded_attributes(location);
+            let mut file = Cursor::new(Vec::new());
+            assert!(matches!(
+                update_shard_in_place(&mut file, &data_attrs, 2, Some(vec![5, 6])).unwrap(),
+                ShardUpdate::Written));
+            assert!(matches!(
+                update_shard_in_place(&mut file, &data_attrs, 0, Some(vec![7])).unwrap(),
+                ShardUpdate::Written));
+            assert_eq!(
+                read_shard(&mut file, &data_attrs).unwrap(),
+                vec![Some(vec![7]), None, Some(vec![5, 6]), None]);
+
+            // Replacing a block repeatedly compacts the shard rather than
+            // growing it without bound.
+            for i in 0..100u8 {
+                match update_shard_in_place(&mut file, &data_attrs, 2, Some(vec![i; 3])).unwrap() {
+                    ShardUpdate::Written => {},
+                    ShardUpdate::Compacted(compacted) => file = Cursor::new(compacted),
+                    ShardUpdate::Empty => panic!("Shard is not empty"),
+                }
+                assert!(file.get_ref().len() <= 64 + 2 * 4);
+                assert_eq!(
+                    read_shard(&mut file, &data_attrs).unwrap(),
+                    vec![Some(vec![7]), None, Some(vec![i; 3]), None]);
+            }
+
+            assert!(!matches!(
+                update_shard_in_place(&mut file, &data_attrs, 0, None).unwrap(),
+                ShardUpdate::Empty));
+            assert!(matches!(
+                update_shard_in_place(&mut file, &data_attrs, 2, None).unwrap(),
+                ShardUpdate::Empty));
+        }
+    }
+
+    #[test]
+    fn reject_truncated_shards() {
+        let data_attrs = sharded_attributes(ShardIndexLocation::End);
+        let mut shard = write_shard(&[None, Some(vec![1, 2, 3]), None, Some(vec![4])], &data_attrs).unwrap();
+        // Extend the length of the second block past the end of the shard.
+        shard[4 + 16 + 15] = 0xff;
+        assert!(matches!(
+            read_shard(&mut Cursor::new(&shard), &data_attrs),
+            Err(N5Error::MalformedHeader(_))));
+        assert!(matches!(
+            ShardIndex::read(&mut Cursor::new(&shard[4..20]), &data_attrs),
+            Err(N5Error::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn reject_misaligned_shards() {
+        let data_attrs: DatasetAttributes = serde_json::from_value(serde_json::json!({
+            "dimensions": [10, 10],
+            "blockSize": [2, 3],
+            "shardSize": [4, 4],
+            "dataType": "uint8",
+            "compression": {"type": "raw"},
+        })).unwrap();
+        assert!(matches!(locate(&data_attrs, &[0, 0]), Err(N5Error::InvalidMetadata(_))));
+    }
+}
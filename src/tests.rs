@@ -32,6 +32,8 @@ fn doc_spec_dataset_attributes(compression: compression::CompressionType) -> Dat
         block_size: smallvec![1, 2, 3],
         data_type: DataType::INT16,
         compression,
+        shard_size: None,
+        index_location: Default::default(),
     }
 }
 
@@ -71,12 +73,61 @@ pub(crate) fn test_write_doc_spec_block(
     assert_eq!(buff, expected_block);
 }
 
+pub(crate) fn test_read_corrupt_block(
+        block: &[u8],
+        compression: compression::CompressionType,
+) {
+    let data_attrs = doc_spec_dataset_attributes(compression);
+
+    match <DefaultBlock as DefaultBlockReader<i16, _>>::read_block(block, &data_attrs, smallvec![0, 0, 0]) {
+        Err(N5Error::Codec(_)) => {},
+        other => panic!("Expected Codec, got {:?}", other.map(|b| b.into_data())),
+    }
+}
+
+/// Writer accepting a limited number of bytes, then failing.
+struct LimitedWriter(usize);
+
+impl std::io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.0 == 0 && !buf.is_empty() {
+            return Err(std::io::Error::other("Writer is full"));
+        }
+        let len = buf.len().min(self.0);
+        self.0 -= len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn test_write_block_io_error(compression: compression::CompressionType) {
+    let data_attrs = doc_spec_dataset_attributes(compression);
+    let block_in = SliceDataBlock::new(
+        data_attrs.block_size.clone(),
+        smallvec![0, 0, 0],
+        DOC_SPEC_BLOCK_DATA);
+
+    // Accept the 16 byte block header, but not the compressed data.
+    match <DefaultBlock as DefaultBlockWriter<i16, _, _>>::write_block(
+            LimitedWriter(16),
+            &data_attrs,
+            &block_in) {
+        Err(N5Error::Codec(_)) => {},
+        other => panic!("Expected Codec, got {:?}", other),
+    }
+}
+
 pub(crate) fn test_block_compression_rw(compression: compression::CompressionType) {
     let data_attrs = DatasetAttributes {
         dimensions: smallvec![10, 10, 10],
         block_size: smallvec![5, 5, 5],
         data_type: DataType::INT32,
         compression,
+        shard_size: None,
+        index_location: Default::default(),
     };
     let block_data: Vec<i32> = (0..125_i32).collect();
     let block_in = SliceDataBlock::new(
@@ -101,12 +152,33 @@ pub(crate) fn test_block_compression_rw(compression: compression::CompressionTyp
     assert_eq!(block_out.get_data(), &block_data[..]);
 }
 
+pub(crate) fn test_encoder_flush(compression: compression::CompressionType) {
+    use std::io::{Read, Write};
+    use crate::compression::Compression;
+
+    let data: Vec<u8> = (0..1000_u32).map(|i| (i % 7) as u8).collect();
+    let mut encoded: Vec<u8> = Vec::new();
+    let mut encoder = compression.encoder(&mut encoded).expect("encoder failed");
+    encoder.write_all(&data[..500]).expect("write failed");
+    // Flushing must not end the compressed stream.
+    encoder.flush().expect("flush failed");
+    encoder.write_all(&data[500..]).expect("write failed");
+    encoder.finish().expect("finish failed");
+
+    let mut decoded = Vec::new();
+    compression.decoder(&encoded[..]).expect("decoder failed")
+        .read_to_end(&mut decoded).expect("read failed");
+    assert_eq!(decoded, data);
+}
+
 pub(crate) fn test_varlength_block_rw(compression: compression::CompressionType) {
     let data_attrs = DatasetAttributes {
         dimensions: smallvec![10, 10, 10],
         block_size: smallvec![5, 5, 5],
         data_type: DataType::INT32,
         compression,
+        shard_size: None,
+        index_location: Default::default(),
     };
     let block_data: Vec<i32> = (0..100_i32).collect();
     let block_in = SliceDataBlock::new(
@@ -131,6 +203,37 @@ pub(crate) fn test_varlength_block_rw(compression: compression::CompressionType)
     assert_eq!(block_out.get_data(), &block_data[..]);
 }
 
+pub(crate) fn test_object_block_rw(compression: compression::CompressionType) {
+    let data_attrs = DatasetAttributes {
+        dimensions: smallvec![10, 10, 10],
+        block_size: smallvec![5, 5, 5],
+        data_type: DataType::OBJECT,
+        compression,
+        shard_size: None,
+        index_location: Default::default(),
+    };
+    let block_data: Vec<u8> = b"an opaque object payload".to_vec();
+    let block_in = SliceDataBlock::new_object(
+        smallvec![0, 1, 0],
+        &block_data);
+
+    let mut inner: Vec<u8> = Vec::new();
+
+    <DefaultBlock as DefaultBlockWriter<u8, _, _>>::write_block(
+        &mut inner,
+        &data_attrs,
+        &block_in).expect("write_block failed");
+
+    let block_out = <DefaultBlock as DefaultBlockReader<u8, _>>::read_block(
+        &inner[..],
+        &data_attrs,
+        smallvec![0, 1, 0]).expect("read_block failed");
+
+    assert!(block_out.get_size().is_empty());
+    assert_eq!(block_out.get_grid_position(), &[0, 1, 0]);
+    assert_eq!(block_out.get_data(), &block_data[..]);
+}
+
 pub(crate) fn create_backend<N: N5Testable>() {
     let wrapper = N::temp_new_rw();
     let create = wrapper.as_ref();
@@ -170,6 +273,11 @@ pub(crate) fn attributes_rw<N: N5Testable>() {
     // Currently reading attributes that have not been set is an error.
     // Whether this should be the case is still open for decision.
     assert!(create.list_attributes(group).is_err());
+    match create.list_attributes(group) {
+        Err(N5Error::AttributeNotFound { path, key: None }) => assert_eq!(path, group),
+        other => panic!("Expected AttributeNotFound, got {:?}", other),
+    }
+    assert!(matches!(create.list_attributes("missing"), Err(N5Error::PathNotFound(_))));
 
     let attrs_1 = json!({
         "foo": {"bar": 42},
@@ -213,6 +321,162 @@ pub(crate) fn attributes_rw<N: N5Testable>() {
         serde_json::Value::Object(attrs_3));
 }
 
+pub(crate) fn typed_attributes<N: N5Testable>() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Provenance {
+        source: String,
+        resolution: [f64; 3],
+        offset: Option<Vec<i64>>,
+    }
+
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let group = "foo";
+    create.create_group(group)
+        .expect("Failed to create group");
+    let provenance = Provenance {
+        source: "scope".to_owned(),
+        resolution: [4.0, 4.0, 40.0],
+        offset: None,
+    };
+    create.set_attributes(group, serde_json::to_value(&provenance).unwrap().as_object().unwrap().clone())
+        .expect("Failed to set attributes");
+
+    assert_eq!(create.get_attributes_as::<Provenance>(group).unwrap(), provenance);
+    assert_eq!(create.get_attribute::<String>(group, "source").unwrap(), "scope");
+    assert_eq!(create.get_attribute::<Vec<f32>>(group, "resolution").unwrap(), vec![4.0, 4.0, 40.0]);
+    assert_eq!(create.get_attribute::<Option<Vec<i64>>>(group, "offset").unwrap(), None);
+    assert_eq!(create.get_attribute::<Option<u8>>(group, "missing").unwrap(), None);
+
+    match create.get_attribute::<String>(group, "missing") {
+        Err(N5Error::AttributeNotFound { path, key }) => {
+            assert_eq!(path, group);
+            assert_eq!(key.as_deref(), Some("missing"));
+        },
+        other => panic!("Expected AttributeNotFound, got {:?}", other),
+    }
+    match create.get_attribute::<u64>(group, "source") {
+        Err(N5Error::InvalidAttribute { key, .. }) => assert_eq!(key.as_deref(), Some("source")),
+        other => panic!("Expected InvalidAttribute, got {:?}", other),
+    }
+    match create.get_attributes_as::<Vec<u64>>(group) {
+        Err(N5Error::InvalidAttribute { key, .. }) => assert_eq!(key, None),
+        other => panic!("Expected InvalidAttribute, got {:?}", other),
+    }
+}
+
+pub(crate) fn remove_attributes<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let group = "foo";
+    create.create_group(group)
+        .expect("Failed to create group");
+    assert!(create.remove_attributes(group, &["foo"], false).unwrap().is_empty());
+
+    let attrs = json!({
+        "foo": {"bar": 42},
+        "baz": [1, 2, 3],
+        "qux": null,
+    }).as_object().unwrap().clone();
+    create.set_attributes(group, attrs).expect("Failed to set attributes");
+
+    assert_eq!(create.remove_attribute(group, "foo").unwrap(), Some(json!({"bar": 42})));
+    assert_eq!(create.remove_attribute(group, "foo").unwrap(), None);
+    assert_eq!(
+        create.remove_attributes(group, &["baz", "qux", "missing"], false).unwrap(),
+        json!({"baz": [1, 2, 3], "qux": null}).as_object().unwrap().clone());
+    assert_eq!(create.list_attributes(group).unwrap(), json!({}));
+
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10],
+        smallvec![5, 5],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    );
+    create.create_dataset("foo/bar", &data_attrs)
+        .expect("Failed to create dataset");
+    create.set_attribute("foo/bar", "baz".to_owned(), 1).expect("Failed to set attribute");
+
+    match create.remove_attributes("foo/bar", &["baz", "dimensions"], false) {
+        Err(N5Error::MandatoryAttribute { path, key }) => {
+            assert_eq!(path, "foo/bar");
+            assert_eq!(key, "dimensions");
+        },
+        other => panic!("Expected MandatoryAttribute, got {:?}", other),
+    }
+    assert_eq!(create.get_attribute::<u8>("foo/bar", "baz").unwrap(), 1);
+    assert_eq!(create.get_dataset_attributes("foo/bar").unwrap(), data_attrs);
+
+    let removed = create.remove_attributes("foo/bar", &["baz", "dimensions"], true)
+        .expect("Failed to force removal of attributes");
+    assert_eq!(removed["dimensions"], json!([10, 10]));
+    assert!(!create.dataset_exists("foo/bar").unwrap());
+}
+
+pub(crate) fn resize_dataset<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 7],
+        smallvec![4, 4],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    );
+    // Voxel values are unique and independent of the block layout.
+    let block_data = |grid_position: [u64; 2], size: [u32; 2]| -> Vec<i32> {
+        (0..size[1] as u64)
+            .flat_map(|y| (0..size[0] as u64).map(move |x| (x, y)))
+            .map(|(x, y)| (grid_position[0] * 4 + x + 10 * (grid_position[1] * 4 + y)) as i32)
+            .collect()
+    };
+    create.create_dataset("foo", &data_attrs)
+        .expect("Failed to create dataset");
+    for (grid_position, size) in [
+        ([0, 0], [4, 4]), ([1, 0], [4, 4]), ([2, 0], [2, 4]),
+        ([0, 1], [4, 3]), ([1, 1], [4, 3]), ([2, 1], [2, 3]),
+    ] {
+        let block_in = crate::SliceDataBlock::new(
+            size.iter().cloned().collect(),
+            grid_position.iter().cloned().collect(),
+            block_data(grid_position, size));
+        create.write_block("foo", &data_attrs, &block_in)
+            .expect("Failed to write block");
+    }
+
+    assert!(matches!(
+        create.resize_dataset("foo", smallvec![6]),
+        Err(N5Error::DimensionMismatch { expected: 2, found: 1 })));
+
+    create.resize_dataset("foo", smallvec![6, 5])
+        .expect("Failed to resize dataset");
+    let new_attrs = create.get_dataset_attributes("foo").unwrap();
+    assert_eq!(new_attrs.get_dimensions(), &[6, 5]);
+    assert_eq!(new_attrs.get_block_size(), data_attrs.get_block_size());
+
+    let read = create.open_reader();
+    for (grid_position, size) in [([0, 0], [4, 4]), ([1, 0], [2, 4]), ([0, 1], [4, 1]), ([1, 1], [2, 1])] {
+        let block_out = read.read_block::<i32>("foo", &new_attrs, grid_position.iter().cloned().collect())
+            .expect("Failed to read block")
+            .expect("Block is empty");
+        assert_eq!(block_out.get_size(), &size);
+        assert_eq!(block_out.get_data(), &block_data(grid_position, size)[..]);
+    }
+    for grid_position in [[2, 0], [2, 1]] {
+        assert!(read.read_block::<i32>("foo", &data_attrs, grid_position.iter().cloned().collect())
+            .expect("Failed to read block")
+            .is_none());
+    }
+
+    create.resize_dataset("foo", smallvec![6, 20])
+        .expect("Failed to resize dataset");
+    let new_attrs = create.get_dataset_attributes("foo").unwrap();
+    assert_eq!(new_attrs.get_dimensions(), &[6, 20]);
+    let block_out = create.read_block::<i32>("foo", &new_attrs, smallvec![0, 0])
+        .expect("Failed to read block")
+        .expect("Block is empty");
+    assert_eq!(block_out.get_data(), &block_data([0, 0], [4, 4])[..]);
+}
+
 pub(crate) fn create_block_rw<N: N5Testable>() {
     let wrapper = N::temp_new_rw();
     let create = wrapper.as_ref();
@@ -258,6 +522,110 @@ pub(crate) fn create_block_rw<N: N5Testable>() {
     assert_eq!(block_out.get_data(), &block_data[..]);
 }
 
+pub(crate) fn raw_block_rw<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10],
+        smallvec![5, 5],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    );
+    let block_data: Vec<i32> = (0..25_i32).collect();
+    let block_in = crate::SliceDataBlock::new(
+        data_attrs.block_size.clone(),
+        smallvec![1, 0],
+        &block_data);
+    let mut encoded = Vec::new();
+    <DefaultBlock as DefaultBlockWriter<i32, _, _>>::write_block(&mut encoded, &data_attrs, &block_in)
+        .expect("Failed to encode block");
+
+    create.create_dataset("foo/bar", &data_attrs)
+        .expect("Failed to create dataset");
+    create.write_block("foo/bar", &data_attrs, &block_in)
+        .expect("Failed to write block");
+    create.write_block_raw("foo/bar", &data_attrs, smallvec![0, 1], &encoded)
+        .expect("Failed to write raw block");
+
+    let read = create.open_reader();
+    assert_eq!(
+        read.read_block_raw("foo/bar", &data_attrs, smallvec![1, 0]).expect("Failed to read raw block"),
+        Some(encoded));
+    assert_eq!(
+        read.read_block_raw("foo/bar", &data_attrs, smallvec![1, 1]).expect("Failed to read raw block"),
+        None);
+    let block_out = read.read_block::<i32>("foo/bar", &data_attrs, smallvec![0, 1])
+        .expect("Failed to read block")
+        .expect("Block is empty");
+    assert_eq!(block_out.get_data(), &block_data[..]);
+}
+
+pub(crate) fn varlength_block_rw<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10, 10],
+        smallvec![5, 5, 5],
+        DataType::UINT64,
+        crate::compression::CompressionType::default(),
+    );
+    let block_data: Vec<u64> = (0..300_u64).collect();
+    let block_in = crate::SliceDataBlock::new(
+        data_attrs.block_size.clone(),
+        smallvec![1, 0, 1],
+        &block_data);
+
+    create.create_dataset("foo/bar", &data_attrs)
+        .expect("Failed to create dataset");
+    create.write_block("foo/bar", &data_attrs, &block_in)
+        .expect("Failed to write block");
+
+    let read = create.open_reader();
+    let block_out = read.read_block::<u64>("foo/bar", &data_attrs, smallvec![1, 0, 1])
+        .expect("Failed to read block")
+        .expect("Block is empty");
+    assert_eq!(block_out.get_size(), &[5, 5, 5]);
+    assert_eq!(block_out.get_data(), &block_data[..]);
+
+    let mut block_into = VecDataBlock::<u64>::new(smallvec![], smallvec![], vec![]);
+    read.read_block_into("foo/bar", &data_attrs, smallvec![1, 0, 1], &mut block_into)
+        .expect("Failed to read block")
+        .expect("Block is empty");
+    assert_eq!(block_into.get_size(), &[5, 5, 5]);
+    assert_eq!(block_into.get_num_elements(), 300);
+}
+
+pub(crate) fn object_block_rw<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10],
+        smallvec![5, 5],
+        DataType::OBJECT,
+        crate::compression::CompressionType::default(),
+    );
+    let block_data: Vec<u8> = (0..=255_u8).cycle().take(1000).collect();
+    let block_in = crate::SliceDataBlock::new_object(
+        smallvec![1, 1],
+        &block_data);
+
+    create.create_dataset("foo/bar", &data_attrs)
+        .expect("Failed to create dataset");
+    assert_eq!(
+        create.get_dataset_attributes("foo/bar").unwrap().get_data_type(),
+        &DataType::OBJECT);
+    create.write_block("foo/bar", &data_attrs, &block_in)
+        .expect("Failed to write block");
+
+    let read = create.open_reader();
+    let block_out = read.read_block::<u8>("foo/bar", &data_attrs, smallvec![1, 1])
+        .expect("Failed to read block")
+        .expect("Block is empty");
+    assert!(block_out.get_size().is_empty());
+    assert_eq!(block_out.get_grid_position(), &[1, 1]);
+    assert_eq!(block_out.get_data(), &block_data[..]);
+}
+
 pub(crate) fn delete_block<N: N5Testable>() {
     let wrapper = N::temp_new_rw();
     let create = wrapper.as_ref();
@@ -296,32 +664,150 @@ pub(crate) fn delete_block<N: N5Testable>() {
         .is_none());
 }
 
-#[macro_export]
-macro_rules! test_backend {
-    ($backend:ty) => {
-        #[test]
-        fn create_backend() {
-            $crate::tests::create_backend::<$backend>()
-        }
+pub(crate) fn list_blocks<N: N5Testable + N5BlockLister>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![100, 100, 100],
+        smallvec![5, 5, 5],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    );
+    let dataset = "foo/bar";
+    create.create_dataset(dataset, &data_attrs)
+        .expect("Failed to create dataset");
+    assert!(create.list_blocks(dataset).unwrap().is_empty());
 
-        #[test]
-        fn create_dataset() {
-            $crate::tests::create_dataset::<$backend>()
-        }
+    let block_data: Vec<i32> = (0..125_i32).collect();
+    let mut coords: Vec<GridCoord> = vec![
+        smallvec![0, 0, 0],
+        smallvec![1, 2, 3],
+        smallvec![1, 2, 19],
+        smallvec![12, 0, 7],
+    ];
+    for coord in &coords {
+        let block_in = crate::SliceDataBlock::new(
+            data_attrs.block_size.clone(),
+            coord.clone(),
+            &block_data);
+        create.write_block(dataset, &data_attrs, &block_in)
+            .expect("Failed to write block");
+    }
+    // Child groups are not blocks.
+    create.create_group("foo/bar/baz")
+        .expect("Failed to create group");
+    assert!(create.delete_block(dataset, &coords.pop().unwrap()).unwrap());
 
-        #[test]
-        fn attributes_rw() {
-            $crate::tests::attributes_rw::<$backend>()
-        }
+    let mut blocks = create.open_reader().list_blocks(dataset)
+        .expect("Failed to list blocks");
+    blocks.sort();
+    assert_eq!(blocks, coords);
 
-        #[test]
-        fn create_block_rw() {
-            $crate::tests::create_block_rw::<$backend>()
-        }
+    assert!(matches!(create.list_blocks("foo"), Err(N5Error::DatasetNotFound(_))));
+}
 
-        #[test]
-        fn delete_block() {
-            $crate::tests::delete_block::<$backend>()
-        }
+/// Backends which do not support sharding refuse to read or write blocks of
+/// sharded datasets.
+pub(crate) fn sharded_unsupported<N: N5Testable + N5BlockLister>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10],
+        smallvec![5, 5],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    ).with_shard_size(smallvec![10, 10]);
+    let block_data: Vec<i32> = (0..25_i32).collect();
+    let block_in = crate::SliceDataBlock::new(
+        data_attrs.block_size.clone(),
+        smallvec![1, 0],
+        &block_data);
+    let mut encoded = Vec::new();
+    <DefaultBlock as DefaultBlockWriter<i32, _, _>>::write_block(&mut encoded, &data_attrs, &block_in)
+        .expect("Failed to encode block");
+
+    create.create_dataset("foo", &data_attrs)
+        .expect("Failed to create dataset");
+    assert!(matches!(
+        create.write_block("foo", &data_attrs, &block_in),
+        Err(N5Error::InvalidMetadata(_))));
+    assert!(matches!(
+        create.write_block_raw("foo", &data_attrs, smallvec![1, 0], &encoded),
+        Err(N5Error::InvalidMetadata(_))));
+    assert!(matches!(
+        create.read_block::<i32>("foo", &data_attrs, smallvec![1, 0]),
+        Err(N5Error::InvalidMetadata(_))));
+    assert!(matches!(
+        create.read_block_raw("foo", &data_attrs, smallvec![1, 0]),
+        Err(N5Error::InvalidMetadata(_))));
+    assert!(matches!(
+        create.block_metadata("foo", &data_attrs, &[1, 0]),
+        Err(N5Error::InvalidMetadata(_))));
+    assert!(matches!(create.list_blocks("foo"), Err(N5Error::InvalidMetadata(_))));
+}
+
+pub(crate) fn error_causes<N: N5Testable>() {
+    let wrapper = N::temp_new_rw();
+    let create = wrapper.as_ref();
+    let data_attrs = DatasetAttributes::new(
+        smallvec![10, 10, 10],
+        smallvec![5, 5, 5],
+        DataType::INT32,
+        crate::compression::CompressionType::default(),
+    );
+    let block_data: Vec<i32> = (0..125_i32).collect();
+    let block_in = crate::SliceDataBlock::new(
+        data_attrs.block_size.clone(),
+        smallvec![0, 0, 0],
+        &block_data);
+
+    create.create_group("foo")
+        .expect("Failed to create group");
+    match create.get_dataset_attributes("foo/bar") {
+        Err(N5Error::DatasetNotFound(path)) => assert_eq!(path, "foo/bar"),
+        other => panic!("Expected DatasetNotFound, got {:?}", other),
+    }
+    assert!(matches!(create.exists("../foo"), Err(N5Error::PathEscape(_))));
+
+    create.create_dataset("foo/bar", &data_attrs)
+        .expect("Failed to create dataset");
+    create.write_block("foo/bar", &data_attrs, &block_in)
+        .expect("Failed to write block");
+    match create.read_block::<f32>("foo/bar", &data_attrs, smallvec![0, 0, 0]) {
+        Err(N5Error::WrongDataType { expected, found }) => {
+            assert_eq!(expected, DataType::INT32);
+            assert_eq!(found, DataType::FLOAT32);
+        },
+        other => panic!("Expected WrongDataType, got {:?}", other.map(|b| b.map(|b| b.into_data()))),
+    }
+}
+
+/// Generate the backend test suite for a backend type, or only the listed
+/// tests of it for backends to which some of the suite does not apply.
+#[macro_export]
+macro_rules! test_backend {
+    ($backend:ty) => {
+        $crate::test_backend!($backend, [
+            create_backend,
+            create_dataset,
+            attributes_rw,
+            typed_attributes,
+            remove_attributes,
+            resize_dataset,
+            create_block_rw,
+            raw_block_rw,
+            varlength_block_rw,
+            object_block_rw,
+            delete_block,
+            error_causes,
+        ]);
+    };
+    ($backend:ty, [$($test:ident),* $(,)?]) => {
+        $(
+            #[test]
+            fn $test() {
+                $crate::tests::$test::<$backend>()
+            }
+        )*
     };
 }
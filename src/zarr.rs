@@ -0,0 +1,1328 @@
+//! A filesystem-backed Zarr v2 store, accessed through the N5 traits.
+//!
+//! Arrays are presented with N5 axis conventions: the axes of C order arrays
+//! are reversed, so that their chunks are column-major like N5 blocks, while
+//! F order arrays keep their axes. Chunks at the far edges of an array are
+//! cropped when read and padded when written, so that blocks have the same
+//! sizes as in an N5 dataset. Missing chunks are read as `None` regardless of
+//! the array's `fill_value`.
+
+use std::cmp;
+use std::fs;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Error,
+    ErrorKind,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    json,
+    Value,
+};
+use walkdir::WalkDir;
+
+use crate::compression::{
+    Compression,
+    CompressionType,
+};
+use crate::error::Result;
+use crate::filesystem::{
+    merge_top_level,
+    remove_dir_locked,
+    resolve_path,
+    FileLocks,
+    LockMode,
+};
+use crate::{
+    BlockCoord,
+    BlockHeader,
+    DataBlock,
+    DataBlockMetadata,
+    DataType,
+    DatasetAttributes,
+    GridCoord,
+    N5BlockLister,
+    N5Error,
+    N5Lister,
+    N5Reader,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    Version,
+    WriteableDataBlock,
+};
+
+
+/// Name of the array metadata file stored in array dirs.
+const ZARRAY_FILE: &str = ".zarray";
+/// Name of the user attributes file stored in array and group dirs.
+const ZATTRS_FILE: &str = ".zattrs";
+/// Name of the group metadata file stored in group dirs.
+const ZGROUP_FILE: &str = ".zgroup";
+const ZARR_FORMAT: u64 = 2;
+
+
+/// Memory layout of chunks.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum ZarrOrder {
+    C,
+    F,
+}
+
+fn default_dimension_separator() -> String {".".to_owned()}
+
+/// Contents of a `.zarray` file.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+struct ZArrayMetadata {
+    zarr_format: u64,
+    shape: Vec<u64>,
+    chunks: Vec<u32>,
+    dtype: String,
+    compressor: Option<Value>,
+    fill_value: Value,
+    order: ZarrOrder,
+    filters: Option<Vec<Value>>,
+    #[serde(default = "default_dimension_separator")]
+    dimension_separator: String,
+}
+
+impl ZArrayMetadata {
+    fn from_dataset_attributes(data_attrs: &DatasetAttributes) -> Result<ZArrayMetadata> {
+        if data_attrs.get_shard_size().is_some() {
+            return Err(N5Error::InvalidMetadata("Zarr v2 does not support sharding".to_owned()));
+        }
+        Ok(ZArrayMetadata {
+            zarr_format: ZARR_FORMAT,
+            shape: data_attrs.get_dimensions().iter().rev().cloned().collect(),
+            chunks: data_attrs.get_block_size().iter().rev().cloned().collect(),
+            dtype: dtype_from_data_type(*data_attrs.get_data_type())?,
+            compressor: compressor_from_compression(data_attrs.get_compression())?,
+            fill_value: json!(0),
+            order: ZarrOrder::C,
+            filters: None,
+            dimension_separator: default_dimension_separator(),
+        })
+    }
+
+    fn to_dataset_attributes(&self) -> Result<DatasetAttributes> {
+        if self.zarr_format != ZARR_FORMAT {
+            return Err(N5Error::InvalidMetadata(
+                format!("Unsupported Zarr format {}", self.zarr_format)));
+        }
+        if self.shape.len() != self.chunks.len() {
+            return Err(N5Error::InvalidMetadata(
+                "Zarr array shape and chunks have different dimensions".to_owned()));
+        }
+        if self.filters.as_ref().map(|f| !f.is_empty()).unwrap_or(false) {
+            return Err(N5Error::InvalidMetadata("Zarr filters are not supported".to_owned()));
+        }
+
+        let (data_type, _) = parse_dtype(&self.dtype)?;
+        let mut dimensions: GridCoord = self.shape.iter().cloned().collect();
+        let mut block_size: BlockCoord = self.chunks.iter().cloned().collect();
+        if self.order == ZarrOrder::C {
+            dimensions.reverse();
+            block_size.reverse();
+        }
+
+        Ok(DatasetAttributes::new(
+            dimensions,
+            block_size,
+            data_type,
+            compression_from_compressor(self.compressor.as_ref())?,
+        ))
+    }
+
+    /// Get the shape of this array resized to the dimensions of `data_attrs`,
+    /// which may not change any other array metadata.
+    fn resized_shape(&self, data_attrs: &DatasetAttributes) -> Result<Vec<u64>> {
+        let existing = self.to_dataset_attributes()?;
+        if existing.get_ndim() != data_attrs.get_ndim() ||
+                existing.get_block_size() != data_attrs.get_block_size() ||
+                existing.get_data_type() != data_attrs.get_data_type() ||
+                existing.get_compression() != data_attrs.get_compression() ||
+                data_attrs.get_shard_size().is_some() {
+            return Err(N5Error::InvalidMetadata(
+                "Only the shape of an existing Zarr array can be changed".to_owned()));
+        }
+
+        let mut shape: Vec<u64> = data_attrs.get_dimensions().to_vec();
+        if self.order == ZarrOrder::C {
+            shape.reverse();
+        }
+        Ok(shape)
+    }
+
+    /// Whether chunk elements are little-endian, unlike N5 blocks.
+    fn is_little_endian(&self) -> Result<bool> {
+        parse_dtype(&self.dtype).map(|(_, little_endian)| little_endian)
+    }
+
+    fn chunk_key(&self, grid_position: &[u64]) -> String {
+        let mut coords: Vec<String> = grid_position.iter().map(u64::to_string).collect();
+        if self.order == ZarrOrder::C {
+            coords.reverse();
+        }
+        coords.join(&self.dimension_separator)
+    }
+
+    /// Parse a chunk key relative to the array dir into a grid position, or
+    /// `None` if it is not a chunk key.
+    fn grid_position(&self, key: &std::path::Path) -> Option<GridCoord> {
+        let key = key.to_str()?;
+        let mut grid_position = if self.dimension_separator == "/" {
+            key.split(std::path::MAIN_SEPARATOR)
+                .map(|c| c.parse().ok())
+                .collect::<Option<GridCoord>>()?
+        } else {
+            key.split(self.dimension_separator.as_str())
+                .map(|c| c.parse().ok())
+                .collect::<Option<GridCoord>>()?
+        };
+        if grid_position.len() != self.shape.len() {
+            return None;
+        }
+        if self.order == ZarrOrder::C {
+            grid_position.reverse();
+        }
+        Some(grid_position)
+    }
+}
+
+/// Parse a NumPy type string into a data type and whether it is
+/// little-endian.
+fn parse_dtype(dtype: &str) -> Result<(DataType, bool)> {
+    let unsupported = || N5Error::InvalidMetadata(format!("Unsupported Zarr dtype \"{}\"", dtype));
+    let little_endian = match dtype.chars().next() {
+        Some('<') => true,
+        Some('>') | Some('|') => false,
+        _ => return Err(unsupported()),
+    };
+    let data_type = match &dtype[1..] {
+        "u1" => DataType::UINT8,
+        "u2" => DataType::UINT16,
+        "u4" => DataType::UINT32,
+        "u8" => DataType::UINT64,
+        "i1" => DataType::INT8,
+        "i2" => DataType::INT16,
+        "i4" => DataType::INT32,
+        "i8" => DataType::INT64,
+        "f4" => DataType::FLOAT32,
+        "f8" => DataType::FLOAT64,
+        _ => return Err(unsupported()),
+    };
+
+    Ok((data_type, little_endian && data_type.size_of() > 1))
+}
+
+fn dtype_from_data_type(data_type: DataType) -> Result<String> {
+    Ok(match data_type {
+        DataType::UINT8 => "|u1",
+        DataType::UINT16 => "<u2",
+        DataType::UINT32 => "<u4",
+        DataType::UINT64 => "<u8",
+        DataType::INT8 => "|i1",
+        DataType::INT16 => "<i2",
+        DataType::INT32 => "<i4",
+        DataType::INT64 => "<i8",
+        DataType::FLOAT32 => "<f4",
+        DataType::FLOAT64 => "<f8",
+        DataType::OBJECT => return Err(N5Error::InvalidMetadata(
+            "Object datasets are not supported by Zarr".to_owned())),
+    }.to_owned())
+}
+
+/// Map a numcodecs compressor configuration to a compression, via the N5
+/// compression JSON format.
+fn compression_from_compressor(compressor: Option<&Value>) -> Result<CompressionType> {
+    let compressor = match compressor {
+        None | Some(Value::Null) => return Ok(CompressionType::default()),
+        Some(compressor) => compressor,
+    };
+    let id = compressor.get("id").and_then(Value::as_str)
+        .ok_or_else(|| N5Error::InvalidMetadata("Zarr compressor has no id".to_owned()))?;
+    let unsupported = || N5Error::InvalidMetadata(format!("Unsupported Zarr compressor \"{}\"", id));
+
+    let (compression_type, keys): (&str, &[(&str, &str)]) = match id {
+        "blosc" => ("blosc", &[
+            ("cname", "cname"),
+            ("clevel", "clevel"),
+            ("shuffle", "shuffle"),
+            ("blocksize", "blocksize"),
+        ]),
+        "bz2" => ("bzip2", &[("level", "blockSize")]),
+        "gzip" | "zlib" => ("gzip", &[("level", "level")]),
+        "lzma" => {
+            if compressor.get("format").and_then(Value::as_i64).unwrap_or(1) != 1 ||
+                    !compressor.get("filters").map(Value::is_null).unwrap_or(true) {
+                return Err(unsupported());
+            }
+            ("xz", &[("preset", "preset")])
+        },
+        "zstd" => ("zstd", &[("level", "level")]),
+        _ => return Err(unsupported()),
+    };
+
+    let mut n5 = serde_json::Map::new();
+    n5.insert("type".to_owned(), json!(compression_type));
+    if id == "zlib" {
+        n5.insert("useZlib".to_owned(), json!(true));
+    }
+    for (zarr_key, n5_key) in keys {
+        match compressor.get(*zarr_key) {
+            None | Some(Value::Null) => {},
+            Some(v) => { n5.insert((*n5_key).to_owned(), v.clone()); },
+        }
+    }
+
+    // Fails if the compression's feature is not enabled.
+    serde_json::from_value(Value::Object(n5)).map_err(|_| unsupported())
+}
+
+/// Map a compression to a numcodecs compressor configuration, via the N5
+/// compression JSON format.
+fn compressor_from_compression(compression: &CompressionType) -> Result<Option<Value>> {
+    let n5 = serde_json::to_value(compression)?;
+    let get = |key: &str| n5.get(key).cloned().unwrap_or(Value::Null);
+    let compression_type = n5.get("type").and_then(Value::as_str).unwrap_or("");
+
+    Ok(Some(match compression_type {
+        "raw" => return Ok(None),
+        "blosc" => json!({
+            "id": "blosc",
+            "cname": get("cname"),
+            "clevel": get("clevel"),
+            "shuffle": get("shuffle"),
+            "blocksize": get("blocksize"),
+        }),
+        "bzip2" => json!({"id": "bz2", "level": get("blockSize")}),
+        "gzip" if get("useZlib") == Value::Bool(true) => json!({"id": "zlib", "level": get("level")}),
+        "gzip" => json!({"id": "gzip", "level": get("level")}),
+        "xz" => json!({
+            "id": "lzma",
+            "format": 1,
+            "check": -1,
+            "preset": get("preset"),
+            "filters": null,
+        }),
+        "zstd" => json!({"id": "zstd", "level": get("level")}),
+        _ => return Err(N5Error::InvalidMetadata(
+            format!("Compression \"{}\" is not supported by Zarr", compression_type))),
+    }))
+}
+
+/// Copy the region common to two column-major arrays, aligned at their
+/// origins.
+fn copy_common_region(
+    src: &[u8],
+    src_shape: &[usize],
+    dst: &mut [u8],
+    dst_shape: &[usize],
+    elem_size: usize,
+) {
+    let region: Vec<usize> = src_shape.iter().zip(dst_shape.iter())
+        .map(|(&s, &d)| cmp::min(s, d))
+        .collect();
+    if region.contains(&0) {
+        return;
+    }
+    let offset = |idx: &[usize], shape: &[usize]| idx.iter().zip(shape.iter())
+        .rev()
+        .fold(0, |acc, (&i, &s)| acc * s + i) * elem_size;
+    let run = region[0] * elem_size;
+
+    // Copy contiguous runs along the first axis, iterating over the rest.
+    let mut idx = vec![0; region.len()];
+    loop {
+        let src_start = offset(&idx, src_shape);
+        let dst_start = offset(&idx, dst_shape);
+        dst[dst_start..dst_start + run].copy_from_slice(&src[src_start..src_start + run]);
+
+        let mut d = 1;
+        loop {
+            if d == region.len() {
+                return;
+            }
+            idx[d] += 1;
+            if idx[d] < region[d] {
+                break;
+            }
+            idx[d] = 0;
+            d += 1;
+        }
+    }
+}
+
+fn swap_byte_order(data: &mut [u8], elem_size: usize) {
+    if elem_size > 1 {
+        data.chunks_exact_mut(elem_size).for_each(<[u8]>::reverse);
+    }
+}
+
+
+/// Options for opening a `Zarr2Filesystem`, created by
+/// `Zarr2Filesystem::builder`. Files are locked as in an `N5Filesystem`.
+///
+/// ```
+/// use n5::filesystem::LockMode;
+/// use n5::zarr::Zarr2Filesystem;
+/// # let dir = tempdir::TempDir::new("zarr_builder").unwrap();
+/// # let path = dir.path().to_str().unwrap();
+///
+/// let zarr = Zarr2Filesystem::builder(path)
+///     .create(true)
+///     .lock_mode(LockMode::LockFile)
+///     .open()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Zarr2FilesystemBuilder {
+    base_path: PathBuf,
+    create: bool,
+    locks: FileLocks,
+}
+
+impl Zarr2FilesystemBuilder {
+    /// Create the store if none exists, as with
+    /// `Zarr2Filesystem::open_or_create`. Disabled by default.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Set how files are locked. `LockMode::Advisory` by default.
+    pub fn lock_mode(mut self, lock_mode: LockMode) -> Self {
+        self.locks.mode = lock_mode;
+        self
+    }
+
+    /// Set how long to wait for a lock file held by another process; see
+    /// `N5FilesystemBuilder::lock_timeout`. One minute by default.
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.locks.timeout = lock_timeout;
+        self
+    }
+
+    /// Remove lock files last modified longer ago than this; see
+    /// `N5FilesystemBuilder::stale_lock_age`. Disabled by default.
+    pub fn stale_lock_age(mut self, stale_lock_age: Option<Duration>) -> Self {
+        self.locks.stale_age = stale_lock_age;
+        self
+    }
+
+    /// Refuse all `N5Writer` calls with `N5Error::ReadOnly`. Disabled by
+    /// default.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.locks.read_only = read_only;
+        self
+    }
+
+    /// Open the store. Creating a store is an error if it is also read-only.
+    pub fn open(self) -> Result<Zarr2Filesystem> {
+        let store = Zarr2Filesystem {
+            base_path: self.base_path,
+            locks: self.locks,
+        };
+
+        if self.create {
+            store.check_writable("")?;
+            fs::create_dir_all(&store.base_path)?;
+        }
+
+        for file in &[ZGROUP_FILE, ZARRAY_FILE] {
+            if let Some(metadata) = store.read_json(&store.base_path.join(file))? {
+                let format = metadata.get("zarr_format").and_then(Value::as_u64);
+                if format != Some(ZARR_FORMAT) {
+                    return Err(N5Error::InvalidMetadata(
+                        format!("Unsupported Zarr format {:?}", format)));
+                }
+            }
+        }
+
+        if self.create {
+            store.create_group("")?;
+        }
+
+        Ok(store)
+    }
+}
+
+
+/// A filesystem-backed Zarr v2 store.
+#[derive(Clone)]
+pub struct Zarr2Filesystem {
+    base_path: PathBuf,
+    locks: FileLocks,
+}
+
+impl Zarr2Filesystem {
+    /// Open an existing Zarr store by path.
+    pub fn open(base_path: &str) -> Result<Zarr2Filesystem> {
+        Zarr2Filesystem::builder(base_path).open()
+    }
+
+    /// Open an existing Zarr store by path or create one if none exists.
+    pub fn open_or_create(base_path: &str) -> Result<Zarr2Filesystem> {
+        Zarr2Filesystem::builder(base_path).create(true).open()
+    }
+
+    /// Configure how to open a Zarr store by path.
+    pub fn builder(base_path: &str) -> Zarr2FilesystemBuilder {
+        Zarr2FilesystemBuilder {
+            base_path: PathBuf::from(base_path),
+            create: false,
+            locks: FileLocks::new(LockMode::default(), false),
+        }
+    }
+
+    /// Refuse to modify a read-only store.
+    fn check_writable(&self, path_name: &str) -> Result<()> {
+        if self.locks.read_only {
+            Err(N5Error::ReadOnly(path_name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_path(&self, path_name: &str) -> Result<PathBuf> {
+        resolve_path(&self.base_path, path_name)
+    }
+
+    fn get_metadata_path(&self, path_name: &str, file: &str) -> Result<PathBuf> {
+        let mut path = self.get_path(path_name)?;
+        path.push(file);
+        Ok(path)
+    }
+
+    fn read_json(&self, path: &std::path::Path) -> Result<Option<Value>> {
+        let file = match self.locks.open(path, fs::OpenOptions::new().read(true), false) {
+            Ok(file) => file,
+            Err(N5Error::Io(ref e)) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let reader = BufReader::new(file);
+        Ok(Some(serde_json::from_reader(reader)?))
+    }
+
+    fn write_json(&self, path: &std::path::Path, value: &Value) -> Result<()> {
+        let file = self.locks.open(
+            path,
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false),
+            true)?;
+        file.set_len(0)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, value)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn get_array_metadata(&self, path_name: &str) -> Result<ZArrayMetadata> {
+        let value = self.read_json(&self.get_metadata_path(path_name, ZARRAY_FILE)?)?
+            .ok_or_else(|| N5Error::DatasetNotFound(path_name.to_owned()))?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    fn get_chunk_path(&self, path_name: &str, metadata: &ZArrayMetadata, grid_position: &[u64]) -> Result<PathBuf> {
+        let mut path = self.get_path(path_name)?;
+        path.push(metadata.chunk_key(grid_position));
+        Ok(path)
+    }
+
+    /// Read a chunk as the big-endian data of the corresponding N5 block.
+    fn read_chunk<T: ReflectedType>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<(BlockHeader, Vec<u8>)>> {
+        data_attrs.check_data_type::<T>()?;
+        let metadata = self.get_array_metadata(path_name)?;
+        let size = block_size_at(data_attrs, &grid_position);
+
+        let chunk_path = self.get_chunk_path(path_name, &metadata, &grid_position)?;
+        if !chunk_path.is_file() {
+            return Ok(None);
+        }
+        let file = self.locks.open(&chunk_path, fs::OpenOptions::new().read(true), false)?;
+
+        let elem_size = data_attrs.get_data_type().size_of();
+        let chunk_shape: Vec<usize> = data_attrs.get_block_size().iter().map(|&s| s as usize).collect();
+        let mut chunk = Vec::with_capacity(chunk_shape.iter().product::<usize>() * elem_size);
+        data_attrs.get_compression().decoder(BufReader::new(file))
+            .map_err(N5Error::Codec)?
+            .read_to_end(&mut chunk)
+            .map_err(N5Error::Codec)?;
+        if chunk.len() < chunk.capacity() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Zarr chunk is truncated").into());
+        }
+
+        let block_shape: Vec<usize> = size.iter().map(|&s| s as usize).collect();
+        let mut data = if block_shape == chunk_shape {
+            chunk
+        } else {
+            let mut data = vec![0; block_shape.iter().product::<usize>() * elem_size];
+            copy_common_region(&chunk, &chunk_shape, &mut data, &block_shape, elem_size);
+            data
+        };
+        if metadata.is_little_endian()? {
+            swap_byte_order(&mut data, elem_size);
+        }
+
+        let header = BlockHeader {
+            num_el: block_shape.iter().product(),
+            size,
+            grid_position,
+        };
+        Ok(Some((header, data)))
+    }
+}
+
+/// Get the size of the N5 block at a grid position, cropped to the dataset
+/// bounds. Like other backends, blocks out of bounds are not cropped.
+fn block_size_at(data_attrs: &DatasetAttributes, grid_position: &GridCoord) -> BlockCoord {
+    data_attrs.get_block_size().iter()
+        .zip(data_attrs.get_dimensions().iter())
+        .zip(grid_position.iter())
+        .map(|((&b, &d), &g)| match d.checked_sub(g * u64::from(b)) {
+            Some(remaining) if remaining > 0 => cmp::min(u64::from(b), remaining) as u32,
+            _ => b,
+        })
+        .collect()
+}
+
+impl N5Reader for Zarr2Filesystem {
+    /// Zarr stores have no N5 version, so this is always `crate::VERSION`.
+    fn get_version(&self) -> Result<Version> {
+        Ok(crate::VERSION)
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+        self.get_array_metadata(path_name)?.to_dataset_attributes()
+    }
+
+    fn exists(&self, path_name: &str) -> Result<bool> {
+        Ok(self.get_path(path_name)?.is_dir())
+    }
+
+    fn dataset_exists(&self, path_name: &str) -> Result<bool> {
+        Ok(self.get_metadata_path(path_name, ZARRAY_FILE)?.is_file())
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        let metadata = self.get_array_metadata(path_name)?;
+        self.get_chunk_path(path_name, &metadata, grid_position)?.to_str()
+            .map(|s| format!("file://{}", s))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Paths must be UTF-8").into())
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<VecDataBlock<T>>>
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        match self.read_chunk::<T>(path_name, data_attrs, grid_position)? {
+            Some((header, data)) => {
+                let mut block = T::create_data_block(header);
+                block.read_data(&data[..])?;
+                Ok(Some(block))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read_block_into<T: ReflectedType, B: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        block: &mut B,
+    ) -> Result<Option<()>> {
+        match self.read_chunk::<T>(path_name, data_attrs, grid_position)? {
+            Some((header, data)) => {
+                block.reinitialize(header);
+                block.read_data(&data[..])?;
+                Ok(Some(()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        _data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<DataBlockMetadata>> {
+        let metadata = self.get_array_metadata(path_name)?;
+        let chunk_path = self.get_chunk_path(path_name, &metadata, grid_position)?;
+        if chunk_path.is_file() {
+            let metadata = fs::metadata(chunk_path)?;
+            Ok(Some(DataBlockMetadata {
+                created: metadata.created().ok(),
+                accessed: metadata.accessed().ok(),
+                modified: metadata.modified().ok(),
+                size: Some(metadata.len()),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// List the user attributes of a group or array from `.zattrs`.
+    fn list_attributes(&self, path_name: &str) -> Result<Value> {
+        let attr_path = self.get_metadata_path(path_name, ZATTRS_FILE)?;
+        match self.read_json(&attr_path)? {
+            Some(attrs) => Ok(attrs),
+            None => Err(N5Error::missing_attributes(path_name, self.exists(path_name)?)),
+        }
+    }
+}
+
+impl N5Lister for Zarr2Filesystem {
+    /// List all groups and arrays in a group.
+    fn list(&self, path_name: &str) -> Result<Vec<String>> {
+        let mut children = Vec::new();
+        for entry in fs::read_dir(self.get_path(path_name)?)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.join(ZGROUP_FILE).is_file() || path.join(ZARRAY_FILE).is_file() {
+                if let Ok(name) = entry.file_name().into_string() {
+                    children.push(name);
+                }
+            }
+        }
+        Ok(children)
+    }
+}
+
+impl N5BlockLister for Zarr2Filesystem {
+    /// List the grid positions of all chunk files in an array.
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>> {
+        let metadata = self.get_array_metadata(path_name)?;
+        let array_path = self.get_path(path_name)?;
+        let max_depth = if metadata.dimension_separator == "/" {metadata.shape.len()} else {1};
+
+        let mut blocks = Vec::new();
+        for entry in WalkDir::new(&array_path).min_depth(1).max_depth(max_depth) {
+            let entry = entry.map_err(Error::from)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let key = entry.path().strip_prefix(&array_path).expect("Walked entries are in the array dir");
+            if let Some(grid_position) = metadata.grid_position(key) {
+                blocks.push(grid_position);
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+impl N5Writer for Zarr2Filesystem {
+    /// Set user attributes in `.zattrs`.
+    fn set_attributes(
+        &self,
+        path_name: &str,
+        attributes: serde_json::Map<String, Value>,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        let mut file = self.locks.open(
+            &self.get_metadata_path(path_name, ZATTRS_FILE)?,
+            fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false),
+            true)?;
+
+        let mut existing_buf = String::new();
+        file.read_to_string(&mut existing_buf)?;
+        let existing = serde_json::from_str(&existing_buf).unwrap_or_else(|_| json!({}));
+        let mut merged = existing.clone();
+
+        merge_top_level(&mut merged, attributes);
+
+        if merged != existing {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            let writer = BufWriter::new(file);
+            serde_json::to_writer(writer, &merged)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove user attributes from `.zattrs`. Array metadata is stored
+    /// separately in `.zarray`, so it is never removed.
+    fn remove_attributes(
+        &self,
+        path_name: &str,
+        keys: &[&str],
+        force: bool,
+    ) -> Result<serde_json::Map<String, Value>> {
+        self.check_writable(path_name)?;
+        let mut file = match self.locks.open(
+            &self.get_metadata_path(path_name, ZATTRS_FILE)?,
+            fs::OpenOptions::new().read(true).write(true),
+            true)
+        {
+            Ok(file) => file,
+            Err(N5Error::Io(ref e)) if e.kind() == ErrorKind::NotFound => return Ok(serde_json::Map::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut existing_buf = String::new();
+        file.read_to_string(&mut existing_buf)?;
+        let mut attributes = serde_json::from_str(&existing_buf).unwrap_or_else(|_| json!({}));
+
+        let removed = crate::remove_attribute_keys(path_name, &mut attributes, keys, force)?;
+        if !removed.is_empty() {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            let writer = BufWriter::new(file);
+            serde_json::to_writer(writer, &attributes)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Write the array metadata in `.zarray`. New arrays are C order with `.`
+    /// dimension separators. Only the shape of existing arrays is changed,
+    /// keeping the rest of their metadata.
+    fn set_dataset_attributes(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        let new_metadata = ZArrayMetadata::from_dataset_attributes(data_attrs)?;
+        let mut file = self.locks.open(
+            &self.get_metadata_path(path_name, ZARRAY_FILE)?,
+            fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false),
+            true)?;
+
+        let mut existing_buf = String::new();
+        file.read_to_string(&mut existing_buf)?;
+        let zarray = if existing_buf.is_empty() {
+            serde_json::to_value(new_metadata)?
+        } else {
+            let mut zarray: Value = serde_json::from_str(&existing_buf)?;
+            let shape = serde_json::from_value::<ZArrayMetadata>(zarray.clone())?
+                .resized_shape(data_attrs)?;
+            zarray["shape"] = json!(shape);
+            zarray
+        };
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &zarray)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Create a group, and any parent groups, with `.zgroup` files.
+    fn create_group(&self, path_name: &str) -> Result<()> {
+        self.check_writable(path_name)?;
+        let path = self.get_path(path_name)?;
+        fs::create_dir_all(&path)?;
+
+        let mut group = self.base_path.clone();
+        let relative = path.strip_prefix(&self.base_path).expect("Resolved paths are in the store");
+        for component in std::iter::once(None).chain(relative.components().map(Some)) {
+            if let Some(component) = component {
+                group.push(component);
+            }
+            if !group.join(ZGROUP_FILE).is_file() && !group.join(ZARRAY_FILE).is_file() {
+                self.write_json(&group.join(ZGROUP_FILE), &json!({"zarr_format": ZARR_FORMAT}))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_dataset(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        let path = self.get_path(path_name)?;
+        if let Some(parent) = path.parent().and_then(|p| p.strip_prefix(&self.base_path).ok()) {
+            self.create_group(parent.to_str().ok_or_else(
+                || Error::new(ErrorKind::InvalidData, "Paths must be UTF-8"))?)?;
+        }
+        fs::create_dir_all(&path)?;
+        self.set_dataset_attributes(path_name, data_attrs)
+    }
+
+    fn remove(
+        &self,
+        path_name: &str,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        remove_dir_locked(&self.get_path(path_name)?, self.locks)
+    }
+
+    /// Write a block as a chunk, padding it to the full chunk size. Object
+    /// and varlength blocks are not supported.
+    fn write_block<T, B: DataBlock<T> + WriteableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        block: &B,
+    ) -> Result<()> {
+        self.check_writable(path_name)?;
+        let metadata = self.get_array_metadata(path_name)?;
+        let elem_size = data_attrs.get_data_type().size_of();
+        let block_shape: Vec<usize> = block.get_size().iter().map(|&s| s as usize).collect();
+        let chunk_shape: Vec<usize> = data_attrs.get_block_size().iter().map(|&s| s as usize).collect();
+        if block_shape.len() != chunk_shape.len() {
+            return Err(N5Error::DimensionMismatch {
+                expected: chunk_shape.len(),
+                found: block_shape.len(),
+            });
+        }
+        if block.get_num_elements() as usize != block_shape.iter().product::<usize>() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "Zarr does not support varlength blocks").into());
+        }
+
+        let mut data = Vec::with_capacity(chunk_shape.iter().product::<usize>() * elem_size);
+        block.write_data(&mut data)?;
+        let mut chunk = if block_shape == chunk_shape {
+            data
+        } else {
+            let mut chunk = vec![0; data.capacity()];
+            copy_common_region(&data, &block_shape, &mut chunk, &chunk_shape, elem_size);
+            chunk
+        };
+        if metadata.is_little_endian()? {
+            swap_byte_order(&mut chunk, elem_size);
+        }
+
+        let path = self.get_chunk_path(path_name, &metadata, block.get_grid_position())?;
+        fs::create_dir_all(path.parent().expect("Chunks are in an array dir"))?;
+        let file = self.locks.open(
+            &path,
+            fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false),
+            true)?;
+        // Truncate after the lock is acquired, rather than on opening.
+        file.set_len(0)?;
+
+        let mut compressor = data_attrs.compression.typed_encoder(BufWriter::new(file), data_attrs.data_type)
+            .map_err(N5Error::Codec)?;
+        compressor.write_all(&chunk).map_err(N5Error::Codec)?;
+        compressor.finish().map_err(N5Error::Codec)
+    }
+
+    fn delete_block(
+        &self,
+        path_name: &str,
+        grid_position: &[u64],
+    ) -> Result<bool> {
+        self.check_writable(path_name)?;
+        let metadata = self.get_array_metadata(path_name)?;
+        let path = self.get_chunk_path(path_name, &metadata, grid_position)?;
+
+        if path.exists() {
+            let _file = self.locks.open(&path, fs::OpenOptions::new().read(true), true)?;
+            fs::remove_file(&path)?;
+        }
+
+        Ok(true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_backend;
+    use crate::tests::{ContextWrapper, N5Testable};
+    use tempdir::TempDir;
+
+    impl crate::tests::N5Testable for Zarr2Filesystem {
+        type Wrapper = ContextWrapper<TempDir, Zarr2Filesystem>;
+
+        fn temp_new_rw() -> Self::Wrapper {
+            let dir = TempDir::new("rust_n5_zarr_tests").unwrap();
+            let path_str = dir.path().to_str().unwrap();
+            let n5 = Zarr2Filesystem::open_or_create(path_str)
+                .expect("Failed to create Zarr store");
+
+            ContextWrapper {
+                context: dir,
+                n5,
+            }
+        }
+
+        fn open_reader(&self) -> Self {
+            Zarr2Filesystem::open(self.base_path.to_str().unwrap()).unwrap()
+        }
+    }
+
+    // Not applicable from the backend test suite:
+    // - `create_block_rw` writes a block shorter than its size, which Zarr
+    //   chunks can not store.
+    // - `varlength_block_rw` and `object_block_rw`, since Zarr has neither.
+    // - `remove_attributes` expects dataset attributes among the user
+    //   attributes, whereas Zarr keeps them in `.zarray`, so it is replaced
+    //   below.
+    test_backend!(Zarr2Filesystem, [
+        create_backend,
+        create_dataset,
+        attributes_rw,
+        typed_attributes,
+        resize_dataset,
+        raw_block_rw,
+        delete_block,
+        error_causes,
+    ]);
+
+    #[test]
+    fn remove_attributes() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![5, 5],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        create.create_dataset("foo", &data_attrs)
+            .expect("Failed to create dataset");
+        assert!(create.remove_attributes("foo", &["baz"], false).unwrap().is_empty());
+
+        create.set_attribute("foo", "baz".to_owned(), 1).expect("Failed to set attribute");
+        create.set_attribute("foo", "dimensions".to_owned(), "user").expect("Failed to set attribute");
+        assert_eq!(
+            create.remove_attributes("foo", &["baz", "dimensions"], false).unwrap(),
+            json!({"baz": 1, "dimensions": "user"}).as_object().unwrap().clone());
+        assert_eq!(create.list_attributes("foo").unwrap(), json!({}));
+        assert_eq!(create.get_dataset_attributes("foo").unwrap(), data_attrs);
+    }
+
+    #[test]
+    fn list_blocks() {
+        crate::tests::list_blocks::<Zarr2Filesystem>()
+    }
+
+    #[test]
+    fn read_only() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            DataType::UINT8,
+            CompressionType::default(),
+        );
+        create.create_dataset("foo", &data_attrs).expect("Failed to create dataset");
+        create.set_attribute("foo", "bar".to_owned(), 1).expect("Failed to set attribute");
+        let block_in = crate::SliceDataBlock::new(smallvec![2, 2], smallvec![0, 0], &[1u8, 2, 3, 4][..]);
+        create.write_block("foo", &data_attrs, &block_in).expect("Failed to write block");
+
+        let base_path = create.base_path.to_str().unwrap();
+        assert!(matches!(
+            Zarr2Filesystem::builder(base_path).create(true).read_only(true).open(),
+            Err(N5Error::ReadOnly(_))));
+
+        let read = Zarr2Filesystem::builder(base_path)
+            .lock_mode(LockMode::LockFile)
+            .read_only(true)
+            .open()
+            .expect("Failed to open read-only store");
+        assert_eq!(read.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+        assert!(read.read_block::<u8>("foo", &data_attrs, smallvec![0, 0]).unwrap().is_some());
+
+        let is_read_only = |result: Result<_>| matches!(result, Err(N5Error::ReadOnly(_)));
+        assert!(is_read_only(read.set_attribute("foo", "bar".to_owned(), 2)));
+        assert!(is_read_only(read.remove_attribute("foo", "bar").map(|_| ())));
+        assert!(is_read_only(read.create_group("baz")));
+        assert!(is_read_only(read.create_dataset("baz", &data_attrs)));
+        assert!(is_read_only(read.resize_dataset("foo", smallvec![2, 2])));
+        assert!(is_read_only(read.write_block("foo", &data_attrs, &block_in)));
+        assert!(is_read_only(read.delete_block("foo", &[0, 0]).map(|_| ())));
+        assert!(is_read_only(read.remove("foo")));
+
+        assert_eq!(read.get_dataset_attributes("foo").unwrap(), data_attrs);
+        assert!(read.read_block::<u8>("foo", &data_attrs, smallvec![0, 0]).unwrap().is_some());
+        assert!(!read.exists("baz").unwrap());
+        let lock_files = WalkDir::new(base_path).into_iter()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".lock"))
+            .count();
+        assert_eq!(lock_files, 0);
+    }
+
+    #[test]
+    fn lock_modes() {
+        let data_attrs = DatasetAttributes::new(
+            smallvec![4, 4],
+            smallvec![2, 2],
+            DataType::UINT8,
+            CompressionType::default(),
+        );
+
+        for lock_mode in [LockMode::None, LockMode::Advisory, LockMode::LockFile] {
+            let dir = TempDir::new("rust_n5_zarr_tests").unwrap();
+            let zarr = Zarr2Filesystem::builder(dir.path().to_str().unwrap())
+                .create(true)
+                .lock_mode(lock_mode)
+                .open()
+                .expect("Failed to create Zarr store");
+
+            zarr.create_dataset("foo", &data_attrs).expect("Failed to create dataset");
+            let block_in = crate::SliceDataBlock::new(smallvec![2, 2], smallvec![1, 0], &[1u8, 2, 3, 4][..]);
+            zarr.write_block("foo", &data_attrs, &block_in).expect("Failed to write block");
+            let block_out = zarr.read_block::<u8>("foo", &data_attrs, smallvec![1, 0])
+                .expect("Failed to read block")
+                .expect("Block is empty");
+            assert_eq!(block_out.get_data(), &[1, 2, 3, 4]);
+            let expected: Vec<GridCoord> = vec![smallvec![1, 0]];
+            assert_eq!(zarr.list_blocks("foo").unwrap(), expected);
+            assert!(zarr.delete_block("foo", &[1, 0]).unwrap());
+            zarr.set_attribute("foo", "bar".to_owned(), 1).expect("Failed to set attribute");
+            assert_eq!(zarr.get_attribute::<u8>("foo", "bar").unwrap(), 1);
+            zarr.remove("foo").expect("Failed to remove array");
+            assert!(!zarr.exists("foo").unwrap());
+
+            let lock_files = WalkDir::new(dir.path()).into_iter()
+                .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".lock"))
+                .count();
+            assert_eq!(lock_files, 0);
+        }
+    }
+
+    fn write_file(path: PathBuf, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn read_c_order_store() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let store = wrapper.as_ref();
+        // A 3x5 `<u2` array with 2x3 chunks holding `row * 10 + col`, as
+        // written by zarr-python.
+        write_file(store.base_path.join("arr/.zarray"), br#"{
+            "chunks": [2, 3],
+            "compressor": null,
+            "dtype": "<u2",
+            "fill_value": 0,
+            "filters": null,
+            "order": "C",
+            "shape": [3, 5],
+            "zarr_format": 2
+        }"#);
+        let chunk = |rows: std::ops::Range<u16>, cols: std::ops::Range<u16>| -> Vec<u8> {
+            let mut bytes = Vec::new();
+            for r in rows.start..rows.start + 2 {
+                for c in cols.start..cols.start + 3 {
+                    let v = if r < 3 && c < 5 {r * 10 + c} else {0};
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            bytes
+        };
+        write_file(store.base_path.join("arr/0.0"), &chunk(0..2, 0..3));
+        write_file(store.base_path.join("arr/0.1"), &chunk(0..2, 3..5));
+        write_file(store.base_path.join("arr/1.1"), &chunk(2..3, 3..5));
+
+        let data_attrs = store.get_dataset_attributes("arr").unwrap();
+        assert_eq!(data_attrs.get_dimensions(), &[5, 3]);
+        assert_eq!(data_attrs.get_block_size(), &[3, 2]);
+        assert_eq!(data_attrs.get_data_type(), &DataType::UINT16);
+
+        let block = store.read_block::<u16>("arr", &data_attrs, smallvec![0, 0]).unwrap().unwrap();
+        assert_eq!(block.get_size(), &[3, 2]);
+        assert_eq!(block.get_data(), &[0, 1, 2, 10, 11, 12]);
+        let block = store.read_block::<u16>("arr", &data_attrs, smallvec![1, 1]).unwrap().unwrap();
+        assert_eq!(block.get_size(), &[2, 1]);
+        assert_eq!(block.get_data(), &[23, 24]);
+        assert!(store.read_block::<u16>("arr", &data_attrs, smallvec![0, 1]).unwrap().is_none());
+
+        let mut blocks = store.list_blocks("arr").unwrap();
+        blocks.sort();
+        let expected: Vec<GridCoord> = vec![smallvec![0, 0], smallvec![1, 0], smallvec![1, 1]];
+        assert_eq!(blocks, expected);
+    }
+
+    /// Write a 3x2 F order `>i4` array with `/` separators and a single
+    /// chunk, `1/0`.
+    fn write_f_order_array(store: &Zarr2Filesystem) {
+        write_file(store.base_path.join("arr/.zarray"), br#"{
+            "chunks": [2, 2],
+            "compressor": null,
+            "dimension_separator": "/",
+            "dtype": ">i4",
+            "fill_value": null,
+            "filters": null,
+            "order": "F",
+            "shape": [3, 2],
+            "zarr_format": 2
+        }"#);
+        let chunk: Vec<u8> = [5i32, 6, 0, 0].iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+        write_file(store.base_path.join("arr/1/0"), &chunk);
+    }
+
+    #[test]
+    fn read_f_order_store() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let store = wrapper.as_ref();
+        write_f_order_array(store);
+
+        let data_attrs = store.get_dataset_attributes("arr").unwrap();
+        assert_eq!(data_attrs.get_dimensions(), &[3, 2]);
+        let block = store.read_block::<i32>("arr", &data_attrs, smallvec![1, 0]).unwrap().unwrap();
+        assert_eq!(block.get_size(), &[1, 2]);
+        assert_eq!(block.get_data(), &[5, 0]);
+        let expected: Vec<GridCoord> = vec![smallvec![1, 0]];
+        assert_eq!(store.list_blocks("arr").unwrap(), expected);
+    }
+
+    #[test]
+    fn resize_f_order_store() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let store = wrapper.as_ref();
+        write_f_order_array(store);
+
+        store.resize_dataset("arr", smallvec![5, 1]).unwrap();
+        let zarray: Value = serde_json::from_slice(&fs::read(store.base_path.join("arr/.zarray")).unwrap()).unwrap();
+        assert_eq!(zarray, json!({
+            "chunks": [2, 2],
+            "compressor": null,
+            "dimension_separator": "/",
+            "dtype": ">i4",
+            "fill_value": null,
+            "filters": null,
+            "order": "F",
+            "shape": [5, 1],
+            "zarr_format": 2,
+        }));
+
+        let data_attrs = store.get_dataset_attributes("arr").unwrap();
+        assert_eq!(data_attrs.get_dimensions(), &[5, 1]);
+        let block = store.read_block::<i32>("arr", &data_attrs, smallvec![1, 0]).unwrap().unwrap();
+        assert_eq!(block.get_size(), &[2, 1]);
+        assert_eq!(block.get_data(), &[5, 0]);
+
+        let rechunked = DatasetAttributes::new(
+            smallvec![5, 1],
+            smallvec![1, 1],
+            DataType::INT32,
+            CompressionType::default(),
+        );
+        assert!(matches!(
+            store.set_dataset_attributes("arr", &rechunked),
+            Err(N5Error::InvalidMetadata(_))));
+        assert_eq!(store.get_dataset_attributes("arr").unwrap(), data_attrs);
+    }
+
+    #[test]
+    fn write_store() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let store = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![5, 3],
+            smallvec![3, 2],
+            DataType::INT16,
+            CompressionType::default(),
+        );
+        store.create_dataset("foo/arr", &data_attrs).unwrap();
+        assert!(store.base_path.join(".zgroup").is_file());
+        assert!(store.base_path.join("foo/.zgroup").is_file());
+        assert!(!store.base_path.join("foo/arr/.zgroup").is_file());
+        assert_eq!(store.list("foo").unwrap(), vec!["arr"]);
+
+        let zarray: Value = serde_json::from_slice(&fs::read(store.base_path.join("foo/arr/.zarray")).unwrap()).unwrap();
+        assert_eq!(zarray, json!({
+            "zarr_format": 2,
+            "shape": [3, 5],
+            "chunks": [2, 3],
+            "dtype": "<i2",
+            "compressor": null,
+            "fill_value": 0,
+            "order": "C",
+            "filters": null,
+            "dimension_separator": ".",
+        }));
+
+        let block = VecDataBlock::<i16>::new(smallvec![2, 1], smallvec![1, 1], vec![-1, 2]);
+        store.write_block("foo/arr", &data_attrs, &block).unwrap();
+        let chunk = fs::read(store.base_path.join("foo/arr/1.1")).unwrap();
+        assert_eq!(chunk, vec![0xff, 0xff, 0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let block_out = store.read_block::<i16>("foo/arr", &data_attrs, smallvec![1, 1]).unwrap().unwrap();
+        assert_eq!(block_out.get_data(), &[-1, 2]);
+    }
+
+    #[test]
+    fn compressor_mapping() {
+        let compressors = vec![
+            json!({"id": "gzip", "level": 5}),
+            json!({"id": "zlib", "level": 1}),
+            json!({"id": "bz2", "level": 9}),
+            json!({"id": "lzma", "format": 1, "check": -1, "preset": 6, "filters": null}),
+            json!({"id": "zstd", "level": 3}),
+            json!({"id": "blosc", "cname": "zstd", "clevel": 5, "shuffle": 2, "blocksize": 0}),
+        ];
+        for compressor in compressors {
+            let compression = compression_from_compressor(Some(&compressor)).unwrap();
+            assert_eq!(compressor_from_compression(&compression).unwrap(), Some(compressor));
+        }
+
+        assert_eq!(compression_from_compressor(None).unwrap(), CompressionType::default());
+        assert!(matches!(
+            compression_from_compressor(Some(&json!({"id": "lz4", "acceleration": 1}))),
+            Err(N5Error::InvalidMetadata(_))));
+    }
+
+    #[test]
+    fn compressed_rw() {
+        let wrapper = Zarr2Filesystem::temp_new_rw();
+        let store = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10, 10],
+            smallvec![5, 5, 5],
+            DataType::FLOAT64,
+            compression_from_compressor(Some(&json!({"id": "gzip", "level": 5}))).unwrap(),
+        );
+        store.create_dataset("arr", &data_attrs).unwrap();
+        let block_data: Vec<f64> = (0..125).map(f64::from).collect();
+        let block = VecDataBlock::<f64>::new(smallvec![5, 5, 5], smallvec![1, 0, 1], block_data.clone());
+        store.write_block("arr", &data_attrs, &block).unwrap();
+
+        let read = store.open_reader();
+        let read_attrs = read.get_dataset_attributes("arr").unwrap();
+        assert_eq!(read_attrs, data_attrs);
+        let block_out = read.read_block::<f64>("arr", &read_attrs, smallvec![1, 0, 1]).unwrap().unwrap();
+        assert_eq!(block_out.get_data(), &block_data[..]);
+    }
+}
@@ -0,0 +1,653 @@
+//! An N5 container stored in a single zip archive.
+//!
+//! Entries are laid out exactly as files are in an `N5Filesystem` container,
+//! so a zip of an N5 directory can be opened directly. Blocks are already
+//! compressed by their dataset's compression, so entries are stored without
+//! zip compression.
+//!
+//! Zip archives can only be appended to. Overwriting a block or attributes
+//! appends a new entry that supersedes the old one without reclaiming its
+//! space, and blocks and groups can not be removed.
+
+use std::collections::BTreeSet;
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::io::{
+    BufReader,
+    Error,
+    ErrorKind,
+    Read,
+    Write,
+};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    PoisonError,
+};
+
+use ::zip::{
+    result::ZipError,
+    write::FileOptions,
+    CompressionMethod,
+    ZipArchive,
+    ZipWriter,
+};
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::in_memory::normalize_path;
+use crate::shard::check_unsharded;
+use crate::{
+    is_version_compatible,
+    DataBlock,
+    DataBlockMetadata,
+    DatasetAttributes,
+    DefaultBlockReader,
+    DefaultBlockWriter,
+    GridCoord,
+    N5BlockLister,
+    N5Error,
+    N5Lister,
+    N5Reader,
+    N5Writer,
+    ReadableDataBlock,
+    ReflectedType,
+    ReinitDataBlock,
+    VecDataBlock,
+    Version,
+    WriteableDataBlock,
+};
+
+
+/// Name of the attributes file stored in the container root and dataset dirs.
+const ATTRIBUTES_FILE: &str = "attributes.json";
+
+
+/// The open archive, which is either being read or appended to.
+enum Archive {
+    Reading(ZipArchive<File>),
+    Writing(ZipWriter<File>),
+    /// Left in place if switching between reading and writing fails.
+    Closed,
+}
+
+impl Archive {
+    /// Get the archive for reading, writing its central directory first if
+    /// it is being appended to.
+    fn reading(&mut self) -> Result<&mut ZipArchive<File>> {
+        match std::mem::replace(self, Archive::Closed) {
+            Archive::Reading(archive) => *self = Archive::Reading(archive),
+            Archive::Writing(mut writer) => {
+                let file = writer.finish().map_err(zip_error)?;
+                *self = Archive::Reading(ZipArchive::new(file).map_err(zip_error)?);
+            },
+            Archive::Closed => return Err(closed_error()),
+        }
+        match self {
+            Archive::Reading(archive) => Ok(archive),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get the archive for appending entries.
+    fn writing(&mut self) -> Result<&mut ZipWriter<File>> {
+        match std::mem::replace(self, Archive::Closed) {
+            Archive::Reading(archive) => {
+                *self = Archive::Writing(ZipWriter::new_append(archive.into_inner()).map_err(zip_error)?);
+            },
+            Archive::Writing(writer) => *self = Archive::Writing(writer),
+            Archive::Closed => return Err(closed_error()),
+        }
+        match self {
+            Archive::Writing(writer) => Ok(writer),
+            _ => unreachable!(),
+        }
+    }
+}
+
+struct ZipState {
+    archive: Archive,
+    /// Names of all entries in the archive, including those not yet in its
+    /// central directory.
+    entries: BTreeSet<String>,
+}
+
+fn zip_error(e: ZipError) -> N5Error {
+    match e {
+        ZipError::Io(e) => N5Error::Io(e),
+        e => N5Error::Io(e.into()),
+    }
+}
+
+fn closed_error() -> N5Error {
+    Error::other("Zip archive was closed by an earlier error").into()
+}
+
+/// Join a normalized path name and a name within it into an entry name.
+fn entry_name(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+fn block_entry_name(path: &str, grid_position: &[u64]) -> String {
+    let coords: Vec<String> = grid_position.iter().map(u64::to_string).collect();
+    entry_name(path, &coords.join("/"))
+}
+
+
+/// An N5 container stored in a single zip archive.
+///
+/// Clones of an `N5Zip` share the same open archive.
+#[derive(Clone)]
+pub struct N5Zip {
+    path: PathBuf,
+    writable: bool,
+    state: Arc<Mutex<ZipState>>,
+}
+
+impl N5Zip {
+    /// Open an existing zip container by path for reading.
+    pub fn open(path: &str) -> Result<N5Zip> {
+        let archive = ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+        let entries = archive.file_names().map(str::to_owned).collect();
+        let reader = N5Zip {
+            path: PathBuf::from(path),
+            writable: false,
+            state: Arc::new(Mutex::new(ZipState {
+                archive: Archive::Reading(archive),
+                entries,
+            })),
+        };
+
+        if reader.state().entries.contains(ATTRIBUTES_FILE) {
+            let version = reader.get_version()?;
+
+            if !is_version_compatible(&crate::VERSION, &version) {
+                return Err(N5Error::IncompatibleVersion(version))
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Create a new zip container by path, failing if the file exists.
+    pub fn create(path: &str) -> Result<N5Zip> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        let writer = N5Zip {
+            path: PathBuf::from(path),
+            writable: true,
+            state: Arc::new(Mutex::new(ZipState {
+                archive: Archive::Writing(ZipWriter::new(file)),
+                entries: BTreeSet::new(),
+            })),
+        };
+
+        writer.set_attribute("", crate::VERSION_ATTRIBUTE_KEY.to_owned(), crate::VERSION.to_string())?;
+
+        Ok(writer)
+    }
+
+    /// Write the archive's central directory, so that the archive is complete
+    /// for other readers. Entries written afterwards are appended.
+    ///
+    /// This also happens when reading from the container and when the last
+    /// clone of the container is dropped, but errors are then not reported.
+    pub fn flush(&self) -> Result<()> {
+        self.state().archive.reading()?;
+        Ok(())
+    }
+
+    // The archive is closed if an operation fails while switching modes, so
+    // it is safe to ignore lock poisoning.
+    fn state(&self) -> MutexGuard<'_, ZipState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read_entry(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let mut state = self.state();
+        if !state.entries.contains(name) {
+            return Ok(None);
+        }
+        let mut entry = state.archive.reading()?.by_name(name).map_err(zip_error)?;
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn write_entry(&self, name: String, data: &[u8]) -> Result<()> {
+        if !self.writable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Zip container was opened for reading").into());
+        }
+        let mut state = self.state();
+        let writer = state.archive.writing()?;
+        writer.start_file(name.clone(), FileOptions::default().compression_method(CompressionMethod::Stored))
+            .map_err(zip_error)?;
+        writer.write_all(data)?;
+        state.entries.insert(name);
+        Ok(())
+    }
+
+    /// Read a block entry with a block reading function.
+    fn read_block_entry<F, R>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+        f: F,
+    ) -> Result<Option<R>>
+            where F: FnOnce(BufReader<&mut dyn Read>) -> Result<R> {
+        check_unsharded(data_attrs, "N5Zip")?;
+        let name = block_entry_name(&normalize_path(path_name)?, grid_position);
+        let mut state = self.state();
+        if !state.entries.contains(&name) {
+            return Ok(None);
+        }
+        let mut entry = state.archive.reading()?.by_name(&name).map_err(zip_error)?;
+        f(BufReader::new(&mut entry)).map(Some)
+    }
+
+    /// Whether any entry exists in a dir.
+    fn dir_exists(entries: &BTreeSet<String>, path: &str) -> bool {
+        let prefix = entry_name(path, "");
+        path.is_empty() || entries.range(prefix.clone()..).next()
+            .is_some_and(|e| e.starts_with(&prefix))
+    }
+
+    /// Iterate over the names of entries in a dir, relative to that dir.
+    fn dir_entries<'a>(entries: &'a BTreeSet<String>, path: &str) -> impl Iterator<Item = &'a str> {
+        let prefix = entry_name(path, "");
+        let prefix_len = prefix.len();
+        entries.range(prefix.clone()..)
+            .take_while(move |e| e.starts_with(&prefix))
+            .map(move |e| &e[prefix_len..])
+    }
+}
+
+impl N5Reader for N5Zip {
+    fn get_version(&self) -> Result<Version> {
+        let attrs = self.list_attributes("")?;
+        let version = attrs.get(crate::VERSION_ATTRIBUTE_KEY)
+            .ok_or_else(|| N5Error::InvalidVersion("Version attribute not present".to_owned()))?
+            .as_str().unwrap_or("");
+
+        Version::from_str(version)
+            .map_err(|e| N5Error::InvalidVersion(format!("\"{}\": {}", version, e)))
+    }
+
+    fn get_dataset_attributes(&self, path_name: &str) -> Result<DatasetAttributes> {
+        let name = entry_name(&normalize_path(path_name)?, ATTRIBUTES_FILE);
+        let data = self.read_entry(&name)?
+            .ok_or_else(|| N5Error::DatasetNotFound(path_name.to_owned()))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    fn exists(&self, path_name: &str) -> Result<bool> {
+        let path = normalize_path(path_name)?;
+        Ok(N5Zip::dir_exists(&self.state().entries, &path))
+    }
+
+    fn get_block_uri(&self, path_name: &str, grid_position: &[u64]) -> Result<String> {
+        let name = block_entry_name(&normalize_path(path_name)?, grid_position);
+        self.path.to_str()
+            .map(|s| format!("jar:file://{}!/{}", s, name))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Paths must be UTF-8").into())
+    }
+
+    fn read_block<T>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<VecDataBlock<T>>>
+            where VecDataBlock<T>: DataBlock<T> + ReadableDataBlock,
+                  T: ReflectedType {
+        self.read_block_entry(path_name, data_attrs, &grid_position.clone(), |reader| {
+            <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block(
+                reader,
+                data_attrs,
+                grid_position)
+        })
+    }
+
+    fn read_block_into<T: ReflectedType, B: DataBlock<T> + ReinitDataBlock<T> + ReadableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        block: &mut B,
+    ) -> Result<Option<()>> {
+        self.read_block_entry(path_name, data_attrs, &grid_position.clone(), |reader| {
+            <crate::DefaultBlock as DefaultBlockReader<T, _>>::read_block_into(
+                reader,
+                data_attrs,
+                grid_position,
+                block)
+        })
+    }
+
+    fn read_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+    ) -> Result<Option<Vec<u8>>> {
+        self.read_block_entry(path_name, data_attrs, &grid_position, |mut reader| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    /// Get the size of a block entry. Zip entries have no usable timestamps.
+    fn block_metadata(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: &[u64],
+    ) -> Result<Option<DataBlockMetadata>> {
+        check_unsharded(data_attrs, "N5Zip")?;
+        let name = block_entry_name(&normalize_path(path_name)?, grid_position);
+        let mut state = self.state();
+        if !state.entries.contains(&name) {
+            return Ok(None);
+        }
+        let entry = state.archive.reading()?.by_name(&name).map_err(zip_error)?;
+        Ok(Some(DataBlockMetadata {
+            created: None,
+            accessed: None,
+            modified: None,
+            size: Some(entry.size()),
+        }))
+    }
+
+    fn list_attributes(&self, path_name: &str) -> Result<Value> {
+        let name = entry_name(&normalize_path(path_name)?, ATTRIBUTES_FILE);
+        match self.read_entry(&name)? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Err(N5Error::missing_attributes(path_name, self.exists(path_name)?)),
+        }
+    }
+}
+
+impl N5Lister for N5Zip {
+    fn list(&self, path_name: &str) -> Result<Vec<String>> {
+        let path = normalize_path(path_name)?;
+        let state = self.state();
+        if !N5Zip::dir_exists(&state.entries, &path) {
+            return Err(N5Error::PathNotFound(path_name.to_owned()));
+        }
+
+        let mut children: Vec<String> = N5Zip::dir_entries(&state.entries, &path)
+            .filter_map(|e| e.find('/').map(|i| e[..i].to_owned()))
+            .collect();
+        children.dedup();
+        Ok(children)
+    }
+}
+
+impl N5BlockLister for N5Zip {
+    fn list_blocks(&self, path_name: &str) -> Result<Vec<GridCoord>> {
+        let data_attrs = self.get_dataset_attributes(path_name)?;
+        check_unsharded(&data_attrs, "N5Zip")?;
+        let ndim = data_attrs.get_ndim();
+        let path = normalize_path(path_name)?;
+        let state = self.state();
+        Ok(N5Zip::dir_entries(&state.entries, &path)
+            .filter_map(|e| e.split('/')
+                .map(|c| u64::from_str(c).ok())
+                .collect::<Option<GridCoord>>())
+            .filter(|coord| coord.len() == ndim)
+            .collect())
+    }
+}
+
+impl N5Writer for N5Zip {
+    fn set_attributes(
+        &self,
+        path_name: &str,
+        attributes: serde_json::Map<String, Value>,
+    ) -> Result<()> {
+        let name = entry_name(&normalize_path(path_name)?, ATTRIBUTES_FILE);
+        let existing = match self.read_entry(&name)? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => Value::Null,
+        };
+
+        let mut merged = match &existing {
+            Value::Object(existing) => existing.clone(),
+            _ => serde_json::Map::new(),
+        };
+        merged.extend(attributes);
+        let merged = Value::Object(merged);
+
+        if merged != existing {
+            self.write_entry(name, &serde_json::to_vec(&merged)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_attributes(
+        &self,
+        path_name: &str,
+        keys: &[&str],
+        force: bool,
+    ) -> Result<serde_json::Map<String, Value>> {
+        let name = entry_name(&normalize_path(path_name)?, ATTRIBUTES_FILE);
+        let mut attributes = match self.read_entry(&name)? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => return Ok(serde_json::Map::new()),
+        };
+
+        let removed = crate::remove_attribute_keys(path_name, &mut attributes, keys, force)?;
+        if !removed.is_empty() {
+            self.write_entry(name, &serde_json::to_vec(&attributes)?)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Create a group by adding dir entries for it and its ancestors.
+    fn create_group(&self, path_name: &str) -> Result<()> {
+        let path = normalize_path(path_name)?;
+        let mut ancestor = String::new();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            ancestor.push_str(component);
+            ancestor.push('/');
+            if !self.state().entries.contains(&ancestor) {
+                self.write_entry(ancestor.clone(), &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Not supported, as zip containers are append-only.
+    fn remove(
+        &self,
+        _path_name: &str,
+    ) -> Result<()> {
+        Err(Error::other("Zip containers are append-only").into())
+    }
+
+    fn write_block<T, B: DataBlock<T> + WriteableDataBlock>(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        block: &B,
+    ) -> Result<()> {
+        check_unsharded(data_attrs, "N5Zip")?;
+        let name = block_entry_name(&normalize_path(path_name)?, block.get_grid_position());
+
+        let mut data = Vec::new();
+        <crate::DefaultBlock as DefaultBlockWriter<T, _, _>>::write_block(
+            &mut data,
+            data_attrs,
+            block)?;
+
+        self.write_entry(name, &data)
+    }
+
+    fn write_block_raw(
+        &self,
+        path_name: &str,
+        data_attrs: &DatasetAttributes,
+        grid_position: GridCoord,
+        data: &[u8],
+    ) -> Result<()> {
+        check_unsharded(data_attrs, "N5Zip")?;
+        self.write_entry(block_entry_name(&normalize_path(path_name)?, &grid_position), data)
+    }
+
+    /// Not supported, as zip containers are append-only.
+    fn delete_block(
+        &self,
+        _path_name: &str,
+        _grid_position: &[u64],
+    ) -> Result<bool> {
+        Err(Error::other("Zip containers are append-only").into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_backend;
+    use crate::tests::{ContextWrapper, N5Testable};
+    use tempdir::TempDir;
+
+    impl crate::tests::N5Testable for N5Zip {
+        type Wrapper = ContextWrapper<TempDir, N5Zip>;
+
+        fn temp_new_rw() -> Self::Wrapper {
+            let dir = TempDir::new("rust_n5_zip_tests").unwrap();
+            let path = dir.path().join("test.n5.zip");
+            let n5 = N5Zip::create(path.to_str().unwrap())
+                .expect("Failed to create N5 zip");
+
+            ContextWrapper {
+                context: dir,
+                n5,
+            }
+        }
+
+        fn open_reader(&self) -> Self {
+            self.flush().unwrap();
+            N5Zip::open(self.path.to_str().unwrap()).unwrap()
+        }
+    }
+
+    // Not applicable from the backend test suite:
+    // - `create_block_rw` overwrites a block through an open reader, but
+    //   readers opened from the archive do not see later writes; see
+    //   `overwrite_and_reopen` instead.
+    // - `delete_block` and `resize_dataset`, since zip containers are
+    //   append-only and can not delete blocks.
+    test_backend!(N5Zip, [
+        create_backend,
+        create_dataset,
+        attributes_rw,
+        typed_attributes,
+        remove_attributes,
+        raw_block_rw,
+        varlength_block_rw,
+        object_block_rw,
+        error_causes,
+    ]);
+
+    #[test]
+    fn sharded_unsupported() {
+        crate::tests::sharded_unsupported::<N5Zip>()
+    }
+
+    #[test]
+    fn overwrite_and_reopen() {
+        let wrapper = N5Zip::temp_new_rw();
+        let create = wrapper.as_ref();
+        let data_attrs = DatasetAttributes::new(
+            smallvec![10, 10],
+            smallvec![5, 5],
+            crate::DataType::UINT16,
+            crate::compression::CompressionType::default(),
+        );
+        create.create_dataset("foo/bar", &data_attrs).unwrap();
+
+        for value in 0..3_u16 {
+            let block = crate::VecDataBlock::new(smallvec![5, 5], smallvec![1, 0], vec![value; 25]);
+            create.write_block("foo/bar", &data_attrs, &block).unwrap();
+            // Reading finishes the archive, after which writes append to it.
+            let block_out = create.read_block::<u16>("foo/bar", &data_attrs, smallvec![1, 0])
+                .unwrap().unwrap();
+            assert_eq!(block_out.get_data(), &[value; 25][..]);
+        }
+        assert!(create.delete_block("foo/bar", &[1, 0]).is_err());
+        assert!(create.remove("foo").is_err());
+
+        let read = create.open_reader();
+        assert_eq!(read.list("").unwrap(), vec!["foo"]);
+        assert_eq!(read.list("foo").unwrap(), vec!["bar"]);
+        assert_eq!(read.get_dataset_attributes("foo/bar").unwrap(), data_attrs);
+        let block_out = read.read_block::<u16>("foo/bar", &data_attrs, smallvec![1, 0])
+            .unwrap().unwrap();
+        assert_eq!(block_out.get_data(), &[2; 25][..]);
+        assert!(read.set_attribute("foo", "baz".to_owned(), 1).is_err());
+    }
+
+    #[test]
+    fn read_zipped_filesystem_container() {
+        // A zip of an N5 directory, as made by `zip -r -0`.
+        let dir = TempDir::new("rust_n5_zip_tests").unwrap();
+        let path = dir.path().join("test.n5.zip");
+        {
+            let mut writer = ZipWriter::new(File::create(&path).unwrap());
+            let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+            writer.start_file("attributes.json", options).unwrap();
+            writer.write_all(br#"{"n5":"2.1.0"}"#).unwrap();
+            writer.add_directory("foo/", options).unwrap();
+            writer.start_file("foo/attributes.json", options).unwrap();
+            writer.write_all(br#"{
+                "dimensions":[5,6,7],
+                "blockSize":[1,2,3],
+                "dataType":"int16",
+                "compression":{"type":"raw"}
+            }"#).unwrap();
+            writer.add_directory("foo/0/", options).unwrap();
+            writer.add_directory("foo/0/0/", options).unwrap();
+            writer.start_file("foo/0/0/0", options).unwrap();
+            writer.write_all(&[
+                0x00, 0x00,
+                0x00, 0x03,
+                0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x02,
+                0x00, 0x00, 0x00, 0x03,
+                0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x06,
+            ]).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let read = N5Zip::open(path.to_str().unwrap()).unwrap();
+        let data_attrs = read.get_dataset_attributes("foo").unwrap();
+        let block = read.read_block::<i16>("foo", &data_attrs, smallvec![0, 0, 0]).unwrap().unwrap();
+        assert_eq!(block.get_data(), &[1, 2, 3, 4, 5, 6]);
+        assert!(read.read_block::<i16>("foo", &data_attrs, smallvec![0, 0, 1]).unwrap().is_none());
+        let blocks: Vec<GridCoord> = vec![smallvec![0, 0, 0]];
+        assert_eq!(read.list_blocks("foo").unwrap(), blocks);
+        assert_eq!(read.list("foo").unwrap(), vec!["0"]);
+        assert!(read.exists("foo/0").unwrap());
+        assert!(!read.exists("bar").unwrap());
+        assert!(matches!(read.exists("../foo"), Err(N5Error::PathEscape(_))));
+    }
+}
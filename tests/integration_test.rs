@@ -92,6 +92,8 @@ fn test_n5_filesystem_dims() {
 
 fn test_all_compressions<N5: N5Reader + N5Writer>(n: &N5) {
     test_all_types(n, &CompressionType::Raw(compression::raw::RawCompression::default()), 3);
+    #[cfg(feature = "blosc")]
+    test_all_types(n, &CompressionType::Blosc(compression::blosc::BloscCompression::default()), 3);
     #[cfg(feature = "bzip")]
     test_all_types(n, &CompressionType::Bzip2(compression::bzip::Bzip2Compression::default()), 3);
     #[cfg(feature = "gzip")]
@@ -100,6 +102,8 @@ fn test_all_compressions<N5: N5Reader + N5Writer>(n: &N5) {
     test_all_types(n, &CompressionType::Lz4(compression::lz::Lz4Compression::default()), 3);
     #[cfg(feature = "xz")]
     test_all_types(n, &CompressionType::Xz(compression::xz::XzCompression::default()), 3);
+    #[cfg(feature = "zstd")]
+    test_all_types(n, &CompressionType::Zstd(compression::zstd::ZstdCompression::default()), 3);
 }
 
 #[test]
@@ -117,3 +117,57 @@ fn test_write_read_ndarray() {
 
     assert_eq!(array, a);
 }
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_write_read_ndarray() {
+
+    let dir = tempdir::TempDir::new("rust_n5_ndarray_tests").unwrap();
+    let path_str = dir.path().to_str().unwrap();
+
+    let n = N5Filesystem::open_or_create(path_str)
+        .expect("Failed to create N5 filesystem");
+
+    let block_size = smallvec![3, 4, 2, 1];
+    let data_attrs = DatasetAttributes::new(
+        smallvec![3, 300, 200, 100],
+        block_size.clone(),
+        i32::VARIANT,
+        CompressionType::default(),
+    );
+
+    let path_name = "test/dataset/group";
+    n.create_dataset(path_name, &data_attrs)
+        .expect("Failed to create dataset");
+
+    let rng = rand::thread_rng();
+    let arr_shape = [3, 35, 15, 7];
+    let array: Array<i32, _> = Array::from_iter(
+            rng.sample_iter(&Standard)
+            .take(arr_shape.iter().product()))
+        .into_shape(arr_shape).unwrap()
+        .into_dyn();
+    let offset = smallvec![0, 5, 4, 3];
+
+    n.par_write_ndarray(path_name, &data_attrs, offset.clone(), &array, 0).unwrap();
+
+    let bbox = BoundingBox::new(offset, arr_shape.iter().map(|s| *s as u64).collect());
+    let a = n.par_read_ndarray::<i32>(path_name, &data_attrs, &bbox).unwrap();
+    assert_eq!(array, a);
+    let a = n.read_ndarray::<i32>(path_name, &data_attrs, &bbox).unwrap();
+    assert_eq!(array, a);
+
+    // Overwrite a region partially overlapping existing blocks.
+    let inner_shape = [3, 10, 5, 2];
+    let inner = Array::from_elem(&inner_shape[..], 7i32);
+    let inner_offset = smallvec![0, 11, 7, 4];
+    n.par_write_ndarray(path_name, &data_attrs, inner_offset, &inner, 0).unwrap();
+
+    let mut expected = array.clone();
+    expected.slice_mut(ndarray::s![.., 6..16, 3..8, 1..3]).fill(7);
+    let a = n.par_read_ndarray::<i32>(path_name, &data_attrs, &bbox).unwrap();
+    assert_eq!(expected, a);
+
+    let out_of_bounds = n.par_write_ndarray(path_name, &data_attrs, smallvec![0, 295, 0, 0], &array, 0);
+    assert!(matches!(out_of_bounds, Err(N5Error::OutOfBounds(_))));
+}